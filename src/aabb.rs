@@ -0,0 +1,128 @@
+//! Axis-aligned bounding boxes.
+use crate::prelude::*;
+
+/// An axis-aligned bounding box given by its minimum and maximum corners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    /// Creates the smallest box containing both points.
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Aabb {
+            min: Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }
+    }
+
+    /// Creates an empty box containing only `p`.
+    pub fn from_point(p: Point3) -> Self {
+        Aabb { min: p, max: p }
+    }
+
+    /// Creates the smallest box containing the disk around `center` orthogonal to the unit
+    /// vector `normal`.
+    pub fn disk(center: Point3, normal: Vec3, radius: f64) -> Self {
+        let extent = |n: f64| radius.abs() * (1.0 - n * n).max(0.0).sqrt();
+        let extent = Vec3::new(extent(normal.x), extent(normal.y), extent(normal.z));
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(self, other: Aabb) -> Self {
+        self.expand(other.min).expand(other.max)
+    }
+
+    /// Returns the box shared by both boxes, or `None` if they do not overlap.
+    pub fn intersection(self, other: Aabb) -> Option<Self> {
+        let min = Point3::new(
+            self.min.x.max(other.min.x),
+            self.min.y.max(other.min.y),
+            self.min.z.max(other.min.z),
+        );
+        let max = Point3::new(
+            self.max.x.min(other.max.x),
+            self.max.y.min(other.max.y),
+            self.max.z.min(other.max.z),
+        );
+        if min.x > max.x || min.y > max.y || min.z > max.z {
+            return None;
+        }
+        Some(Aabb { min, max })
+    }
+
+    /// Returns the smallest box containing this box and `p`.
+    pub fn expand(self, p: Point3) -> Self {
+        Aabb {
+            min: Point3::new(
+                self.min.x.min(p.x),
+                self.min.y.min(p.y),
+                self.min.z.min(p.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(p.x),
+                self.max.y.max(p.y),
+                self.max.z.max(p.z),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn diagonal(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// The index of the axis along which the box is the longest.
+    pub fn longest_axis(&self) -> usize {
+        let d = self.diagonal();
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// The squared distance from `p` to the closest point of the box, zero inside the box.
+    pub fn distance_squared(&self, p: Point3) -> f64 {
+        let dx = (self.min.x - p.x).max(p.x - self.max.x).max(0.0);
+        let dy = (self.min.y - p.y).max(p.y - self.max.y).max(0.0);
+        let dz = (self.min.z - p.z).max(p.z - self.max.z).max(0.0);
+        dx * dx + dy * dy + dz * dz
+    }
+
+    /// Clips the range of `ray` from `t_min` to `t_max` to the part inside the box, or returns
+    /// `None` if the ray misses the box in that range.
+    pub fn clip(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (t_min, t_max);
+        let axes = [
+            (self.min.x, self.max.x, ray.origin.x, ray.direction.x),
+            (self.min.y, self.max.y, ray.origin.y, ray.direction.y),
+            (self.min.z, self.max.z, ray.origin.z, ray.direction.z),
+        ];
+        for &(min, max, origin, direction) in &axes {
+            let inverse = 1.0 / direction;
+            let mut near = (min - origin) * inverse;
+            let mut far = (max - origin) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaNs from rays parallel to a slab through its boundary keep the current range.
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+}
@@ -0,0 +1,261 @@
+//! Arbitrary output variables (AOVs) collected alongside the beauty render.
+//!
+//! AOVs are auxiliary images describing the first surface seen through each pixel. They are
+//! box filtered: every sample taken within a pixel contributes equally.
+use crate::image::Image;
+use crate::prelude::*;
+
+/// The kinds of auxiliary buffers collected by [`AovBuffers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aov {
+    /// The albedo of the first hit material, black where nothing was hit.
+    Albedo,
+    /// The shading normal of the first hit, facing the camera, black where nothing was hit.
+    Normal,
+    /// The distance from the ray origin to the first hit, replicated in all channels.
+    Depth,
+    /// The world space position of the first hit.
+    Position,
+    /// A false color rendering of the object seen by most samples of each pixel.
+    ObjectId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::ObjectId,
+    ];
+
+    /// A short name of the AOV, suitable for file names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+        }
+    }
+}
+
+/// Accumulates first-hit information for every pixel of an image.
+#[derive(Debug, Clone)]
+pub struct AovBuffers {
+    width: usize,
+    height: usize,
+    albedo: Vec<Rgb>,
+    normal: Vec<Vec3>,
+    depth: Vec<f64>,
+    position: Vec<Point3>,
+    samples: Vec<u32>,
+    hits: Vec<u32>,
+    /// Number of samples that hit each object, per pixel.
+    coverage: Vec<Vec<(usize, u32)>>,
+}
+
+impl AovBuffers {
+    pub fn new(width: usize, height: usize) -> Self {
+        let len = width * height;
+        AovBuffers {
+            width,
+            height,
+            albedo: vec![Rgb::default(); len],
+            normal: vec![Vec3::default(); len],
+            depth: vec![0.0; len],
+            position: vec![Point3::default(); len],
+            samples: vec![0; len],
+            hits: vec![0; len],
+            coverage: vec![Vec::new(); len],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Records a camera `ray` through pixel `(i, j)` and its first hit, if any.
+    pub fn add_sample(&mut self, i: usize, j: usize, ray: &Ray, rec: Option<&HitRecord>) {
+        let index = j * self.width + i;
+        self.samples[index] += 1;
+
+        let rec = match rec {
+            Some(rec) => rec,
+            None => return,
+        };
+
+        self.hits[index] += 1;
+        self.albedo[index] += rec.material.map_or(Rgb::default(), |m| m.albedo(rec));
+        self.normal[index] += rec.material.map_or(rec.normal, |m| m.shading_normal(rec));
+        self.depth[index] += (rec.p - ray.origin).len();
+        self.position[index] += rec.p;
+
+        let coverage = &mut self.coverage[index];
+        match coverage.iter_mut().find(|(id, _)| *id == rec.object_id) {
+            Some((_, count)) => *count += 1,
+            None => coverage.push((rec.object_id, 1)),
+        }
+    }
+
+    /// Returns the filtered image of the given AOV.
+    ///
+    /// Albedo and normal are averaged over all samples of a pixel, so they fade out along
+    /// silhouettes. Depth and position are averaged over the samples that hit something, and are
+    /// zero for pixels that only see the background.
+    pub fn image(&self, aov: Aov) -> Image {
+        let mut image = Image::new(self.width, self.height);
+
+        for (index, pixel) in image.pixels_mut().iter_mut().enumerate() {
+            let samples = self.samples[index].max(1) as f64;
+            let hits = self.hits[index].max(1) as f64;
+
+            *pixel = match aov {
+                Aov::Albedo => self.albedo[index] / samples,
+                Aov::Normal => Rgb::from(self.normal[index] / samples),
+                Aov::Depth => Rgb::from(self.depth[index] / hits),
+                Aov::Position => Rgb::from(self.position[index] / hits),
+                Aov::ObjectId => self.coverage[index]
+                    .iter()
+                    .max_by_key(|(_, count)| *count)
+                    .map_or(Rgb::default(), |&(id, _)| id_color(id)),
+            };
+        }
+
+        image
+    }
+
+    /// Returns the ids of all objects seen by at least one sample, in ascending order.
+    pub fn object_ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self
+            .coverage
+            .iter()
+            .flat_map(|coverage| coverage.iter().map(|&(id, _)| id))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Returns the coverage mask of object `id`, i.e. the fraction of samples of each pixel that
+    /// hit the object, replicated in all channels.
+    pub fn object_mask(&self, id: usize) -> Image {
+        let mut image = Image::new(self.width, self.height);
+
+        for (index, pixel) in image.pixels_mut().iter_mut().enumerate() {
+            let count = self.coverage[index]
+                .iter()
+                .find(|(object, _)| *object == id)
+                .map_or(0, |&(_, count)| count);
+            *pixel = Rgb::from(count as f64 / self.samples[index].max(1) as f64);
+        }
+
+        image
+    }
+}
+
+/// Maps an object id to a distinct, fully saturated color.
+fn id_color(id: usize) -> Rgb {
+    // Walking the hue circle by the golden ratio keeps neighbouring ids far apart.
+    let hue = ((id as f64 + 1.0) * 0.618_033_988_749_895).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as u32 {
+        0 => Rgb::new(1.0, x, 0.0),
+        1 => Rgb::new(x, 1.0, 0.0),
+        2 => Rgb::new(0.0, 1.0, x),
+        3 => Rgb::new(0.0, x, 1.0),
+        4 => Rgb::new(x, 0.0, 1.0),
+        _ => Rgb::new(1.0, 0.0, x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, NormalMapped};
+
+    fn record(material: &dyn Material, p: Point3, object_id: usize) -> HitRecord<'_> {
+        let mut rec = HitRecord::new(p, 1.0, Some(material));
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.dpdu = Vec3::new(1.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 1.0, 0.0);
+        rec.object_id = object_id;
+        rec
+    }
+
+    #[test]
+    fn samples_are_averaged_per_pixel() {
+        let material = Lambertian::new(Rgb::new(0.8, 0.4, 0.2));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 4.0), Vec3::new(0.0, 0.0, -2.0));
+        let mut aovs = AovBuffers::new(2, 1);
+        aovs.add_sample(0, 0, &ray, Some(&record(&material, Point3::default(), 3)));
+        let far = Point3::new(0.0, 0.0, -2.0);
+        aovs.add_sample(0, 0, &ray, Some(&record(&material, far, 3)));
+        aovs.add_sample(0, 0, &ray, None);
+        aovs.add_sample(0, 0, &ray, None);
+
+        // Albedo and normal fade out with the samples that miss, depth and position do not.
+        let albedo = aovs.image(Aov::Albedo).get(0, 0);
+        assert!(
+            (albedo - Rgb::new(0.4, 0.2, 0.1)).len() < 1.0e-12,
+            "{:?}",
+            albedo
+        );
+        assert!((aovs.image(Aov::Normal).get(0, 0).b - 0.5).abs() < 1.0e-12);
+        assert!((aovs.image(Aov::Depth).get(0, 0).r - 5.0).abs() < 1.0e-12);
+        assert!((aovs.image(Aov::Position).get(0, 0).b + 1.0).abs() < 1.0e-12);
+        assert_eq!(aovs.image(Aov::ObjectId).get(0, 0), id_color(3));
+
+        // Pixels without samples stay black.
+        for &aov in &Aov::ALL {
+            assert_eq!(aovs.image(aov).get(1, 0), Rgb::default());
+        }
+    }
+
+    #[test]
+    fn object_masks_cover_the_samples_hitting_each_object() {
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut aovs = AovBuffers::new(2, 2);
+        for &id in &[4, 4, 4, 1] {
+            aovs.add_sample(1, 0, &ray, Some(&record(&material, Point3::default(), id)));
+        }
+        aovs.add_sample(0, 1, &ray, Some(&record(&material, Point3::default(), 1)));
+        aovs.add_sample(0, 1, &ray, None);
+
+        assert_eq!(aovs.object_ids(), vec![1, 4]);
+        let mask = aovs.object_mask(4);
+        assert!((mask.get(1, 0).r - 0.75).abs() < 1.0e-12);
+        assert_eq!(mask.get(0, 1), Rgb::default());
+        let mask = aovs.object_mask(1);
+        assert!((mask.get(1, 0).r - 0.25).abs() < 1.0e-12);
+        assert!((mask.get(0, 1).r - 0.5).abs() < 1.0e-12);
+        assert!(aovs
+            .object_mask(2)
+            .pixels()
+            .iter()
+            .all(|&pixel| pixel == Rgb::default()));
+
+        // The object id AOV shows the object covering most of the pixel.
+        assert_eq!(aovs.image(Aov::ObjectId).get(1, 0), id_color(4));
+    }
+
+    #[test]
+    fn normals_include_the_perturbation_of_the_material() {
+        // A normal map tilting every normal towards the tangent.
+        let tilted = Vec3::new(1.0, 0.0, 1.0).normal();
+        let encoded = Rgb::from(0.5 * (tilted + Vec3::new(1.0, 1.0, 1.0)));
+        let material = NormalMapped::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5)), encoded);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut aovs = AovBuffers::new(1, 1);
+        aovs.add_sample(0, 0, &ray, Some(&record(&material, Point3::default(), 0)));
+
+        let normal = Vec3::from(aovs.image(Aov::Normal).get(0, 0));
+        assert!((normal - tilted).len() < 1.0e-9, "{:?}", normal);
+    }
+}
@@ -0,0 +1,785 @@
+//! A bidirectional path tracer.
+use crate::integrator::{Emitter, Scene};
+use crate::light::*;
+use crate::prelude::*;
+use crate::sampler::{self, Stream};
+use crate::util::random_f64;
+
+/// Bidirectional path tracing, following Veach, "Robust Monte Carlo Methods for Light Transport
+/// Simulation" (1997).
+///
+/// For every camera ray, a subpath is traced from the camera and another one from a light or
+/// the environment, and every prefix of one is connected to every prefix of the other. The
+/// resulting paths are weighted by the power heuristic over all the ways each of them could
+/// have been sampled, which finds caustics and light coming through small openings far more
+/// efficiently than a [`PathTracer`](crate::integrator::PathTracer).
+///
+/// Paths that end on the camera are splatted to the image rather than returned with the
+/// radiance of the camera ray. Materials that cannot be evaluated are treated as perfectly
+/// specular, and paths are traced in RGB. The camera subpath, the light subpath and their
+/// connections read separate [`Stream`]s of the sampler.
+#[derive(Debug, Clone, Copy)]
+pub struct Bdpt {
+    /// The maximum number of bounces of a path.
+    pub max_depth: u32,
+}
+
+#[derive(Debug, Clone)]
+enum VertexKind<'world> {
+    Camera,
+    /// The start of a light subpath, or the environment reached by a camera subpath.
+    Light(Emitter),
+    Surface(HitRecord<'world>),
+}
+
+/// A vertex of a subpath, with the densities of sampling it from either side in area measure,
+/// or in solid angle measure for vertices infinitely far away.
+#[derive(Debug, Clone)]
+struct Vertex<'world> {
+    kind: VertexKind<'world>,
+    p: Point3,
+    /// The normal of the surface at the vertex, which is zero for vertices that are not on a
+    /// surface.
+    n: Vec3,
+    /// The throughput of the subpath up to the vertex, divided by its density.
+    beta: Rgb,
+    /// Whether the vertex scatters with a delta distribution and cannot be connected.
+    delta: bool,
+    /// Whether the material scattered from below the surface, e.g. during a subsurface random
+    /// walk. The vertex then stands in for a point inside the object, whose densities are not
+    /// known.
+    below_surface: bool,
+    /// The density of sampling the vertex from the previous vertex of its subpath.
+    pdf_fwd: f64,
+    /// The density of sampling the vertex from the next vertex of its subpath, i.e. when the
+    /// subpath is traced in reverse.
+    pdf_rev: f64,
+}
+
+/// The densities and delta flag of a vertex, as used by the multiple importance sampling weight.
+type Densities = (f64, f64, bool);
+
+impl<'world> Vertex<'world> {
+    fn new(kind: VertexKind<'world>, p: Point3, n: Vec3, beta: Rgb) -> Self {
+        Vertex {
+            kind,
+            p,
+            n,
+            beta,
+            delta: false,
+            below_surface: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn is_on_surface(&self) -> bool {
+        self.n != Vec3::default()
+    }
+
+    /// The emitter at the vertex, for the start of light subpaths and emissive surfaces.
+    fn emitter(&self, scene: &Scene) -> Option<Emitter> {
+        match &self.kind {
+            VertexKind::Light(emitter) => Some(*emitter),
+            VertexKind::Surface(rec) => scene.object_light(rec).map(Emitter::Light),
+            VertexKind::Camera => None,
+        }
+    }
+
+    fn is_infinite_light(&self, scene: &Scene) -> bool {
+        match self.kind {
+            VertexKind::Light(emitter) => is_infinite(scene, emitter),
+            _ => false,
+        }
+    }
+
+    fn is_delta_light(&self, scene: &Scene) -> bool {
+        match self.kind {
+            VertexKind::Light(Emitter::Light(index)) => scene.lights()[index].is_delta(),
+            _ => false,
+        }
+    }
+
+    fn is_connectible(&self, scene: &Scene) -> bool {
+        match self.kind {
+            // Distant lights only emit in a single direction.
+            VertexKind::Light(emitter) => {
+                !(is_infinite(scene, emitter) && self.is_delta_light(scene))
+            }
+            VertexKind::Camera => true,
+            VertexKind::Surface(_) => !self.delta,
+        }
+    }
+
+    /// The outward normal of an emissive vertex.
+    fn light_normal(&self) -> Vec3 {
+        match &self.kind {
+            VertexKind::Surface(rec) if !rec.front_face => -rec.geometric_normal,
+            _ => self.n,
+        }
+    }
+
+    /// The radiance emitted from the vertex towards `prev`.
+    fn le(&self, scene: &Scene, prev: &Vertex) -> Rgb {
+        match &self.kind {
+            VertexKind::Light(Emitter::Environment) => {
+                scene.environment().radiance((self.p - prev.p).normal())
+            }
+            VertexKind::Surface(rec) => rec.material.map_or(Rgb::default(), |m| m.emitted(rec)),
+            _ => Rgb::default(),
+        }
+    }
+
+    /// The BSDF times the cosine for light scattered at the vertex between `prev` and the
+    /// unit direction `wi`.
+    fn f(&self, prev: &Vertex, wi: Vec3) -> Rgb {
+        match &self.kind {
+            VertexKind::Surface(rec) => {
+                eval_surface(rec, (prev.p - self.p).normal(), wi).map_or(Rgb::default(), |(f, _)| f)
+            }
+            _ => Rgb::default(),
+        }
+    }
+
+    /// Converts the solid angle density of sampling `next` from this vertex to area measure.
+    fn convert_density(&self, scene: &Scene, pdf: f64, next: &Vertex) -> f64 {
+        if next.is_infinite_light(scene) {
+            return pdf;
+        }
+        let offset = next.p - self.p;
+        let distance_squared = offset.len_squared();
+        if distance_squared <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if next.is_on_surface() {
+            pdf *= next.n.dot(offset / distance_squared.sqrt()).abs();
+        }
+        pdf
+    }
+
+    /// The density of sampling `next` from this vertex, having arrived from `prev`.
+    fn pdf(&self, scene: &Scene, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let wn = (next.p - self.p).normal();
+        let pdf = match &self.kind {
+            VertexKind::Light(_) => return self.pdf_light(scene, next),
+            VertexKind::Camera => camera.pdf_direction(wn),
+            VertexKind::Surface(rec) => match prev {
+                Some(prev) => {
+                    eval_surface(rec, (prev.p - self.p).normal(), wn).map_or(0.0, |(_, pdf)| pdf)
+                }
+                None => 0.0,
+            },
+        };
+        self.convert_density(scene, pdf, next)
+    }
+
+    /// The density of the emitter at this vertex emitting light that reaches `v`.
+    fn pdf_light(&self, scene: &Scene, v: &Vertex) -> f64 {
+        let offset = v.p - self.p;
+        let distance_squared = offset.len_squared();
+        if distance_squared <= 0.0 {
+            return 0.0;
+        }
+        let w = offset / distance_squared.sqrt();
+
+        let mut pdf = if self.is_infinite_light(scene) {
+            let (_, radius) = bounding_sphere(scene.emitter_sampler().scene_bounds());
+            1.0 / (PI * radius * radius)
+        } else {
+            match self.emitter(scene) {
+                Some(Emitter::Light(index)) => {
+                    let ray = Ray::new(self.p, w);
+                    let (_, pdf_dir) = scene.lights()[index].pdf_le(
+                        &ray,
+                        self.light_normal(),
+                        scene.emitter_sampler().scene_bounds(),
+                    );
+                    pdf_dir / distance_squared
+                }
+                _ => 0.0,
+            }
+        };
+        if v.is_on_surface() {
+            pdf *= v.n.dot(w).abs();
+        }
+        pdf
+    }
+
+    /// The density of a light subpath starting at this vertex, when it emits towards `v`.
+    fn pdf_light_origin(&self, scene: &Scene, v: &Vertex) -> f64 {
+        let w = (v.p - self.p).normal();
+        if self.is_infinite_light(scene) {
+            return scene.emitter_sampler().infinite_light_density(scene, w);
+        }
+        match self.emitter(scene) {
+            Some(Emitter::Light(index)) => {
+                let ray = Ray::new(self.p, w);
+                let (pdf_pos, _) = scene.lights()[index].pdf_le(
+                    &ray,
+                    self.light_normal(),
+                    scene.emitter_sampler().scene_bounds(),
+                );
+                scene.emitter_sampler().pmf(Emitter::Light(index)) * pdf_pos
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+impl Bdpt {
+    pub fn new(max_depth: u32) -> Self {
+        Bdpt { max_depth }
+    }
+
+    /// Estimates the radiance arriving along the camera `ray`.
+    ///
+    /// Light reaching the camera through other points of the image is passed to `splat`, along
+    /// with the coordinates of [`Camera::get_ray`] for that point. Splats must be added to the
+    /// image and scaled by one over the number of samples per pixel.
+    pub fn radiance(
+        &self,
+        ray: &Ray,
+        camera: &Camera,
+        scene: &Scene,
+        splat: impl FnMut(f64, f64, Rgb),
+    ) -> Rgb {
+        self.radiance_with_first_hit(ray, camera, scene, splat, |_| {})
+    }
+
+    /// Like [`Bdpt::radiance`], also passing the first hit of `ray`, or `None` if it escapes,
+    /// to `first_hit`, e.g. to collect AOVs without tracing the ray again.
+    pub fn radiance_with_first_hit(
+        &self,
+        ray: &Ray,
+        camera: &Camera,
+        scene: &Scene,
+        mut splat: impl FnMut(f64, f64, Rgb),
+        first_hit: impl FnOnce(Option<&HitRecord>),
+    ) -> Rgb {
+        let camera_path = self.camera_subpath(ray, camera, scene);
+        first_hit(match camera_path.get(1).map(|vertex| &vertex.kind) {
+            Some(VertexKind::Surface(rec)) => Some(rec),
+            _ => None,
+        });
+        sampler::start_stream(Stream::Light);
+        let light_path = self.light_subpath(scene);
+        sampler::start_stream(Stream::Connection);
+
+        let mut color = Rgb::default();
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = (t + s) as i64 - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.max_depth as i64 {
+                    continue;
+                }
+                if t == 1 {
+                    if let Some(((u, v), c)) = self.connect_to_camera(scene, camera, &light_path, s)
+                    {
+                        splat(u, v, c);
+                    }
+                } else {
+                    color += self.connect(scene, camera, &light_path, &camera_path, s, t);
+                }
+            }
+        }
+        color
+    }
+
+    fn camera_subpath<'world>(
+        &self,
+        ray: &Ray,
+        camera: &Camera,
+        scene: &'world Scene,
+    ) -> Vec<Vertex<'world>> {
+        let ray = Ray::new(ray.origin, ray.direction.normal());
+        let mut path = vec![Vertex::new(
+            VertexKind::Camera,
+            ray.origin,
+            Vec3::default(),
+            Rgb::new(1.0, 1.0, 1.0),
+        )];
+        let pdf_dir = camera.pdf_direction(ray.direction);
+        let beta = Rgb::new(1.0, 1.0, 1.0);
+        self.random_walk(
+            scene,
+            ray,
+            beta,
+            pdf_dir,
+            self.max_depth + 1,
+            false,
+            &mut path,
+        );
+        path
+    }
+
+    fn light_subpath<'world>(&self, scene: &'world Scene) -> Vec<Vertex<'world>> {
+        let emitters = scene.emitter_sampler();
+        let (emitter, pmf) = match emitters.sample(random_f64()) {
+            Some(sampled) => sampled,
+            None => return Vec::new(),
+        };
+        let u_pos = (random_f64(), random_f64());
+        let u_dir = (random_f64(), random_f64());
+        let sample = match emitters.sample_le(scene, emitter, u_pos, u_dir) {
+            Some(sample) if pmf > 0.0 && sample.pdf_pos > 0.0 && sample.pdf_dir > 0.0 => sample,
+            _ => return Vec::new(),
+        };
+        if sample.radiance.luminance() <= 0.0 {
+            return Vec::new();
+        }
+
+        let mut start = Vertex::new(
+            VertexKind::Light(emitter),
+            sample.ray.origin,
+            sample.normal,
+            sample.radiance,
+        );
+        start.pdf_fwd = sample.pdf_pos * pmf;
+        let mut path = vec![start];
+
+        let cosine = if sample.normal == Vec3::default() {
+            1.0
+        } else {
+            sample.normal.dot(sample.ray.direction).abs()
+        };
+        let beta = sample.radiance * (cosine / (pmf * sample.pdf_pos * sample.pdf_dir));
+        self.random_walk(
+            scene,
+            sample.ray,
+            beta,
+            sample.pdf_dir,
+            self.max_depth,
+            true,
+            &mut path,
+        );
+
+        // Light from infinitely far away is sampled by position on a disk and by direction,
+        // rather than by the position of the first vertex.
+        if is_infinite(scene, emitter) {
+            if let Some(first) = path.get_mut(1) {
+                first.pdf_fwd = sample.pdf_pos;
+                if first.is_on_surface() {
+                    first.pdf_fwd *= first.n.dot(sample.ray.direction).abs();
+                }
+            }
+            path[0].pdf_fwd = emitters.infinite_light_density(scene, sample.ray.direction);
+        }
+        path
+    }
+
+    /// Extends `path` by up to `max_vertices` vertices, tracing `ray` sampled with solid angle
+    /// density `pdf` and carrying throughput `beta`.
+    ///
+    /// Camera subpaths, which carry radiance rather than importance, end with a vertex on the
+    /// environment when they escape the scene.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk<'world>(
+        &self,
+        scene: &'world Scene,
+        mut ray: Ray,
+        mut beta: Rgb,
+        pdf: f64,
+        max_vertices: u32,
+        importance: bool,
+        path: &mut Vec<Vertex<'world>>,
+    ) {
+        let mut pdf_fwd = pdf;
+        for _ in 0..max_vertices {
+            // 0.001 here is for fixing shadow acne.
+            let rec = match scene.world().hit(&ray, 0.001, INIFINTY) {
+                Some(rec) => rec,
+                None => {
+                    if !importance {
+                        let kind = VertexKind::Light(Emitter::Environment);
+                        let p = ray.origin + ray.direction;
+                        let mut vertex = Vertex::new(kind, p, Vec3::default(), beta);
+                        vertex.pdf_fwd = pdf_fwd;
+                        path.push(vertex);
+                    }
+                    return;
+                }
+            };
+
+            let (p, n, material) = (rec.p, rec.geometric_normal, rec.material);
+            let mut vertex = Vertex::new(VertexKind::Surface(rec.clone()), p, n, beta);
+            let prev = path.last().unwrap();
+            vertex.pdf_fwd = prev.convert_density(scene, pdf_fwd, &vertex);
+            path.push(vertex);
+
+            let scatter = match material.and_then(|m| m.scatter(&ray, &rec)) {
+                Some(scatter) => scatter,
+                None => return,
+            };
+            let wo = -ray.direction;
+            let wi = scatter.ray.direction.normal();
+            let mut pdf_rev = 0.0;
+            // Scattering from below the surface cannot be evaluated at the vertex.
+            let below_surface = scatter.below_surface;
+            match eval_surface(&rec, wo, wi).filter(|_| !below_surface) {
+                Some((_, pdf)) if pdf > 0.0 => {
+                    pdf_fwd = pdf;
+                    pdf_rev = eval_surface(&rec, wi, wo).map_or(0.0, |(_, pdf)| pdf);
+                }
+                _ => {
+                    let vertex = path.last_mut().unwrap();
+                    vertex.delta = true;
+                    vertex.below_surface = below_surface;
+                    vertex.p = scatter.ray.origin;
+                    pdf_fwd = 0.0;
+                }
+            }
+
+            beta *= scatter.attenuation;
+            if beta.luminance() <= 0.0 {
+                return;
+            }
+
+            let len = path.len();
+            let pdf_rev = path[len - 1].convert_density(scene, pdf_rev, &path[len - 2]);
+            path[len - 2].pdf_rev = pdf_rev;
+            ray = Ray::new(scatter.ray.origin, wi);
+        }
+    }
+
+    /// Connects the first `s` vertices of the light subpath to the camera, returning the
+    /// coordinates of the image point they are seen through and the weighted contribution.
+    fn connect_to_camera(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        light_path: &[Vertex],
+        s: usize,
+    ) -> Option<((f64, f64), Rgb)> {
+        let qs = &light_path[s - 1];
+        if !qs.is_connectible(scene) {
+            return None;
+        }
+        let uv = camera.project(qs.p)?;
+
+        let offset = camera.origin() - qs.p;
+        let distance = offset.len();
+        let wi = offset / distance;
+        // The importance times the cosine at the camera, over the density of sampling the
+        // camera from `qs` in solid angle.
+        let pdf_dir = camera.pdf_direction(-wi);
+        if pdf_dir <= 0.0 {
+            return None;
+        }
+        let beta = Rgb::from(pdf_dir / (distance * distance));
+        let sampled = Vertex::new(VertexKind::Camera, camera.origin(), Vec3::default(), beta);
+
+        let mut color = qs.beta * qs.f(&light_path[s - 2], wi) * sampled.beta;
+        if color.luminance() > 0.0 {
+            color *= scene.transmittance(qs.p, wi, distance * (1.0 - 1.0e-4));
+        }
+        if color.luminance() <= 0.0 {
+            return None;
+        }
+        let weight = self.mis_weight(scene, camera, light_path, &[], s, 1, Some(&sampled));
+        Some((uv, weight * color))
+    }
+
+    /// Connects the first `s` vertices of the light subpath to the first `t` vertices of the
+    /// camera subpath, for `t` of at least two, and returns the weighted contribution.
+    fn connect(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+    ) -> Rgb {
+        let pt = &camera_path[t - 1];
+        let pt_minus = &camera_path[t - 2];
+        if s == 0 {
+            let color = pt.beta * pt.le(scene, pt_minus);
+            if color.luminance() <= 0.0 {
+                return Rgb::default();
+            }
+            return self.mis_weight(scene, camera, light_path, camera_path, 0, t, None) * color;
+        }
+
+        // Only the emission of the environment reaches camera subpaths that escaped.
+        if !pt.is_connectible(scene) || matches!(pt.kind, VertexKind::Light(_)) {
+            return Rgb::default();
+        }
+
+        if s == 1 {
+            let (sampled, distance) = match self.sample_light(scene, pt) {
+                Some(sample) => sample,
+                None => return Rgb::default(),
+            };
+            let wi = (sampled.p - pt.p).normal();
+            let mut color = pt.beta * pt.f(pt_minus, wi) * sampled.beta;
+            if color.luminance() > 0.0 {
+                color *= scene.transmittance(pt.p, wi, distance);
+            }
+            if color.luminance() <= 0.0 {
+                return Rgb::default();
+            }
+            let weight =
+                self.mis_weight(scene, camera, light_path, camera_path, 1, t, Some(&sampled));
+            return weight * color;
+        }
+
+        let qs = &light_path[s - 1];
+        if !qs.is_connectible(scene) {
+            return Rgb::default();
+        }
+        let offset = pt.p - qs.p;
+        let distance_squared = offset.len_squared();
+        let distance = distance_squared.sqrt();
+        let w = offset / distance;
+        let mut color =
+            qs.beta * qs.f(&light_path[s - 2], w) * pt.f(pt_minus, -w) * pt.beta / distance_squared;
+        if color.luminance() > 0.0 {
+            color *= scene.transmittance(qs.p, w, distance * (1.0 - 1.0e-4));
+        }
+        if color.luminance() <= 0.0 {
+            return Rgb::default();
+        }
+        self.mis_weight(scene, camera, light_path, camera_path, s, t, None) * color
+    }
+
+    /// Samples an emitter from the vertex `pt`, returning a light vertex whose throughput is
+    /// the arriving radiance over the density of the sample, and the distance at which a
+    /// shadow ray must stop.
+    fn sample_light<'world>(&self, scene: &Scene, pt: &Vertex) -> Option<(Vertex<'world>, f64)> {
+        let (emitter, pmf) = scene.emitter_sampler().sample(random_f64())?;
+        let (direction, distance, radiance, pdf, normal) = match emitter {
+            Emitter::Environment => {
+                let (direction, pdf) = scene.environment().sample(random_f64(), random_f64());
+                let radiance = scene.environment().radiance(direction);
+                (direction, INIFINTY, radiance, pdf, Vec3::default())
+            }
+            Emitter::Light(index) => {
+                let sample = scene.lights()[index].sample_li(pt.p, random_f64(), random_f64())?;
+                let normal = sample.normal;
+                (
+                    sample.direction,
+                    sample.distance,
+                    sample.radiance,
+                    sample.pdf,
+                    normal,
+                )
+            }
+        };
+        if pmf <= 0.0 || pdf <= 0.0 {
+            return None;
+        }
+
+        let p = if distance.is_finite() {
+            pt.p + distance * direction
+        } else {
+            pt.p + direction
+        };
+        let beta = radiance / (pdf * pmf);
+        let mut sampled = Vertex::new(VertexKind::Light(emitter), p, normal, beta);
+        sampled.pdf_fwd = sampled.pdf_light_origin(scene, pt);
+        // Stop short of the light so that it does not occlude itself.
+        Some((sampled, distance * (1.0 - 1.0e-4)))
+    }
+
+    /// The multiple importance sampling weight of the path made of `s` light vertices and `t`
+    /// camera vertices, where `sampled` replaces the last light vertex when `s` is one and the
+    /// camera vertex when `t` is one.
+    #[allow(clippy::too_many_arguments)]
+    fn mis_weight(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        s: usize,
+        t: usize,
+        sampled: Option<&Vertex>,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        // Paths scattered below a surface are only weighted over the strategies that connect
+        // them after the last such vertex, on the side of the light, where every density is
+        // known. Strategies whose light subpath scattered below a surface are left out.
+        if light_path[..s].iter().any(|vertex| vertex.below_surface) {
+            return 0.0;
+        }
+
+        let qs = match (s, sampled) {
+            (0, _) => None,
+            (1, Some(sampled)) => Some(sampled),
+            _ => Some(&light_path[s - 1]),
+        };
+        let pt = match (t, sampled) {
+            (1, Some(sampled)) => sampled,
+            _ => &camera_path[t - 1],
+        };
+        let qs_minus = if s >= 2 {
+            Some(&light_path[s - 2])
+        } else {
+            None
+        };
+        let pt_minus = if t >= 2 {
+            Some(&camera_path[t - 2])
+        } else {
+            None
+        };
+
+        let densities = |v: &Vertex| -> Densities { (v.pdf_fwd, v.pdf_rev, v.delta) };
+        let mut camera_densities: Vec<Densities> =
+            camera_path[..t - 1].iter().map(densities).collect();
+        camera_densities.push((pt.pdf_fwd, pt.pdf_rev, false));
+        let mut light_densities: Vec<Densities> = light_path[..s.saturating_sub(1)]
+            .iter()
+            .map(densities)
+            .collect();
+        if let Some(qs) = qs {
+            light_densities.push((qs.pdf_fwd, qs.pdf_rev, false));
+        }
+
+        // The reverse densities around the connection depend on the strategy.
+        camera_densities[t - 1].1 = match (qs, pt_minus) {
+            (Some(qs), _) => qs.pdf(scene, camera, qs_minus, pt),
+            (None, Some(pt_minus)) => pt.pdf_light_origin(scene, pt_minus),
+            (None, None) => 0.0,
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera_densities[t - 2].1 = match qs {
+                Some(qs) => pt.pdf(scene, camera, Some(qs), pt_minus),
+                None => pt.pdf_light(scene, pt_minus),
+            };
+        }
+        if let Some(qs) = qs {
+            light_densities[s - 1].1 = pt.pdf(scene, camera, pt_minus, qs);
+        }
+        if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+            light_densities[s - 2].1 = qs.pdf(scene, camera, Some(pt), qs_minus);
+        }
+
+        // Zero densities of delta vertices are remapped to one, as the strategies involving
+        // them are skipped anyway.
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            if camera_path[i].below_surface {
+                break;
+            }
+            ratio *= remap(camera_densities[i].1) / remap(camera_densities[i].0);
+            if !camera_densities[i].2 && !camera_densities[i - 1].2 {
+                sum += ratio * ratio;
+            }
+        }
+
+        let delta_light = match (s, qs) {
+            (0, _) | (_, None) => false,
+            (1, Some(qs)) => qs.is_delta_light(scene),
+            _ => light_path[0].is_delta_light(scene),
+        };
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light_densities[i].1) / remap(light_densities[i].0);
+            let delta_prev = if i > 0 {
+                light_densities[i - 1].2
+            } else {
+                delta_light
+            };
+            if !light_densities[i].2 && !delta_prev {
+                sum += ratio * ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+fn is_infinite(scene: &Scene, emitter: Emitter) -> bool {
+    match emitter {
+        Emitter::Light(index) => scene.lights()[index].bounds().is_none(),
+        Emitter::Environment => true,
+    }
+}
+
+/// Evaluates the material at `rec` for light scattered between the unit directions `wo` and
+/// `wi`, seen from the side of `wo`.
+fn eval_surface(rec: &HitRecord, wo: Vec3, wi: Vec3) -> Option<(Rgb, f64)> {
+    let mut rec = rec.clone();
+    if wo.dot(rec.normal) < 0.0 {
+        rec.normal = -rec.normal;
+        rec.geometric_normal = -rec.geometric_normal;
+        rec.front_face = !rec.front_face;
+    }
+    let ray_in = Ray::new(rec.p + wo, -wo);
+    rec.material?.eval(&ray_in, &rec, wi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::PathTracer;
+    use crate::material::{Lambertian, Principled};
+    use crate::sampler::{with_sampler, IndependentSampler};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn images_match_path_tracing() {
+        // A diffuse floor lit by a small spherical lamp, above the view of the camera.
+        let mut world = HittableList::new();
+        world.add(Plane::new(
+            Point3::new(0.0, -0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Lambertian::new(Rgb::new(0.7, 0.7, 0.7)),
+        ));
+        let lamp = Principled {
+            base_color: Box::new(Rgb::default()),
+            emission: Box::new(Rgb::new(1.0, 0.9, 0.8)),
+            emission_strength: 10.0,
+            ..Default::default()
+        };
+        world.add(Sphere::new(Point3::new(0.3, 2.0, -1.5), 0.2, lamp));
+        let scene = Scene::new(world, Rgb::default());
+        let camera = Camera::default();
+
+        let (width, height, samples) = (16, 9, 256);
+        let render = |seed: u64, radiance: &mut dyn FnMut(&Ray, &mut Rgb) -> Rgb| {
+            let sampler = Rc::new(RefCell::new(IndependentSampler::new(seed)));
+            with_sampler(sampler, || {
+                let (mut total, mut splatted) = (Rgb::default(), Rgb::default());
+                for j in 0..height {
+                    for i in 0..width {
+                        for _ in 0..samples {
+                            let u = (i as f64 + random_f64()) / width as f64;
+                            let v = (j as f64 + random_f64()) / height as f64;
+                            total += radiance(&camera.get_ray(u, v), &mut splatted);
+                        }
+                    }
+                }
+                let n = (width * height * samples) as f64;
+                ((total + splatted) / n, splatted / n)
+            })
+        };
+
+        let path_tracer = PathTracer::new(5);
+        let (expected, _) = render(40, &mut |ray, _| path_tracer.radiance(ray, &scene));
+        let bdpt = Bdpt::new(5);
+        let (mean, splatted) = render(41, &mut |ray, splatted| {
+            let splat = |u: f64, v: f64, color| {
+                if (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v) {
+                    *splatted += color;
+                }
+            };
+            bdpt.radiance(ray, &camera, &scene, splat)
+        });
+
+        // Light tracing connected to the camera contributes a good part of the floor, and the
+        // weights of all strategies sum to one.
+        assert!(
+            splatted.luminance() > 0.1 * mean.luminance(),
+            "{:?}",
+            splatted
+        );
+        let error = (mean.luminance() - expected.luminance()).abs() / expected.luminance();
+        assert!(error < 0.03, "{:?} != {:?}", mean, expected);
+    }
+}
@@ -0,0 +1,293 @@
+//! A bounding volume hierarchy ([`Bvh`]) that finds the objects a ray may hit.
+use crate::aabb::Aabb;
+use crate::prelude::*;
+
+/// The most objects stored in a leaf.
+const MAX_LEAF_OBJECTS: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+enum Node {
+    /// The objects `indices[start..end]`.
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    /// Two children split along `axis`. The first directly follows this node and holds the
+    /// objects with the smaller centroids.
+    Interior {
+        bounds: Aabb,
+        second: usize,
+        axis: usize,
+    },
+}
+
+/// A binary tree of bounding boxes over a list of objects, which refers to the objects by
+/// their index in the list.
+///
+/// Objects without a bounding box, such as planes, are kept aside and visited for every ray.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    /// The nodes in depth-first order, starting with the root.
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy over the bounding boxes of the objects, splitting them at the
+    /// median of their centroids along the longest axis.
+    pub fn new(boxes: &[Option<Aabb>]) -> Self {
+        let mut bvh = Bvh::default();
+        let mut bounded = Vec::new();
+        for (index, bounds) in boxes.iter().enumerate() {
+            match bounds {
+                Some(bounds) => bounded.push((index, *bounds)),
+                None => bvh.unbounded.push(index),
+            }
+        }
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded);
+        }
+        bvh
+    }
+
+    fn build(&mut self, objects: &mut [(usize, Aabb)]) {
+        let bounds = objects.iter().map(|&(_, b)| b).reduce(Aabb::union).unwrap();
+        if objects.len() <= MAX_LEAF_OBJECTS {
+            let start = self.indices.len();
+            self.indices.extend(objects.iter().map(|&(index, _)| index));
+            self.nodes.push(Node::Leaf {
+                bounds,
+                start,
+                end: self.indices.len(),
+            });
+            return;
+        }
+
+        let axis = objects
+            .iter()
+            .map(|(_, b)| Aabb::from_point(b.centroid()))
+            .reduce(Aabb::union)
+            .unwrap()
+            .longest_axis();
+        let key = |b: &Aabb| {
+            let c = b.centroid();
+            [c.x, c.y, c.z][axis]
+        };
+        let middle = objects.len() / 2;
+        objects.select_nth_unstable_by(middle, |a, b| key(&a.1).total_cmp(&key(&b.1)));
+
+        let node = self.nodes.len();
+        self.nodes.push(Node::Interior {
+            bounds,
+            second: 0,
+            axis,
+        });
+        let (first, second) = objects.split_at_mut(middle);
+        self.build(first);
+        let second_node = self.nodes.len();
+        if let Node::Interior { second, .. } = &mut self.nodes[node] {
+            *second = second_node;
+        }
+        self.build(second);
+    }
+
+    /// Calls `visit` with the index of every object whose box `ray` passes through between
+    /// `t_min` and `t_max`, nearer boxes first.
+    ///
+    /// `visit` may lower `t_max`, e.g. to the nearest hit so far, which skips the boxes
+    /// beyond it, and returns whether to carry on.
+    pub fn traverse(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: &mut f64,
+        mut visit: impl FnMut(usize, &mut f64) -> bool,
+    ) {
+        for &index in &self.unbounded {
+            if !visit(index, t_max) {
+                return;
+            }
+        }
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            match self.nodes[node] {
+                Node::Leaf { bounds, start, end } => {
+                    if bounds.clip(ray, t_min, *t_max).is_none() {
+                        continue;
+                    }
+                    for &index in &self.indices[start..end] {
+                        if !visit(index, t_max) {
+                            return;
+                        }
+                    }
+                }
+                Node::Interior {
+                    bounds,
+                    second,
+                    axis,
+                } => {
+                    if bounds.clip(ray, t_min, *t_max).is_none() {
+                        continue;
+                    }
+                    if direction[axis] < 0.0 {
+                        stack.push(node + 1);
+                        stack.push(second);
+                    } else {
+                        stack.push(second);
+                        stack.push(node + 1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HittableList;
+    use crate::material::{Lambertian, Masked};
+    use crate::plane::Plane;
+    use crate::sphere::Sphere;
+    use crate::triangle::Triangle;
+
+    /// Collects the objects visited by `ray` without shrinking its range.
+    fn visited(bvh: &Bvh, ray: &Ray) -> Vec<usize> {
+        let mut indices = Vec::new();
+        let mut t_max = INIFINTY;
+        bvh.traverse(ray, 0.0, &mut t_max, |index, _| {
+            indices.push(index);
+            true
+        });
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn rays_only_visit_the_boxes_they_cross() {
+        // A row of unit boxes along the x axis, and an unbounded object.
+        let mut boxes: Vec<_> = (0..100)
+            .map(|i| {
+                let min = Point3::new(2.0 * i as f64, 0.0, 0.0);
+                Some(Aabb::new(min, min + Vec3::new(1.0, 1.0, 1.0)))
+            })
+            .collect();
+        boxes.push(None);
+        let bvh = Bvh::new(&boxes);
+
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let ray = Ray::new(Point3::new(40.5, 2.0, 0.5), down);
+        let indices = visited(&bvh, &ray);
+        assert!(
+            indices.contains(&20) && indices.contains(&100),
+            "{:?}",
+            indices
+        );
+        assert!(indices.len() <= MAX_LEAF_OBJECTS + 1, "{:?}", indices);
+        let ray = Ray::new(Point3::new(40.5, 2.0, 1.5), down);
+        assert_eq!(visited(&bvh, &ray), vec![100]);
+        let along = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(visited(&bvh, &along).len(), 101);
+
+        // Lowering the range skips the boxes beyond it.
+        let (mut count, mut t_max) = (0, INIFINTY);
+        bvh.traverse(&along, 0.0, &mut t_max, |index, t_max| {
+            count += 1;
+            if index == 3 {
+                *t_max = 8.0;
+            }
+            true
+        });
+        assert!(count < 10, "{}", count);
+    }
+
+    #[test]
+    fn lists_find_the_same_hits_as_a_linear_search() {
+        let material = Lambertian::new(Rgb::from(0.5));
+        let mut list = HittableList::new();
+        for i in 0..200 {
+            let x = (i as f64 * 0.618_034).fract() * 10.0;
+            let z = (i as f64 * 0.414_214).fract() * 10.0;
+            let radius = 0.1 + 0.4 * (i as f64 * 0.732_051).fract();
+            list.add(Sphere::new(Point3::new(x, radius, z), radius, material));
+        }
+        list.add(Plane::new(
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+            material,
+        ));
+
+        for k in 0..500 {
+            let u = (k as f64 * 0.618_034).fract();
+            let v = (k as f64 * 0.236_068).fract();
+            let origin = Point3::new(10.0 * u, 3.0, -2.0);
+            let ray = Ray::new(origin, Point3::new(10.0 * v, 0.0, 12.0 * u) - origin);
+
+            let mut expected = None;
+            let mut closest = INIFINTY;
+            for (id, object) in list.objects().iter().enumerate() {
+                if let Some(rec) = object.hit(&ray, 1.0e-3, closest) {
+                    closest = rec.t;
+                    expected = Some(id);
+                }
+            }
+            let rec = list.hit(&ray, 1.0e-3, INIFINTY).unwrap();
+            assert_eq!(Some(rec.object_id), expected);
+            assert_eq!(rec.t, closest);
+        }
+    }
+
+    #[test]
+    fn rays_pass_cut_outs_to_the_objects_behind() {
+        // A grid of triangles over an opaque one, where every other triangle is cut out.
+        let material = Lambertian::new(Rgb::from(0.5));
+        let cell = |i: usize, j: usize, z: f64| {
+            let corner = Point3::new(i as f64, j as f64, z);
+            [
+                corner,
+                corner + Vec3::new(1.0, 0.0, 0.0),
+                corner + Vec3::new(0.0, 1.0, 0.0),
+            ]
+        };
+        let mut list = HittableList::new();
+        for j in 0..10 {
+            for i in 0..10 {
+                let [a, b, c] = cell(i, j, 1.0);
+                let opacity = if (i + j) % 2 == 0 { 1.0 } else { 0.0 };
+                list.add(Triangle::new(a, b, c, Masked::new(material, opacity)));
+            }
+        }
+        let floor = [
+            Point3::new(-1.0, -1.0, 0.0),
+            Point3::new(30.0, -1.0, 0.0),
+            Point3::new(-1.0, 30.0, 0.0),
+        ];
+        list.add(Triangle::new(floor[0], floor[1], floor[2], material));
+
+        for j in 0..10 {
+            for i in 0..10 {
+                let origin = Point3::new(i as f64 + 0.25, j as f64 + 0.25, 5.0);
+                let ray = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0));
+                let rec = list.hit(&ray, 1.0e-3, INIFINTY).unwrap();
+                // Shadow rays stopping short of the floor only see the grid.
+                let transmittance = list.transmittance(&ray, 1.0e-3, 4.5);
+                if (i + j) % 2 == 0 {
+                    assert_eq!(rec.object_id, 10 * j + i);
+                    assert!((rec.p.z - 1.0).abs() < 1.0e-12);
+                    assert_eq!(transmittance, 0.0);
+                } else {
+                    assert_eq!(rec.object_id, 100);
+                    assert!(rec.p.z.abs() < 1.0e-12);
+                    assert_eq!(transmittance, 1.0);
+                }
+            }
+        }
+    }
+}
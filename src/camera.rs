@@ -21,20 +21,117 @@ impl Default for Camera {
         let lower_left_corner =
             origin - horizontal / 2.0 - vertical / 2.0 - Vec3::new(0.0, 0.0, focal_height);
 
-        return Camera {
+        Camera {
             origin,
             lower_left_corner,
             horizontal,
             vertical,
-        };
+        }
     }
 }
 
 impl Camera {
     pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        return Ray::new(
+        Ray::new(
             self.origin,
             self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin,
-        );
+        )
+    }
+
+    /// The position of the pinhole, where every ray starts.
+    pub fn origin(&self) -> Point3 {
+        self.origin
+    }
+
+    /// Returns the coordinates `(u, v)` of [`Camera::get_ray`] for the ray through `p`, or
+    /// `None` if `p` is not in view.
+    pub fn project(&self, p: Point3) -> Option<(f64, f64)> {
+        let (forward, distance) = self.image_plane();
+        let direction = p - self.origin;
+        let cos_theta = direction.dot(forward);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        let offset = self.origin + direction * (distance / cos_theta) - self.lower_left_corner;
+        let u = offset.dot(self.horizontal) / self.horizontal.len_squared();
+        let v = offset.dot(self.vertical) / self.vertical.len_squared();
+        if (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v) {
+            Some((u, v))
+        } else {
+            None
+        }
+    }
+
+    /// The importance emitted by the camera along the unit `direction`, which is zero outside
+    /// the view.
+    ///
+    /// Importance is normalized so that it integrates to one over the image plane, which makes
+    /// the measurement of a ray the average radiance over the image.
+    pub fn importance(&self, direction: Vec3) -> f64 {
+        if self.project(self.origin + direction).is_none() {
+            return 0.0;
+        }
+        let (forward, _) = self.image_plane();
+        let cos_theta = direction.dot(forward);
+        self.pdf_direction(direction) / cos_theta
+    }
+
+    /// The solid angle density of rays through a uniformly sampled point of the image plane
+    /// having the unit `direction`.
+    pub fn pdf_direction(&self, direction: Vec3) -> f64 {
+        if self.project(self.origin + direction).is_none() {
+            return 0.0;
+        }
+        let (forward, distance) = self.image_plane();
+        let cos_theta = direction.dot(forward);
+        let area = self.horizontal.cross(self.vertical).len();
+        distance * distance / (area * cos_theta.powi(3))
+    }
+
+    /// The unit normal of the image plane pointing away from the camera, and the distance of
+    /// the plane from the origin.
+    fn image_plane(&self) -> (Vec3, f64) {
+        let normal = self.horizontal.cross(self.vertical).normal();
+        let distance = (self.lower_left_corner - self.origin).dot(normal);
+        if distance < 0.0 {
+            (-normal, -distance)
+        } else {
+            (normal, distance)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projection_inverts_rays() {
+        let camera = Camera::default();
+        let ray = camera.get_ray(0.25, 0.8);
+        let (u, v) = camera.project(ray.at(3.0)).unwrap();
+        assert!((u - 0.25).abs() < 1.0e-9 && (v - 0.8).abs() < 1.0e-9);
+        assert!(camera.project(ray.at(-1.0)).is_none());
+    }
+
+    #[test]
+    fn importance_integrates_to_one() {
+        // Integrate the importance times the cosine over the solid angle of the image.
+        let camera = Camera::default();
+        let n = 200;
+        let mut total = 0.0;
+        for j in 0..n {
+            for i in 0..n {
+                let (u, v) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let direction = camera.get_ray(u, v).direction.normal();
+                // The solid angle of a cell is its area times the cosine over the squared distance.
+                let area = camera.horizontal.len() * camera.vertical.len() / (n * n) as f64;
+                let cos_theta = -direction.z;
+                let solid_angle = area * cos_theta.powi(3);
+                total += camera.importance(direction) * cos_theta * solid_angle;
+            }
+        }
+        assert!((total - 1.0).abs() < 1.0e-6, "{}", total);
     }
 }
@@ -14,14 +14,38 @@ impl From<Vec3> for Rgb {
     }
 }
 
-impl Into<Vec3> for Rgb {
-    fn into(self) -> Vec3 {
-        let (x, y, z) = self.into();
+impl From<Rgb> for Vec3 {
+    fn from(c: Rgb) -> Self {
+        let (x, y, z) = c.into();
         (x, y, z).into()
     }
 }
 
 impl Rgb {
+    /// Returns the relative luminance of a linear Rec. 709 / sRGB color.
+    pub fn luminance(self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Returns the largest channel.
+    pub fn max(self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+
+    /// Writes a display-referred color, whose channels are within [0, 1], as 8-bit values.
+    pub fn write_display<W: io::Write + fmt::Debug>(self, stream: &mut W) -> Result<()> {
+        let (r, g, b) = self.into();
+
+        writeln!(
+            stream,
+            "{} {} {}",
+            (r.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (g.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (b.clamp(0.0, 1.0) * 255.0).round() as u8,
+        )
+        .map_err(|_| ErrorKind::WriteColor(format!("{:?}", stream)))
+    }
+
     pub fn write<W: io::Write + fmt::Debug>(
         self,
         stream: &mut W,
@@ -35,9 +59,9 @@ impl Rgb {
         g = (g * scale).sqrt();
         b = (b * scale).sqrt();
 
-        write!(
+        writeln!(
             stream,
-            "{} {} {}\n",
+            "{} {} {}",
             (r.clamp(0.0, 0.999) * 256.0) as u8,
             (g.clamp(0.0, 0.999) * 256.0) as u8,
             (b.clamp(0.0, 0.999) * 256.0) as u8,
@@ -0,0 +1,288 @@
+//! 3D hittable [`Cone`]s.
+use crate::aabb::Aabb;
+use crate::disk::disk_uv;
+use crate::hittable::{HitRecord, Hittable};
+use crate::onb::Onb;
+use crate::prelude::*;
+
+/// A cone narrowing from a disk of `radius` around `base` to the `apex`, closed by the disk
+/// unless `capped` is unset.
+///
+/// On the side, `u` is the angle around the axis and `v` the height, both normalized to
+/// [0, 1]. The cap is parameterized like a [`Disk`](crate::disk::Disk).
+#[derive(Debug)]
+pub struct Cone {
+    pub base: Point3,
+    pub apex: Point3,
+    pub radius: f64,
+    pub capped: bool,
+    pub material: Option<Box<dyn Material>>,
+}
+
+impl Cone {
+    pub fn new(base: Point3, apex: Point3, radius: f64, material: impl Material + 'static) -> Self {
+        Cone {
+            base,
+            apex,
+            radius,
+            capped: true,
+            material: Some(Box::new(material)),
+        }
+    }
+
+    /// The local frame around the axis, and the height.
+    fn frame(&self) -> (Onb, f64) {
+        let axis = self.apex - self.base;
+        let height = axis.len();
+        (Onb::from_w(axis / height), height)
+    }
+
+    /// Builds the record of the point at `local` in the frame of the cone, on the side or on
+    /// the cap, with an outward normal.
+    fn record(&self, frame: &Onb, height: f64, cap: bool, local: Vec3, t: f64) -> HitRecord<'_> {
+        let (x, y) = (local.x, local.y);
+        let (local, normal, (u, v, dpdu, dpdv)) = if cap {
+            let local = Vec3::new(x, y, 0.0);
+            (local, -frame.w, disk_uv(frame, local, self.radius))
+        } else {
+            // The gradient of x² + y² - (r / h)² (h - z)², which vanishes at the apex.
+            let k = self.radius / height;
+            let gradient = Vec3::new(x, y, k * k * (height - local.z));
+            let normal = if gradient.near_zero() {
+                frame.w
+            } else {
+                frame.to_world(gradient.normal())
+            };
+            let phi = y.atan2(x).rem_euclid(2.0 * PI);
+            let dpdu = 2.0 * PI * frame.to_world(Vec3::new(-y, x, 0.0));
+            let dpdv = frame.to_world(Vec3::new(
+                -self.radius * phi.cos(),
+                -self.radius * phi.sin(),
+                height,
+            ));
+            (
+                local,
+                normal,
+                (phi / (2.0 * PI), local.z / height, dpdu, dpdv),
+            )
+        };
+
+        let p = self.base + frame.to_world(local);
+        let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
+        record.normal = normal;
+        record.geometric_normal = record.normal;
+        record.front_face = true;
+        record.u = u;
+        record.v = v;
+        record.dpdu = dpdu;
+        record.dpdv = dpdv;
+        record
+    }
+
+    fn side_area(&self, height: f64) -> f64 {
+        PI * self.radius * (self.radius * self.radius + height * height).sqrt()
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (frame, height) = self.frame();
+        let o = frame.to_local(ray.origin - self.base);
+        let d = frame.to_local(ray.direction);
+
+        // Solve x² + y² = k² (h - z)² along the ray, which also finds the mirrored cone above
+        // the apex, rejected by the height check.
+        let k2 = (self.radius / height).powi(2);
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let half_b = o.x * d.x + o.y * d.y + k2 * (height - o.z) * d.z;
+        let c = o.x * o.x + o.y * o.y - k2 * (height - o.z) * (height - o.z);
+        let mut candidates = [(INIFINTY, false); 3];
+        let roots = if a.abs() < 1.0e-12 {
+            if half_b.abs() < 1.0e-12 {
+                [INIFINTY, INIFINTY]
+            } else {
+                [-c / (2.0 * half_b), INIFINTY]
+            }
+        } else {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                [INIFINTY, INIFINTY]
+            } else {
+                let sqrtd = discriminant.sqrt();
+                [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+            }
+        };
+        for (i, &t) in roots.iter().enumerate() {
+            let z = o.z + t * d.z;
+            if t.is_finite() && (0.0..=height).contains(&z) {
+                candidates[i] = (t, false);
+            }
+        }
+        if self.capped && d.z.abs() > 1.0e-12 {
+            let t = -o.z / d.z;
+            let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+            if x * x + y * y <= self.radius * self.radius {
+                candidates[2] = (t, true);
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Find the nearest hit that lies in the acceptable range and is not cut out. Missing
+        // candidates are left at infinity, even when the range is unbounded.
+        for &(t, cap) in &candidates {
+            if t > t_max || t == INIFINTY {
+                break;
+            }
+            if t < t_min {
+                continue;
+            }
+            let mut record = self.record(&frame, height, cap, o + t * d, t);
+            record.set_face_normal(ray, record.normal);
+            if !record.is_cut_out() {
+                return Some(record);
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (frame, _) = self.frame();
+        Some(Aabb::disk(self.base, frame.w, self.radius).expand(self.apex))
+    }
+
+    fn area(&self) -> f64 {
+        let (_, height) = self.frame();
+        let cap = if self.capped { 1.0 } else { 0.0 };
+        self.side_area(height) + cap * PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u1: f64, u2: f64) -> Option<HitRecord<'_>> {
+        let (frame, height) = self.frame();
+        let phi = 2.0 * PI * u2;
+        let (cos_phi, sin_phi) = (phi.cos(), phi.sin());
+
+        // Pick the side or the cap by area, reusing `u1` for the position within the part.
+        let side = self.side_area(height);
+        let s = u1 * self.area();
+        if s < side {
+            // The area of the side grows with the square of the distance from the apex.
+            let r = self.radius * (s / side).sqrt();
+            let z = height * (1.0 - r / self.radius);
+            let local = Vec3::new(r * cos_phi, r * sin_phi, z);
+            return Some(self.record(&frame, height, false, local, 0.0));
+        }
+        let cap = PI * self.radius * self.radius;
+        let r = self.radius * ((s - side) / cap).min(1.0).sqrt();
+        let local = Vec3::new(r * cos_phi, r * sin_phi, 0.0);
+        Some(self.record(&frame, height, true, local, 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    /// A cone of radius 1 standing on the origin, with its apex 2 units up along `y`.
+    fn cone(capped: bool) -> Cone {
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        Cone {
+            capped,
+            ..Cone::new(Point3::default(), Point3::new(0.0, 2.0, 0.0), 1.0, material)
+        }
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).len() < 1.0e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn hits_the_nearest_part() {
+        let cone = cone(true);
+        // The side rises by 2 over a radius of 1.
+        let side_normal = Vec3::new(2.0, 1.0, 0.0).normal();
+
+        let down = Ray::new(Point3::new(0.25, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = cone.hit(&down, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 1.5).abs() < 1.0e-9 && rec.front_face);
+        assert_near(rec.normal, side_normal);
+        let rec = cone.hit(&down, 2.0, INIFINTY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-9 && !rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+
+        let up = Ray::new(Point3::new(0.25, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = cone.hit(&up, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-9 && rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, -1.0, 0.0));
+        let rec = cone.hit(&up, 1.5, INIFINTY).unwrap();
+        assert!((rec.t - 2.5).abs() < 1.0e-9 && !rec.front_face);
+        assert_near(rec.normal, -side_normal);
+
+        // Without the cap, the inside of the side is seen from below.
+        let open = self::cone(false);
+        let rec = open.hit(&up, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 2.5).abs() < 1.0e-9 && !rec.front_face);
+    }
+
+    #[test]
+    fn surface_coordinates_are_angle_and_height_or_radius() {
+        let cone = cone(true);
+        let hit = |x: f64, z: f64, direction: Vec3| {
+            let origin = Point3::new(x, -1.0 - 4.0 * direction.y, z);
+            cone.hit(&Ray::new(origin, direction), 0.0, INIFINTY)
+                .unwrap()
+        };
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        // On the cap, a quarter turn counterclockwise around the axis maps `x` to `-z`.
+        let first = hit(0.5, 0.0, up);
+        let second = hit(0.0, -0.75, up);
+        assert!((first.v - 0.5).abs() < 1.0e-9);
+        assert!((second.v - 0.75).abs() < 1.0e-9);
+        assert!(((second.u - first.u).rem_euclid(1.0) - 0.25).abs() < 1.0e-9);
+
+        // On the side, from above.
+        let first = hit(0.25, 0.0, -up);
+        let second = hit(0.0, -0.5, -up);
+        assert!((first.v - 0.75).abs() < 1.0e-9);
+        assert!((second.v - 0.5).abs() < 1.0e-9);
+        assert!(((second.u - first.u).rem_euclid(1.0) - 0.25).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn samples_are_uniform_over_the_area() {
+        let cone = cone(true);
+        let n = 120;
+        let (mut side, mut upper_side, mut cap, mut inner_cap) = (0, 0, 0, 0);
+        for i in 0..n {
+            for j in 0..n {
+                let (u1, u2) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let rec = cone.sample_surface(u1, u2).unwrap();
+                let radius = (rec.p.x * rec.p.x + rec.p.z * rec.p.z).sqrt();
+                if rec.normal.y > 0.0 {
+                    side += 1;
+                    assert!((radius - (1.0 - rec.p.y / 2.0)).abs() < 1.0e-9);
+                    assert!((rec.normal.y - 1.0 / 5.0_f64.sqrt()).abs() < 1.0e-9);
+                    if rec.p.y > 1.0 {
+                        upper_side += 1;
+                    }
+                } else {
+                    cap += 1;
+                    assert!(rec.p.y.abs() < 1.0e-9 && radius <= 1.0 + 1.0e-9);
+                    if radius < 0.5 {
+                        inner_cap += 1;
+                    }
+                }
+            }
+        }
+
+        // The side covers π√5 of the area of π(√5 + 1).
+        let total = (n * n) as f64;
+        let sqrt5 = 5.0_f64.sqrt();
+        assert!((side as f64 / total - sqrt5 / (sqrt5 + 1.0)).abs() < 0.01);
+        assert!((cap as f64 / total - 1.0 / (sqrt5 + 1.0)).abs() < 0.01);
+        // The upper half of the side, towards the apex, covers a quarter of its area.
+        assert!((upper_side as f64 / side as f64 - 0.25).abs() < 0.01);
+        assert!((inner_cap as f64 / cap as f64 - 0.25).abs() < 0.01);
+    }
+}
@@ -0,0 +1,208 @@
+//! Constructive solid geometry, combining the solids bounded by [`Hittable`]s.
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::prelude::*;
+
+/// How a [`Csg`] combines its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Inside either operand.
+    Union,
+    /// Inside both operands.
+    Intersection,
+    /// Inside the left operand but not the right one.
+    Difference,
+}
+
+impl Operation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            Operation::Union => in_left || in_right,
+            Operation::Intersection => in_left && in_right,
+            Operation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// A solid combining two others, e.g. a lens as the intersection of two spheres, or a bolt
+/// hole as a difference.
+///
+/// The operands must be closed surfaces with outward normals, so that rays enter them at front
+/// faces, and may be [`Csg`]s themselves. Every part of the surface keeps the material of its
+/// operand, and the parts of the right operand that bound a difference face inwards.
+pub struct Csg {
+    pub operation: Operation,
+    pub left: Box<dyn Hittable + Send + Sync>,
+    pub right: Box<dyn Hittable + Send + Sync>,
+}
+
+impl Csg {
+    pub fn new(
+        operation: Operation,
+        left: impl Hittable + Send + Sync + 'static,
+        right: impl Hittable + Send + Sync + 'static,
+    ) -> Self {
+        Csg {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn union(
+        left: impl Hittable + Send + Sync + 'static,
+        right: impl Hittable + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(Operation::Union, left, right)
+    }
+
+    pub fn intersection(
+        left: impl Hittable + Send + Sync + 'static,
+        right: impl Hittable + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(Operation::Intersection, left, right)
+    }
+
+    pub fn difference(
+        left: impl Hittable + Send + Sync + 'static,
+        right: impl Hittable + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(Operation::Difference, left, right)
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.hits(ray, t_min, t_max).into_iter().next()
+    }
+
+    fn hits(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<HitRecord<'_>> {
+        // The operands are searched past `t_max`, since whether the ray starts inside them is
+        // only known from their first hit.
+        let left = self.left.hits(ray, t_min, INIFINTY);
+        let right = self.right.hits(ray, t_min, INIFINTY);
+        let starts_inside =
+            |hits: &[HitRecord]| matches!(hits.first(), Some(rec) if !rec.front_face);
+        let mut in_left = starts_inside(&left);
+        let mut in_right = starts_inside(&right);
+        let mut inside = self.operation.contains(in_left, in_right);
+
+        // Merge the hits of both operands, keeping those where the ray enters or leaves the
+        // combined solid.
+        let mut hits = Vec::new();
+        let mut left = left.into_iter().peekable();
+        let mut right = right.into_iter().peekable();
+        loop {
+            let from_left = match (left.peek(), right.peek()) {
+                (Some(l), Some(r)) => l.t <= r.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let mut rec = if from_left {
+                let rec = left.next().unwrap();
+                in_left = rec.front_face;
+                rec
+            } else {
+                let rec = right.next().unwrap();
+                in_right = rec.front_face;
+                rec
+            };
+            if rec.t > t_max {
+                break;
+            }
+
+            let now_inside = self.operation.contains(in_left, in_right);
+            if now_inside != inside {
+                // The normal already faces the ray, only the side of the solid changes.
+                inside = now_inside;
+                rec.front_face = inside;
+                hits.push(rec);
+            }
+        }
+        hits
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+        match self.operation {
+            Operation::Union => Some(left?.union(right?)),
+            Operation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(
+                    left.intersection(right)
+                        .unwrap_or_else(|| Aabb::from_point(left.min)),
+                ),
+                (left, right) => left.or(right),
+            },
+            Operation::Difference => left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn spheres() -> (Sphere, Sphere) {
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        let left = Sphere::new(Point3::new(-0.5, 0.0, 0.0), 1.0, material);
+        let right = Sphere::new(Point3::new(0.5, 0.0, 0.0), 1.0, material);
+        (left, right)
+    }
+
+    fn crossings(csg: &Csg, origin: Point3) -> Vec<(f64, bool)> {
+        let ray = Ray::new(origin, Vec3::new(1.0, 0.0, 0.0));
+        csg.hits(&ray, 0.0, INIFINTY)
+            .iter()
+            .map(|rec| (rec.p.x, rec.front_face))
+            .collect()
+    }
+
+    fn assert_crossings(actual: Vec<(f64, bool)>, expected: &[(f64, bool)]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a.0 - e.0).abs() < 1.0e-9 && a.1 == e.1, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn operations_keep_the_boundaries_of_the_combined_solid() {
+        let origin = Point3::new(-5.0, 0.0, 0.0);
+        let (left, right) = spheres();
+        let union = Csg::union(left, right);
+        assert_crossings(crossings(&union, origin), &[(-1.5, true), (1.5, false)]);
+
+        let (left, right) = spheres();
+        let lens = Csg::intersection(left, right);
+        assert_crossings(crossings(&lens, origin), &[(-0.5, true), (0.5, false)]);
+
+        let (left, right) = spheres();
+        let difference = Csg::difference(left, right);
+        assert_crossings(
+            crossings(&difference, origin),
+            &[(-1.5, true), (-0.5, false)],
+        );
+    }
+
+    #[test]
+    fn rays_starting_inside_only_leave() {
+        let (left, right) = spheres();
+        let lens = Csg::intersection(left, right);
+        assert_crossings(
+            crossings(&lens, Point3::new(0.0, 0.0, 0.0)),
+            &[(0.5, false)],
+        );
+
+        // Nested operations see the crossings of the inner one.
+        let (left, right) = spheres();
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        let core = Sphere::new(Point3::new(0.0, 0.0, 0.0), 0.25, material);
+        let hollow = Csg::difference(Csg::intersection(left, right), core);
+        assert_crossings(
+            crossings(&hollow, Point3::new(-5.0, 0.0, 0.0)),
+            &[(-0.5, true), (-0.25, false), (0.25, true), (0.5, false)],
+        );
+    }
+}
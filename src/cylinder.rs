@@ -0,0 +1,293 @@
+//! 3D hittable [`Cylinder`]s.
+use crate::aabb::Aabb;
+use crate::disk::disk_uv;
+use crate::hittable::{HitRecord, Hittable};
+use crate::onb::Onb;
+use crate::prelude::*;
+
+/// A cylinder around the segment from `base` to `top`, closed by disks at both ends unless
+/// `capped` is unset.
+///
+/// On the side, `u` is the angle around the axis and `v` the height, both normalized to
+/// [0, 1]. The caps are parameterized like a [`Disk`](crate::disk::Disk).
+#[derive(Debug)]
+pub struct Cylinder {
+    pub base: Point3,
+    pub top: Point3,
+    pub radius: f64,
+    pub capped: bool,
+    pub material: Option<Box<dyn Material>>,
+}
+
+/// The parts of the surface of a [`Cylinder`], in the local frame where its axis is `z`.
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    Side,
+    Base,
+    Top,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, top: Point3, radius: f64, material: impl Material + 'static) -> Self {
+        Cylinder {
+            base,
+            top,
+            radius,
+            capped: true,
+            material: Some(Box::new(material)),
+        }
+    }
+
+    /// The local frame around the axis, and the height.
+    fn frame(&self) -> (Onb, f64) {
+        let axis = self.top - self.base;
+        let height = axis.len();
+        (Onb::from_w(axis / height), height)
+    }
+
+    /// Builds the record of the point of `part` at `local` in the frame of the cylinder, with
+    /// an outward normal.
+    fn record(&self, frame: &Onb, height: f64, part: Part, local: Vec3, t: f64) -> HitRecord<'_> {
+        let (x, y) = (local.x, local.y);
+        let (local, normal, (u, v, dpdu, dpdv)) = match part {
+            Part::Side => {
+                let phi = y.atan2(x).rem_euclid(2.0 * PI);
+                let dpdu = 2.0 * PI * frame.to_world(Vec3::new(-y, x, 0.0));
+                let uv = (phi / (2.0 * PI), local.z / height, dpdu, height * frame.w);
+                (
+                    local,
+                    frame.to_world(Vec3::new(x, y, 0.0) / self.radius),
+                    uv,
+                )
+            }
+            Part::Base => {
+                let local = Vec3::new(x, y, 0.0);
+                (local, -frame.w, disk_uv(frame, local, self.radius))
+            }
+            Part::Top => {
+                let uv = disk_uv(frame, Vec3::new(x, y, 0.0), self.radius);
+                (Vec3::new(x, y, height), frame.w, uv)
+            }
+        };
+
+        let p = self.base + frame.to_world(local);
+        let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
+        record.normal = normal;
+        record.geometric_normal = record.normal;
+        record.front_face = true;
+        record.u = u;
+        record.v = v;
+        record.dpdu = dpdu;
+        record.dpdv = dpdv;
+        record
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (frame, height) = self.frame();
+        let o = frame.to_local(ray.origin - self.base);
+        let d = frame.to_local(ray.direction);
+        let r2 = self.radius * self.radius;
+
+        let mut candidates = [(INIFINTY, Part::Side); 4];
+        let a = d.x * d.x + d.y * d.y;
+        if a > 1.0e-12 {
+            let half_b = o.x * d.x + o.y * d.y;
+            let c = o.x * o.x + o.y * o.y - r2;
+            let discriminant = half_b * half_b - a * c;
+            if discriminant >= 0.0 {
+                let sqrtd = discriminant.sqrt();
+                for (i, &t) in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+                    .iter()
+                    .enumerate()
+                {
+                    let z = o.z + t * d.z;
+                    if (0.0..=height).contains(&z) {
+                        candidates[i] = (t, Part::Side);
+                    }
+                }
+            }
+        }
+        if self.capped && d.z.abs() > 1.0e-12 {
+            for (i, &(z, part)) in [(0.0, Part::Base), (height, Part::Top)].iter().enumerate() {
+                let t = (z - o.z) / d.z;
+                let (x, y) = (o.x + t * d.x, o.y + t * d.y);
+                if x * x + y * y <= r2 {
+                    candidates[2 + i] = (t, part);
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Find the nearest hit that lies in the acceptable range and is not cut out. Missing
+        // candidates are left at infinity, even when the range is unbounded.
+        for &(t, part) in &candidates {
+            if t > t_max || t == INIFINTY {
+                break;
+            }
+            if t < t_min {
+                continue;
+            }
+            let mut record = self.record(&frame, height, part, o + t * d, t);
+            record.set_face_normal(ray, record.normal);
+            if !record.is_cut_out() {
+                return Some(record);
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (frame, _) = self.frame();
+        let base = Aabb::disk(self.base, frame.w, self.radius);
+        Some(base.union(Aabb::disk(self.top, frame.w, self.radius)))
+    }
+
+    fn area(&self) -> f64 {
+        let (_, height) = self.frame();
+        let caps = if self.capped { 2.0 } else { 0.0 };
+        2.0 * PI * self.radius * height + caps * PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u1: f64, u2: f64) -> Option<HitRecord<'_>> {
+        let (frame, height) = self.frame();
+        let phi = 2.0 * PI * u2;
+        let (cos_phi, sin_phi) = (phi.cos(), phi.sin());
+
+        // Pick the side or a cap by area, reusing `u1` for the position within the part.
+        let side = 2.0 * PI * self.radius * height;
+        let s = u1 * self.area();
+        if s < side {
+            let z = height * s / side;
+            let local = Vec3::new(self.radius * cos_phi, self.radius * sin_phi, z);
+            return Some(self.record(&frame, height, Part::Side, local, 0.0));
+        }
+        let cap = PI * self.radius * self.radius;
+        let (part, u) = if s < side + cap {
+            (Part::Base, (s - side) / cap)
+        } else {
+            (Part::Top, ((s - side - cap) / cap).min(1.0))
+        };
+        let r = self.radius * u.sqrt();
+        let local = Vec3::new(r * cos_phi, r * sin_phi, 0.0);
+        Some(self.record(&frame, height, part, local, 0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    /// A cylinder of radius 1 standing on the origin, 2 units high along `y`.
+    fn cylinder(capped: bool) -> Cylinder {
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        Cylinder {
+            capped,
+            ..Cylinder::new(Point3::default(), Point3::new(0.0, 2.0, 0.0), 1.0, material)
+        }
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).len() < 1.0e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn hits_the_nearest_part() {
+        let cylinder = cylinder(true);
+
+        // Up through both caps.
+        let up = Ray::new(Point3::new(0.5, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = cylinder.hit(&up, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-9 && rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, -1.0, 0.0));
+        let rec = cylinder.hit(&up, 1.5, INIFINTY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-9 && !rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, -1.0, 0.0));
+
+        // In through the side and out through the top.
+        let diagonal = Ray::new(Point3::new(-2.0, 0.5, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        let rec = cylinder.hit(&diagonal, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-9 && rec.front_face);
+        assert_near(rec.normal, Vec3::new(-1.0, 0.0, 0.0));
+        let rec = cylinder.hit(&diagonal, 1.01, INIFINTY).unwrap();
+        assert!((rec.t - 1.5).abs() < 1.0e-9 && !rec.front_face);
+        assert_near(rec.normal, Vec3::new(0.0, -1.0, 0.0));
+
+        // Without caps, the same rays pass through the open ends.
+        let open = self::cylinder(false);
+        assert!(open.hit(&up, 0.0, INIFINTY).is_none());
+        let rec = open.hit(&diagonal, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-9);
+        assert!(open.hit(&diagonal, 1.01, INIFINTY).is_none());
+    }
+
+    #[test]
+    fn surface_coordinates_are_angle_and_height_or_radius() {
+        let cylinder = cylinder(true);
+        let hit = |origin: Point3, direction: Vec3| {
+            cylinder
+                .hit(&Ray::new(origin, direction), 0.0, INIFINTY)
+                .unwrap()
+        };
+        let up = Vec3::new(0.0, 1.0, 0.0);
+
+        // On the caps, a quarter turn counterclockwise around the axis maps `x` to `-z`.
+        for &y in &[-1.0, 3.0] {
+            let direction = if y < 0.0 { up } else { -up };
+            let first = hit(Point3::new(0.5, y, 0.0), direction);
+            let second = hit(Point3::new(0.0, y, -0.75), direction);
+            assert!((first.v - 0.5).abs() < 1.0e-9);
+            assert!((second.v - 0.75).abs() < 1.0e-9);
+            assert!(((second.u - first.u).rem_euclid(1.0) - 0.25).abs() < 1.0e-9);
+        }
+
+        let left = Vec3::new(-1.0, 0.0, 0.0);
+        let first = hit(Point3::new(2.0, 0.5, 0.0), left);
+        let second = hit(Point3::new(2.0, 1.5, -0.5), left);
+        assert!((first.v - 0.25).abs() < 1.0e-9);
+        assert!((second.v - 0.75).abs() < 1.0e-9);
+        // The second point lies 30 degrees further around the axis.
+        assert!(((second.u - first.u).rem_euclid(1.0) - 1.0 / 12.0).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn samples_are_uniform_over_the_area() {
+        let cylinder = cylinder(true);
+        let n = 120;
+        let (mut side, mut lower_side, mut caps, mut inner_caps) = (0, 0, [0, 0], 0);
+        for i in 0..n {
+            for j in 0..n {
+                let (u1, u2) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let rec = cylinder.sample_surface(u1, u2).unwrap();
+                let radius = (rec.p.x * rec.p.x + rec.p.z * rec.p.z).sqrt();
+                if rec.normal.y.abs() < 1.0e-9 {
+                    side += 1;
+                    assert!((radius - 1.0).abs() < 1.0e-9);
+                    assert_near(rec.normal, Vec3::new(rec.p.x, 0.0, rec.p.z));
+                    if rec.p.y < 1.0 {
+                        lower_side += 1;
+                    }
+                } else {
+                    let top = rec.normal.y > 0.0;
+                    assert!((rec.p.y - if top { 2.0 } else { 0.0 }).abs() < 1.0e-9);
+                    caps[top as usize] += 1;
+                    if radius < 0.5 {
+                        inner_caps += 1;
+                    }
+                }
+            }
+        }
+
+        // The side covers 4π of the area of 6π.
+        let total = (n * n) as f64;
+        assert!((side as f64 / total - 2.0 / 3.0).abs() < 0.01);
+        assert!((lower_side as f64 / side as f64 - 0.5).abs() < 0.01);
+        for &cap in &caps {
+            assert!((cap as f64 / total - 1.0 / 6.0).abs() < 0.01);
+        }
+        let cap_samples = (caps[0] + caps[1]) as f64;
+        assert!((inner_caps as f64 / cap_samples - 0.25).abs() < 0.01);
+    }
+}
@@ -0,0 +1,305 @@
+//! A CPU image denoiser guided by auxiliary feature buffers.
+//!
+//! The [`Denoiser`] implements the edge-avoiding à-trous wavelet filter of Dammertz et al.,
+//! "Edge-Avoiding À-Trous Wavelet Transform for fast Global Illumination Filtering" (2010). Each
+//! pass convolves the image with a sparse 5x5 B3-spline kernel whose taps are spread `2^i`
+//! pixels apart, so a few passes cover a large footprint. Every tap is additionally weighted by
+//! how similar its color, albedo, normal and depth are to the center pixel, which keeps geometric
+//! and texture edges sharp.
+use crate::aov::{Aov, AovBuffers};
+use crate::image::Image;
+use crate::prelude::*;
+
+/// Feature buffers that guide the [`Denoiser`].
+#[derive(Debug, Clone)]
+pub struct Features {
+    pub albedo: Image,
+    pub normal: Image,
+    pub depth: Image,
+}
+
+impl Features {
+    /// Extracts the feature buffers from collected AOVs.
+    pub fn from_aovs(aovs: &AovBuffers) -> Self {
+        Features {
+            albedo: aovs.image(Aov::Albedo),
+            normal: aovs.image(Aov::Normal),
+            depth: aovs.image(Aov::Depth),
+        }
+    }
+}
+
+/// An edge-avoiding à-trous wavelet denoiser.
+///
+/// Each `sigma_*` controls how quickly the weight of a tap falls off as the corresponding
+/// feature differs from the center pixel; smaller values preserve more edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    /// Number of filter passes. The filter footprint grows as `4 * 2^iterations` pixels.
+    pub iterations: u32,
+    /// Color falloff, applied to tone-compressed colors and halved after every pass.
+    pub sigma_color: f64,
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+    /// Depth falloff, relative to the depth of the center pixel.
+    pub sigma_depth: f64,
+    /// Divides the color by the albedo before filtering and multiplies it back afterwards, so
+    /// that texture detail is not blurred along with the noise.
+    pub demodulate_albedo: bool,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+            demodulate_albedo: true,
+        }
+    }
+}
+
+/// The 1D B3-spline kernel; the 2D kernel is its outer product.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Albedo below this is treated as black and excluded from demodulation.
+const MIN_ALBEDO: f64 = 1.0e-3;
+
+/// The depth tolerance grows with the depth, which is clamped to this distance in scene units
+/// so that the tolerance does not vanish right in front of the camera.
+const MIN_DEPTH: f64 = 1.0e-3;
+
+impl Denoiser {
+    /// Denoises a linear radiance image with the help of `features`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the feature buffers differ in size from `color`.
+    pub fn denoise(&self, color: &Image, features: &Features) -> Image {
+        let (width, height) = (color.width(), color.height());
+        for feature in &[&features.albedo, &features.normal, &features.depth] {
+            assert!(
+                feature.width() == width && feature.height() == height,
+                "feature buffers must match the size of the image"
+            );
+        }
+
+        let mut current = color.clone();
+        if self.demodulate_albedo {
+            for (pixel, &albedo) in current
+                .pixels_mut()
+                .iter_mut()
+                .zip(features.albedo.pixels())
+            {
+                *pixel = demodulate(*pixel, albedo);
+            }
+        }
+
+        let mut sigma_color = self.sigma_color;
+        for iteration in 0..self.iterations {
+            current = self.pass(&current, features, 1 << iteration, sigma_color);
+            sigma_color *= 0.5;
+        }
+
+        if self.demodulate_albedo {
+            for (pixel, &albedo) in current
+                .pixels_mut()
+                .iter_mut()
+                .zip(features.albedo.pixels())
+            {
+                *pixel = remodulate(*pixel, albedo);
+            }
+        }
+
+        current
+    }
+
+    /// Runs a single filter pass with the given tap spacing.
+    fn pass(&self, input: &Image, features: &Features, step: usize, sigma_color: f64) -> Image {
+        let (width, height) = (input.width(), input.height());
+        let mut output = Image::new(width, height);
+
+        for j in 0..height {
+            for i in 0..width {
+                let color_p = input.get(i, j);
+                let compressed_p = compress(color_p);
+                let albedo_p = features.albedo.get(i, j);
+                let normal_p: Vec3 = features.normal.get(i, j).into();
+                let depth_p = features.depth.get(i, j).r;
+
+                let mut sum = Rgb::default();
+                let mut weight_sum = 0.0;
+
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    let y = j as i64 + (dy as i64 - 2) * step as i64;
+                    if y < 0 || y >= height as i64 {
+                        continue;
+                    }
+
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let x = i as i64 + (dx as i64 - 2) * step as i64;
+                        if x < 0 || x >= width as i64 {
+                            continue;
+                        }
+                        let (x, y) = (x as usize, y as usize);
+
+                        let color_q = input.get(x, y);
+                        let normal_q: Vec3 = features.normal.get(x, y).into();
+                        let depth_q = features.depth.get(x, y).r;
+
+                        let w_color = gaussian(
+                            (compress(color_q) - compressed_p).len_squared(),
+                            sigma_color,
+                        );
+                        let w_albedo = gaussian(
+                            (features.albedo.get(x, y) - albedo_p).len_squared(),
+                            self.sigma_albedo,
+                        );
+                        let w_normal =
+                            gaussian((normal_q - normal_p).len_squared(), self.sigma_normal);
+                        let w_depth = (-(depth_q - depth_p).abs()
+                            / (self.sigma_depth * depth_p.max(MIN_DEPTH)))
+                        .exp();
+
+                        let weight = kx * ky * w_color * w_albedo * w_normal * w_depth;
+                        sum += weight * color_q;
+                        weight_sum += weight;
+                    }
+                }
+
+                // The center tap always has a positive weight, so `weight_sum` is never zero.
+                output.set(i, j, sum / weight_sum);
+            }
+        }
+
+        output
+    }
+}
+
+fn gaussian(distance_squared: f64, sigma: f64) -> f64 {
+    (-distance_squared / (sigma * sigma)).exp()
+}
+
+/// Compresses HDR values so that fireflies don't dominate color distances.
+fn compress(color: Rgb) -> Rgb {
+    color.map(|c| c / (1.0 + c))
+}
+
+fn demodulate(color: Rgb, albedo: Rgb) -> Rgb {
+    Rgb::new(
+        if albedo.r > MIN_ALBEDO {
+            color.r / albedo.r
+        } else {
+            color.r
+        },
+        if albedo.g > MIN_ALBEDO {
+            color.g / albedo.g
+        } else {
+            color.g
+        },
+        if albedo.b > MIN_ALBEDO {
+            color.b / albedo.b
+        } else {
+            color.b
+        },
+    )
+}
+
+fn remodulate(color: Rgb, albedo: Rgb) -> Rgb {
+    Rgb::new(
+        if albedo.r > MIN_ALBEDO {
+            color.r * albedo.r
+        } else {
+            color.r
+        },
+        if albedo.g > MIN_ALBEDO {
+            color.g * albedo.g
+        } else {
+            color.g
+        },
+        if albedo.b > MIN_ALBEDO {
+            color.b * albedo.b
+        } else {
+            color.b
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    fn flat_features(width: usize, height: usize) -> Features {
+        let mut normal = Image::new(width, height);
+        let mut depth = Image::new(width, height);
+        let mut albedo = Image::new(width, height);
+        for pixel in normal.pixels_mut() {
+            *pixel = Rgb::new(0.0, 0.0, 1.0);
+        }
+        for pixel in depth.pixels_mut() {
+            *pixel = Rgb::from(2.0);
+        }
+        for pixel in albedo.pixels_mut() {
+            *pixel = Rgb::from(0.5);
+        }
+        Features {
+            albedo,
+            normal,
+            depth,
+        }
+    }
+
+    /// Variance of the red channel over the left half of `image`.
+    fn left_variance(image: &Image) -> f64 {
+        let values: Vec<f64> = (0..image.height())
+            .flat_map(|j| (0..image.width() / 2).map(move |i| (i, j)))
+            .map(|(i, j)| image.get(i, j).r)
+            .collect();
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n
+    }
+
+    #[test]
+    fn constant_image_is_preserved() {
+        let features = flat_features(16, 12);
+        let mut color = Image::new(16, 12);
+        for pixel in color.pixels_mut() {
+            *pixel = Rgb::new(0.2, 0.4, 0.8);
+        }
+
+        let denoised = Denoiser::default().denoise(&color, &features);
+        for pixel in denoised.pixels() {
+            assert!((*pixel - Rgb::new(0.2, 0.4, 0.8)).len() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn noise_is_reduced_and_depth_edges_are_kept() {
+        let (width, height) = (32, 32);
+        let mut rng = StdRng::seed_from_u64(29);
+        let mut features = flat_features(width, height);
+        let mut color = Image::new(width, height);
+        for j in 0..height {
+            for i in 0..width {
+                // The right half is far away and much brighter.
+                let base = if i < width / 2 { 0.2 } else { 0.8 };
+                color.set(i, j, Rgb::from(base + 0.1 * (rng.gen::<f64>() - 0.5)));
+                if i >= width / 2 {
+                    features.depth.set(i, j, Rgb::from(20.0));
+                }
+            }
+        }
+
+        let denoised = Denoiser::default().denoise(&color, &features);
+        assert!(left_variance(&denoised) < 0.1 * left_variance(&color));
+
+        let left = denoised.get(width / 2 - 1, height / 2).r;
+        let right = denoised.get(width / 2, height / 2).r;
+        assert!((left - 0.2).abs() < 0.05, "left of edge was {}", left);
+        assert!((right - 0.8).abs() < 0.05, "right of edge was {}", right);
+    }
+}
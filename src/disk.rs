@@ -0,0 +1,170 @@
+//! 3D hittable [`Disk`]s.
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::onb::Onb;
+use crate::prelude::*;
+
+/// A flat disk around `center`, e.g. a round area light or a lid.
+///
+/// `u` is the angle around the normal and `v` the distance from the center, both normalized to
+/// [0, 1].
+#[derive(Debug)]
+pub struct Disk {
+    pub center: Point3,
+    /// The unit normal of the disk, whose side it points to is the outside.
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: Option<Box<dyn Material>>,
+}
+
+impl Disk {
+    pub fn new(
+        center: Point3,
+        normal: Vec3,
+        radius: f64,
+        material: impl Material + 'static,
+    ) -> Self {
+        Disk {
+            center,
+            normal: normal.normal(),
+            radius,
+            material: Some(Box::new(material)),
+        }
+    }
+
+    /// Builds the record of the point at `local` in the frame of the disk.
+    fn record(&self, frame: &Onb, local: Vec3, t: f64) -> HitRecord<'_> {
+        let p = self.center + frame.to_world(local);
+        let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
+        record.normal = self.normal;
+        record.geometric_normal = record.normal;
+        record.front_face = true;
+        let (u, v, dpdu, dpdv) = disk_uv(frame, local, self.radius);
+        record.u = u;
+        record.v = v;
+        record.dpdu = dpdu;
+        record.dpdv = dpdv;
+        record
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1.0e-12 {
+            return None;
+        }
+        let t = self.normal.dot(self.center - ray.origin) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let frame = Onb::from_w(self.normal);
+        let local = frame.to_local(ray.at(t) - self.center);
+        if local.x * local.x + local.y * local.y > self.radius * self.radius {
+            return None;
+        }
+        let mut record = self.record(&frame, Vec3::new(local.x, local.y, 0.0), t);
+        record.set_face_normal(ray, self.normal);
+
+        if record.is_cut_out() {
+            return None;
+        }
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::disk(self.center, self.normal, self.radius))
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u1: f64, u2: f64) -> Option<HitRecord<'_>> {
+        let r = self.radius * u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let frame = Onb::from_w(self.normal);
+        Some(self.record(&frame, Vec3::new(r * phi.cos(), r * phi.sin(), 0.0), 0.0))
+    }
+}
+
+/// Computes the surface coordinates and their partial derivatives of the point at `local` on
+/// a disk of `radius` in the plane of `frame`, as for [`Disk`].
+pub(crate) fn disk_uv(frame: &Onb, local: Vec3, radius: f64) -> (f64, f64, Vec3, Vec3) {
+    let r = (local.x * local.x + local.y * local.y).sqrt();
+    let phi = local.y.atan2(local.x).rem_euclid(2.0 * PI);
+    if r < 1.0e-9 {
+        // The parameterization is singular at the center; pick any tangent frame.
+        return (phi / (2.0 * PI), 0.0, frame.u, frame.v);
+    }
+    let dpdu = 2.0 * PI * frame.to_world(Vec3::new(-local.y, local.x, 0.0));
+    let dpdv = radius / r * frame.to_world(Vec3::new(local.x, local.y, 0.0));
+    (phi / (2.0 * PI), r / radius, dpdu, dpdv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn disk() -> Disk {
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        Disk::new(Point3::default(), Vec3::new(0.0, 0.0, 1.0), 2.0, material)
+    }
+
+    fn hit_from_above(disk: &Disk, x: f64, y: f64) -> Option<HitRecord<'_>> {
+        let down = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        disk.hit(&down, 0.0, INIFINTY)
+    }
+
+    #[test]
+    fn hits_within_the_radius() {
+        let disk = disk();
+        let rec = hit_from_above(&disk, 1.0, 0.0).unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-12);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit_from_above(&disk, 1.5, 1.5).is_none());
+
+        let up = Ray::new(Point3::new(1.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = disk.hit(&up, 0.0, INIFINTY).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn surface_coordinates_are_angle_and_radius() {
+        let disk = disk();
+        let first = hit_from_above(&disk, 1.0, 0.0).unwrap();
+        let second = hit_from_above(&disk, 0.0, 1.5).unwrap();
+        assert!((first.v - 0.5).abs() < 1.0e-12);
+        assert!((second.v - 0.75).abs() < 1.0e-12);
+        // A quarter turn counterclockwise around the normal.
+        assert!(((second.u - first.u).rem_euclid(1.0) - 0.25).abs() < 1.0e-12);
+        // The derivatives point along increasing angle and radius.
+        assert!((first.dpdu.normal() - Vec3::new(0.0, 1.0, 0.0)).len() < 1.0e-12);
+        assert!((first.dpdv.normal() - Vec3::new(1.0, 0.0, 0.0)).len() < 1.0e-12);
+    }
+
+    #[test]
+    fn samples_are_uniform_over_the_area() {
+        let disk = disk();
+        let n = 100;
+        let mut inner = 0;
+        for i in 0..n {
+            for j in 0..n {
+                let (u1, u2) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let rec = disk.sample_surface(u1, u2).unwrap();
+                assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+                assert!(rec.p.z.abs() < 1.0e-12 && rec.p.len() <= 2.0 + 1.0e-12);
+                if rec.p.len() < 1.0 {
+                    inner += 1;
+                }
+            }
+        }
+        // The inner half of the radius covers a quarter of the area.
+        let fraction = inner as f64 / (n * n) as f64;
+        assert!((fraction - 0.25).abs() < 0.01, "{}", fraction);
+    }
+}
@@ -0,0 +1,260 @@
+//! Piecewise constant and discrete distributions for importance sampling tabulated functions.
+
+/// A piecewise constant 1D distribution over [0, 1), proportional to a non-negative function
+/// tabulated at evenly spaced intervals.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    function: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Creates a distribution proportional to `function`, with one entry per interval.
+    ///
+    /// A function that is zero everywhere results in a uniform distribution.
+    pub fn new(function: Vec<f64>) -> Self {
+        let n = function.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, &f) in function.iter().enumerate() {
+            cdf.push(cdf[i] + f.max(0.0) / n as f64);
+        }
+
+        let integral = cdf[n];
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            cdf.iter_mut()
+                .enumerate()
+                .for_each(|(i, c)| *c = i as f64 / n as f64);
+        }
+
+        Distribution1D {
+            function,
+            cdf,
+            integral,
+        }
+    }
+
+    /// The number of intervals.
+    pub fn len(&self) -> usize {
+        self.function.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.function.is_empty()
+    }
+
+    /// The integral of the tabulated function over [0, 1).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps a uniform sample `u` in [0, 1) to a point in [0, 1), returning the point, its
+    /// density and the index of the interval containing it.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // The last interval whose cumulative probability is at most `u`.
+        let offset = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.len() - 1);
+
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+
+        let x = ((offset as f64 + du) / self.len() as f64).min(1.0 - f64::EPSILON);
+        (x, self.pdf(x), offset)
+    }
+
+    /// The density of [`Distribution1D::sample_continuous`] returning `x`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        let n = self.len();
+        let offset = ((x * n as f64) as usize).min(n - 1);
+        if self.integral > 0.0 {
+            self.function[offset].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A piecewise constant 2D distribution over [0, 1)², sampled by first choosing a row from the
+/// marginal distribution and then a column from the conditional distribution of that row.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditionals: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// Creates a distribution proportional to `function`, given in row-major order with rows
+    /// of `width` entries.
+    pub fn new(function: &[f64], width: usize) -> Self {
+        let conditionals: Vec<_> = function
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditionals.iter().map(|c| c.integral()).collect());
+        Distribution2D {
+            conditionals,
+            marginal,
+        }
+    }
+
+    /// Maps uniform samples to a point `(x, y)` in [0, 1)², where `y` selects the row, and
+    /// returns it along with its density.
+    pub fn sample_continuous(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u2);
+        let (x, pdf_x, _) = self.conditionals[row].sample_continuous(u1);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    /// The density of [`Distribution2D::sample_continuous`] returning `(x, y)`.
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let rows = self.conditionals.len();
+        let row = ((y * rows as f64) as usize).min(rows - 1);
+        let conditional = &self.conditionals[row];
+        let columns = conditional.len();
+        let column = ((x * columns as f64) as usize).min(columns - 1);
+        if self.marginal.integral() > 0.0 {
+            conditional.function[column].max(0.0) / self.marginal.integral()
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A discrete distribution over indices proportional to their weights, sampled in constant time
+/// with Walker's alias method.
+#[derive(Debug, Clone)]
+pub struct AliasTable {
+    /// The probability of keeping each bin, and the index it defers to otherwise.
+    bins: Vec<(f64, usize)>,
+    pmf: Vec<f64>,
+}
+
+impl AliasTable {
+    /// Creates a distribution proportional to `weights`.
+    ///
+    /// Weights that are all zero result in a uniform distribution.
+    pub fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let total: f64 = weights.iter().map(|w| w.max(0.0)).sum();
+        let pmf: Vec<f64> = if total > 0.0 {
+            weights.iter().map(|w| w.max(0.0) / total).collect()
+        } else {
+            vec![1.0 / n as f64; n]
+        };
+
+        // Pair bins below the average with bins above it, following Vose.
+        let mut bins = vec![(1.0, 0); n];
+        let mut scaled: Vec<f64> = pmf.iter().map(|p| p * n as f64).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&i| scaled[i] < 1.0);
+        while let (Some(&s), Some(&l)) = (small.last(), large.last()) {
+            small.pop();
+            bins[s] = (scaled[s], l);
+            scaled[l] -= 1.0 - scaled[s];
+            if scaled[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+        // The remaining bins are full up to rounding errors.
+        for i in small.into_iter().chain(large) {
+            bins[i] = (1.0, i);
+        }
+
+        AliasTable { bins, pmf }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bins.is_empty()
+    }
+
+    /// Maps a uniform sample `u` in [0, 1) to an index, returning it along with its probability.
+    pub fn sample(&self, u: f64) -> (usize, f64) {
+        let scaled = u * self.len() as f64;
+        let bin = (scaled as usize).min(self.len() - 1);
+        let (keep, alias) = self.bins[bin];
+        let index = if scaled - (bin as f64) < keep {
+            bin
+        } else {
+            alias
+        };
+        (index, self.pmf[index])
+    }
+
+    /// The probability of [`AliasTable::sample`] returning `index`.
+    pub fn pmf(&self, index: usize) -> f64 {
+        self.pmf[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_follow_the_function() {
+        let distribution = Distribution1D::new(vec![1.0, 3.0, 0.0, 4.0]);
+        assert_eq!(distribution.integral(), 2.0);
+
+        let n = 10000;
+        let mut counts = [0usize; 4];
+        for i in 0..n {
+            let (x, pdf, offset) = distribution.sample_continuous((i as f64 + 0.5) / n as f64);
+            assert!((0.0..1.0).contains(&x));
+            assert_eq!(offset, (x * 4.0) as usize);
+            assert!(pdf > 0.0);
+            counts[offset] += 1;
+        }
+        assert_eq!(counts[2], 0);
+        for (count, expected) in counts.iter().zip(&[0.125, 0.375, 0.0, 0.5]) {
+            assert!((*count as f64 / n as f64 - expected).abs() < 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn density_integrates_to_one() {
+        let distribution = Distribution2D::new(&[0.0, 1.0, 2.0, 5.0, 0.5, 0.5], 3);
+        let n = 60;
+        let mut total = 0.0;
+        for j in 0..n {
+            for i in 0..n {
+                let (x, y) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                total += distribution.pdf(x, y) / (n * n) as f64;
+            }
+        }
+        assert!((total - 1.0).abs() < 1.0e-9);
+
+        let ((x, y), pdf) = distribution.sample_continuous(0.9, 0.1);
+        assert!((pdf - distribution.pdf(x, y)).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn alias_table_matches_weights() {
+        let weights = [2.0, 0.0, 5.0, 1.0, 0.5, 1.5];
+        let table = AliasTable::new(&weights);
+        let n = 100000;
+        let mut counts = [0usize; 6];
+        for i in 0..n {
+            let (index, pmf) = table.sample((i as f64 + 0.5) / n as f64);
+            assert_eq!(pmf, table.pmf(index));
+            counts[index] += 1;
+        }
+        for (count, weight) in counts.iter().zip(&weights) {
+            assert!((*count as f64 / n as f64 - weight / 10.0).abs() < 1.0e-3);
+        }
+    }
+}
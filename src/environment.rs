@@ -0,0 +1,248 @@
+//! [`Environment`]s that light the scene from infinitely far away.
+use crate::distribution::Distribution2D;
+use crate::image::Image;
+use crate::prelude::*;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// The radiance arriving from infinitely far away along rays that escape the scene.
+///
+/// A plain [`Rgb`] is a constant environment.
+pub trait Environment: Debug + Send + Sync {
+    /// Returns the radiance arriving from the unit `direction`.
+    fn radiance(&self, direction: Vec3) -> Rgb;
+
+    /// Samples a unit direction towards the environment from the uniform samples `u1` and `u2`,
+    /// returning it along with its solid angle density.
+    ///
+    /// Defaults to sampling the sphere of directions uniformly.
+    fn sample(&self, u1: f64, u2: f64) -> (Vec3, f64) {
+        let z = 1.0 - 2.0 * u1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        (Vec3::new(r * phi.cos(), r * phi.sin(), z), 1.0 / (4.0 * PI))
+    }
+
+    /// The density of [`Environment::sample`] returning the unit `direction`.
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+impl Environment for Rgb {
+    fn radiance(&self, _direction: Vec3) -> Rgb {
+        *self
+    }
+}
+
+/// A sky blending linearly from the `horizon` color below to the `zenith` color above.
+#[derive(Debug, Clone, Copy)]
+pub struct GradientSky {
+    pub horizon: Rgb,
+    pub zenith: Rgb,
+}
+
+impl GradientSky {
+    pub fn new(horizon: Rgb, zenith: Rgb) -> Self {
+        GradientSky { horizon, zenith }
+    }
+}
+
+impl Default for GradientSky {
+    /// The white-to-blue sky of "Ray Tracing in One Weekend".
+    fn default() -> Self {
+        GradientSky::new(Rgb::new(1.0, 1.0, 1.0), Rgb::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for GradientSky {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        // A trick that converts range from [-1, 1) to [0, 1)
+        let t = 0.5 * (direction.y + 1.0);
+        (1.0 - t) * self.horizon + t * self.zenith
+    }
+}
+
+/// An image-based environment stored in the equirectangular (latitude-longitude) projection.
+///
+/// The top row of the image is straight up, and the center of the image looks down the `-z`
+/// axis. Directions are sampled proportionally to the luminance of the pixels, so that small
+/// bright features such as the sun are found by light sampling.
+#[derive(Debug, Clone)]
+pub struct EquirectMap {
+    image: Image,
+    distribution: Distribution2D,
+    /// The rotation about the `y` axis in radians.
+    rotation: f64,
+    intensity: f64,
+}
+
+impl EquirectMap {
+    /// Creates an environment from `image`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    pub fn new(image: Image) -> Self {
+        let (width, height) = (image.width(), image.height());
+        assert!(width > 0 && height > 0, "environment map must not be empty");
+
+        // Pixels near the poles cover a smaller solid angle.
+        let function: Vec<f64> = image
+            .pixels()
+            .iter()
+            .enumerate()
+            .map(|(index, pixel)| {
+                let theta = PI * ((index / width) as f64 + 0.5) / height as f64;
+                pixel.luminance().max(0.0) * theta.sin()
+            })
+            .collect();
+
+        EquirectMap {
+            distribution: Distribution2D::new(&function, width),
+            image,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    /// Reads a Radiance HDR (`.hdr`) image from `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let image = Image::read_hdr(&mut reader)?;
+        if image.width() == 0 || image.height() == 0 {
+            return Err(ErrorKind::ReadImage(
+                "environment map must not be empty".to_string(),
+            ));
+        }
+        Ok(EquirectMap::new(image))
+    }
+
+    /// Rotates the environment counterclockwise about the `y` axis by `degrees`.
+    pub fn with_rotation(self, degrees: f64) -> Self {
+        EquirectMap {
+            rotation: degrees.to_radians(),
+            ..self
+        }
+    }
+
+    /// Scales the radiance of the environment by `intensity`.
+    pub fn with_intensity(self, intensity: f64) -> Self {
+        EquirectMap { intensity, ..self }
+    }
+
+    /// Maps a world space direction to image coordinates in [0, 1)², along with the sine of
+    /// its polar angle.
+    fn direction_to_uv(&self, direction: Vec3) -> ((f64, f64), f64) {
+        let direction = rotate_y(direction, -self.rotation);
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = direction.x.atan2(-direction.z);
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = (theta / PI).min(1.0 - f64::EPSILON);
+        ((u, v), theta.sin())
+    }
+}
+
+impl Environment for EquirectMap {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        let ((u, v), _) = self.direction_to_uv(direction);
+        let i = ((u * self.image.width() as f64) as usize).min(self.image.width() - 1);
+        let j = ((v * self.image.height() as f64) as usize).min(self.image.height() - 1);
+        self.intensity * self.image.get(i, j)
+    }
+
+    fn sample(&self, u1: f64, u2: f64) -> (Vec3, f64) {
+        let ((u, v), pdf) = self.distribution.sample_continuous(u1, u2);
+        let theta = PI * v;
+        let phi = 2.0 * PI * (u - 0.5);
+        let sin_theta = theta.sin();
+        let direction = Vec3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos());
+        let direction = rotate_y(direction, self.rotation);
+
+        // Converts the density over the image to a density over solid angle.
+        if sin_theta <= 0.0 {
+            return (direction, 0.0);
+        }
+        (direction, pdf / (2.0 * PI * PI * sin_theta))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let ((u, v), sin_theta) = self.direction_to_uv(direction);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Rotates `v` counterclockwise about the `y` axis by `angle` radians.
+fn rotate_y(v: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> EquirectMap {
+        let mut image = Image::new(16, 8);
+        image
+            .pixels_mut()
+            .iter_mut()
+            .for_each(|p| *p = Rgb::from(0.1));
+        image.set(11, 2, Rgb::from(50.0));
+        EquirectMap::new(image).with_rotation(30.0)
+    }
+
+    #[test]
+    fn samples_match_density() {
+        let map = map();
+        let mut bright = 0;
+        let n = 2000;
+        for i in 0..n {
+            let u1 = (i as f64 + 0.5) / n as f64;
+            let u2 = ((i * 7919) % n) as f64 / n as f64;
+            let (direction, pdf) = map.sample(u1, u2);
+            assert!((direction.len() - 1.0).abs() < 1.0e-9);
+            if pdf > 0.0 {
+                assert!((pdf - map.pdf(direction)).abs() < 1.0e-6 * pdf);
+            }
+            if map.radiance(direction).r > 1.0 {
+                bright += 1;
+            }
+        }
+        // The bright pixel holds most of the luminance, so most samples land on it.
+        assert!(bright > n / 2, "{}", bright);
+    }
+
+    #[test]
+    fn density_integrates_to_one() {
+        let map = map();
+        let n = 400;
+        let mut total = 0.0;
+        for j in 0..n {
+            for i in 0..n {
+                let (u1, u2) = ((j as f64 + 0.5) / n as f64, (i as f64 + 0.5) / n as f64);
+                let (direction, _) = Rgb::default().sample(u1, u2);
+                total += map.pdf(direction) * 4.0 * PI / (n * n) as f64;
+            }
+        }
+        assert!((total - 1.0).abs() < 0.02, "{}", total);
+    }
+
+    #[test]
+    fn opening_empty_or_huge_images_fails() {
+        let path = std::env::temp_dir().join(format!("empty-{}.hdr", std::process::id()));
+        std::fs::write(&path, "#?RADIANCE\n\n-Y 0 +X 16\n").unwrap();
+        let result = EquirectMap::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(ErrorKind::ReadImage(_))));
+
+        let mut header: &[u8] = b"#?RADIANCE\n\n-Y 4000000000 +X 4000000000\n";
+        let result = Image::read_hdr(&mut header);
+        assert!(matches!(result, Err(ErrorKind::ReadImage(_))));
+    }
+}
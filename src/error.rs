@@ -16,6 +16,14 @@ pub enum ErrorKind {
     #[error("cannot write pixel colors into `{0}`")]
     WriteColor(String),
 
+    /// An image file is malformed or uses an unsupported encoding.
+    #[error("cannot read image: {0}")]
+    ReadImage(String),
+
+    /// A mesh file is malformed.
+    #[error("cannot read mesh: {0}")]
+    ReadMesh(String),
+
     /// Represents an [`I/O error`].
     ///
     /// [`I/O error`]: std::io::Error
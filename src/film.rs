@@ -0,0 +1,193 @@
+//! The [`Film`] that accumulates radiance samples into pixels.
+use crate::filter::Filter;
+use crate::image::Image;
+use crate::prelude::*;
+use crate::tonemap::ToneMapping;
+use std::fmt;
+use std::io;
+
+/// A weighted sum of the samples that contribute to a pixel.
+#[derive(Debug, Clone, Copy, Default)]
+struct Pixel {
+    color_sum: Rgb,
+    weight_sum: f64,
+    /// The sum of the splatted colors, which are not normalized by filter weights.
+    splat_sum: Rgb,
+}
+
+/// A framebuffer that reconstructs pixels from radiance samples using a [`Filter`].
+///
+/// Samples are given in continuous raster coordinates, where `(0, 0)` is the top-left corner
+/// of the image and pixel `(i, j)` covers `[i, i + 1) x [j, j + 1)`. Each sample is splatted to
+/// every pixel whose center lies within the filter radius, weighted by the filter.
+#[derive(Debug)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    filter: Box<dyn Filter>,
+    pixels: Vec<Pixel>,
+    splat_scale: f64,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: impl Filter + 'static) -> Self {
+        Film {
+            width,
+            height,
+            filter: Box::new(filter),
+            pixels: vec![Pixel::default(); width * height],
+            splat_scale: 1.0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Adds a radiance sample taken at raster position `(x, y)`.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Rgb) {
+        let radius = self.filter.radius();
+
+        // Pixel centers are at half-integer coordinates.
+        let x0 = (x - 0.5 - radius).ceil().max(0.0) as usize;
+        let y0 = (y - 0.5 - radius).ceil().max(0.0) as usize;
+        let x1 = ((x - 0.5 + radius).floor() as i64).min(self.width as i64 - 1);
+        let y1 = ((y - 0.5 + radius).floor() as i64).min(self.height as i64 - 1);
+
+        for j in y0 as i64..=y1 {
+            for i in x0 as i64..=x1 {
+                let weight = self.filter.eval(x - (i as f64 + 0.5), y - (j as f64 + 0.5));
+                if weight != 0.0 {
+                    let pixel = &mut self.pixels[j as usize * self.width + i as usize];
+                    pixel.color_sum += weight * color;
+                    pixel.weight_sum += weight;
+                }
+            }
+        }
+    }
+
+    /// Adds a contribution to the pixel containing raster position `(x, y)`, such as light
+    /// traced from the lights towards the camera.
+    ///
+    /// Unlike samples, splats are summed without normalization and scaled by the splat scale
+    /// when pixels are resolved.
+    pub fn add_splat(&mut self, x: f64, y: f64, color: Rgb) {
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return;
+        }
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        pixel.splat_sum += color;
+    }
+
+    /// Sets the scale of the splats, usually one over the number of samples per pixel.
+    pub fn set_splat_scale(&mut self, scale: f64) {
+        self.splat_scale = scale;
+    }
+
+    /// Returns the reconstructed color of pixel `(i, j)`.
+    pub fn pixel(&self, i: usize, j: usize) -> Rgb {
+        let pixel = self.pixels[j * self.width + i];
+        let splat = self.splat_scale * pixel.splat_sum;
+        if pixel.weight_sum > 0.0 {
+            // Filters with negative lobes can produce slightly negative values.
+            (pixel.color_sum / pixel.weight_sum + splat).map(|c| c.max(0.0))
+        } else {
+            splat
+        }
+    }
+
+    /// Resolves every pixel into a floating point image.
+    pub fn to_image(&self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for j in 0..self.height {
+            for i in 0..self.width {
+                image.set(i, j, self.pixel(i, j));
+            }
+        }
+        image
+    }
+
+    /// Tone maps the reconstructed image and writes it into `stream` in the plain PPM format.
+    pub fn write<W: io::Write + fmt::Debug>(
+        &self,
+        stream: &mut W,
+        tone_mapping: &ToneMapping,
+    ) -> Result<()> {
+        self.to_image().write_ppm(stream, tone_mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::*;
+
+    #[test]
+    fn filter_weights_are_normalized() {
+        let filters: Vec<Box<dyn Filter>> = vec![
+            Box::new(BoxFilter::default()),
+            Box::new(TentFilter::default()),
+            Box::new(GaussianFilter::default()),
+            Box::new(MitchellFilter::default()),
+        ];
+
+        // A constant signal comes out unchanged whatever the weights, including at the edges
+        // where parts of the filter fall outside the image.
+        let color = Rgb::new(0.25, 0.5, 2.0);
+        for filter in filters {
+            let mut film = Film {
+                filter,
+                ..Film::new(4, 3, BoxFilter::default())
+            };
+            let n = 8;
+            for y in 0..3 * n {
+                for x in 0..4 * n {
+                    let (x, y) = ((x as f64 + 0.5) / n as f64, (y as f64 + 0.5) / n as f64);
+                    film.add_sample(x, y, color);
+                }
+            }
+            for j in 0..3 {
+                for i in 0..4 {
+                    let pixel = film.pixel(i, j);
+                    assert!((pixel - color).len() < 1.0e-9, "{:?}", film.filter);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_only_reach_pixels_within_the_radius() {
+        let mut film = Film::new(3, 1, BoxFilter::default());
+        film.add_sample(1.5, 0.5, Rgb::new(1.0, 1.0, 1.0));
+        assert_eq!(film.pixel(0, 0), Rgb::default());
+        assert_eq!(film.pixel(1, 0), Rgb::new(1.0, 1.0, 1.0));
+        assert_eq!(film.pixel(2, 0), Rgb::default());
+
+        // Samples are averaged by their weights.
+        film.add_sample(1.25, 0.75, Rgb::new(0.0, 0.0, 3.0));
+        assert_eq!(film.pixel(1, 0), Rgb::new(0.5, 0.5, 2.0));
+    }
+
+    #[test]
+    fn splats_accumulate_without_normalization() {
+        let mut film = Film::new(2, 2, BoxFilter::default());
+        film.add_splat(0.5, 1.5, Rgb::new(1.0, 2.0, 3.0));
+        film.add_splat(0.9, 1.1, Rgb::new(1.0, 0.0, 1.0));
+        // Splats outside the image are dropped.
+        film.add_splat(-0.5, 1.5, Rgb::new(9.0, 9.0, 9.0));
+        film.add_splat(2.0, 1.5, Rgb::new(9.0, 9.0, 9.0));
+        assert_eq!(film.pixel(0, 1), Rgb::new(2.0, 2.0, 4.0));
+
+        film.set_splat_scale(0.5);
+        assert_eq!(film.pixel(0, 1), Rgb::new(1.0, 1.0, 2.0));
+        assert_eq!(film.pixel(1, 1), Rgb::default());
+
+        // Splats add to the filtered samples.
+        film.add_sample(0.5, 1.5, Rgb::new(1.0, 1.0, 1.0));
+        assert_eq!(film.pixel(0, 1), Rgb::new(2.0, 2.0, 3.0));
+    }
+}
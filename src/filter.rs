@@ -0,0 +1,207 @@
+//! Pixel reconstruction [`Filter`]s.
+//!
+//! A filter decides how much a sample taken at some offset from a pixel center contributes to
+//! that pixel. Filters are evaluated by [`Film`] when splatting samples.
+//!
+//! [`Film`]: crate::film::Film
+use std::fmt::Debug;
+
+/// A 2D reconstruction filter centered at the origin.
+pub trait Filter: Debug + Send + Sync {
+    /// The radius beyond which the filter evaluates to zero, in pixels.
+    fn radius(&self) -> f64;
+
+    /// Evaluates the filter at offset `(x, y)` from the pixel center.
+    fn eval(&self, x: f64, y: f64) -> f64;
+}
+
+/// A box filter that weights every sample within its radius equally.
+///
+/// With a radius of `0.5` this reproduces plain per-pixel averaging.
+#[derive(Debug, Clone, Copy)]
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> Self {
+        BoxFilter { radius }
+    }
+}
+
+impl Default for BoxFilter {
+    fn default() -> Self {
+        BoxFilter::new(0.5)
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        if x.abs() <= self.radius && y.abs() <= self.radius {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A tent (triangle) filter that falls off linearly to zero at its radius.
+#[derive(Debug, Clone, Copy)]
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> Self {
+        TentFilter { radius }
+    }
+}
+
+impl Default for TentFilter {
+    fn default() -> Self {
+        TentFilter::new(1.0)
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+/// A truncated Gaussian filter.
+///
+/// The Gaussian is shifted down by its value at the radius so that it reaches zero there.
+#[derive(Debug, Clone, Copy)]
+pub struct GaussianFilter {
+    radius: f64,
+    alpha: f64,
+    edge: f64,
+}
+
+impl GaussianFilter {
+    /// Creates a Gaussian filter of `radius` with falloff rate `alpha`; larger values of `alpha`
+    /// give a narrower (sharper) filter.
+    pub fn new(radius: f64, alpha: f64) -> Self {
+        GaussianFilter {
+            radius,
+            alpha,
+            edge: (-alpha * radius * radius).exp(),
+        }
+    }
+
+    fn gaussian(&self, d: f64) -> f64 {
+        ((-self.alpha * d * d).exp() - self.edge).max(0.0)
+    }
+}
+
+impl Default for GaussianFilter {
+    fn default() -> Self {
+        GaussianFilter::new(1.5, 2.0)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        self.gaussian(x) * self.gaussian(y)
+    }
+}
+
+/// The Mitchell-Netravali cubic filter.
+///
+/// `b` and `c` control the trade-off between blurring and ringing; Mitchell and Netravali
+/// recommend `b + 2c = 1`. Note that this filter has negative lobes.
+#[derive(Debug, Clone, Copy)]
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> Self {
+        MitchellFilter { radius, b, c }
+    }
+
+    /// Evaluates the 1D cubic, where `x` is normalized to [-1, 1] over the filter radius.
+    fn mitchell_1d(&self, x: f64) -> f64 {
+        let (b, c) = (self.b, self.c);
+        let x = (2.0 * x).abs();
+        let v = if x > 2.0 {
+            0.0
+        } else if x > 1.0 {
+            (-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c)
+        } else {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b)
+        };
+        v / 6.0
+    }
+}
+
+impl Default for MitchellFilter {
+    fn default() -> Self {
+        MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0)
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn eval(&self, x: f64, y: f64) -> f64 {
+        self.mitchell_1d(x / self.radius) * self.mitchell_1d(y / self.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_vanish_outside_radius() {
+        let filters: Vec<Box<dyn Filter>> = vec![
+            Box::new(BoxFilter::default()),
+            Box::new(TentFilter::default()),
+            Box::new(GaussianFilter::default()),
+            Box::new(MitchellFilter::default()),
+        ];
+
+        for filter in filters {
+            let r = filter.radius();
+            assert!(filter.eval(0.0, 0.0) > 0.0);
+            assert_eq!(filter.eval(r + 0.01, 0.0), 0.0);
+            assert_eq!(filter.eval(0.0, -r - 0.01), 0.0);
+        }
+    }
+
+    #[test]
+    fn mitchell_is_partition_of_unity() {
+        // The Mitchell-Netravali cubics with `b + 2c = 1` reproduce constant signals exactly
+        // when sampled at unit spacing.
+        let filter = MitchellFilter::default();
+        for &offset in &[0.0, 0.25, 0.5, 0.8] {
+            let sum: f64 = (-3..=3)
+                .map(|i| filter.mitchell_1d((i as f64 + offset) / filter.radius()))
+                .sum();
+            assert!((sum - 1.0).abs() < 1e-9, "sum at {} was {}", offset, sum);
+        }
+    }
+}
@@ -1,19 +1,90 @@
 //! [`Hittable`]s and [`HitRecord`] type.
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
 use crate::prelude::*;
+use std::sync::{Arc, OnceLock};
 
 /// A hittable object that a ray can intersect with.
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<HitRecord<'_>>;
+
+    /// Returns a box enclosing the object, or `None` if it is unbounded.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// The surface area of the object, which is zero for objects that cannot be sampled.
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    /// Samples a point uniformly over the surface from the uniform samples `u1` and `u2`.
+    ///
+    /// The returned record has an outward facing normal and is marked as front facing. Objects
+    /// that do not support sampling return `None`, the default.
+    fn sample_surface(&self, _u1: f64, _u2: f64) -> Option<HitRecord<'_>> {
+        None
+    }
+
+    /// Finds every hit of `ray` between `min_t` and `max_t`, in order.
+    ///
+    /// Entering and leaving a closed object are told apart by `front_face`, so consecutive
+    /// hits delimit the intervals of the ray inside it. The default repeatedly looks for the
+    /// nearest hit just past the previous one, up to a bounded number of hits.
+    fn hits(&self, ray: &Ray, min_t: f64, max_t: f64) -> Vec<HitRecord<'_>> {
+        // Bounds the number of hits in case the search gets stuck, e.g. due to round-off.
+        const MAX_HITS: usize = 64;
+
+        let mut hits = Vec::new();
+        let mut t = min_t;
+        while hits.len() < MAX_HITS {
+            match self.hit(ray, t, max_t) {
+                Some(rec) => {
+                    t = rec.t + 1.0e-6;
+                    hits.push(rec);
+                }
+                None => break,
+            }
+        }
+        hits
+    }
+
+    /// Estimates the fraction of the light travelling along `ray` between `min_t` and `max_t`
+    /// that passes through the object.
+    ///
+    /// Defaults to blocking the light if the ray hits the object. Participating media return
+    /// the fraction that is neither absorbed nor scattered away.
+    fn transmittance(&self, ray: &Ray, min_t: f64, max_t: f64) -> f64 {
+        if self.hit(ray, min_t, max_t).is_some() {
+            0.0
+        } else {
+            1.0
+        }
+    }
 }
 
 /// A record that contains the information of a hit.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HitRecord<'world> {
     pub p: Point3,
     pub t: f64,
+    /// The normal materials shade with, which faces the incoming ray.
     pub normal: Vec3,
+    /// The normal of the underlying geometry, on the same side as `normal`. It differs from
+    /// `normal` on surfaces with interpolated normals, and is the one to use when converting
+    /// densities between area and solid angle.
+    pub geometric_normal: Vec3,
+    /// Surface coordinates of the hit point, used for texture lookups.
+    pub u: f64,
+    pub v: f64,
+    /// Partial derivatives of the hit point with respect to the surface coordinates, spanning
+    /// the tangent plane. They are zero for surfaces without a parameterization.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub material: Option<&'world dyn Material>,
     pub front_face: bool,
+    /// The index of the hit object within the top-level [`HittableList`].
+    pub object_id: usize,
 }
 
 impl<'world> HitRecord<'world> {
@@ -22,9 +93,50 @@ impl<'world> HitRecord<'world> {
             p,
             t,
             normal: p,
+            geometric_normal: p,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default(),
             material,
             front_face: false,
+            object_id: 0,
+        }
+    }
+
+    /// Checks whether the hit lies on a part of the surface cut out by the opacity of its
+    /// material, in which case the hit must be discarded.
+    ///
+    /// Partial opacity is resolved by comparing it with a hash of the hit rather than a random
+    /// number, so tracing the same ray again gives the same result and no samples are used up.
+    pub fn is_cut_out(&self) -> bool {
+        match self.material {
+            Some(material) => {
+                let opacity = material.opacity(self);
+                opacity < 1.0 && self.cut_out_threshold() >= opacity
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a value uniformly distributed in [0, 1) over the surface, which only depends on
+    /// the position and surface coordinates of the hit.
+    fn cut_out_threshold(&self) -> f64 {
+        let mut h = 0u64;
+        for x in &[self.p.x, self.p.y, self.p.z, self.u, self.v] {
+            h = (h ^ x.to_bits()).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            h ^= h >> 32;
         }
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+        (h >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Whether the hit lies on a surface, rather than inside a participating medium where the
+    /// normal is zero.
+    pub fn is_on_surface(&self) -> bool {
+        self.normal != Vec3::default()
     }
 
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
@@ -33,43 +145,114 @@ impl<'world> HitRecord<'world> {
             outward_normal
         } else {
             -outward_normal
-        }
+        };
+        self.geometric_normal = self.normal;
     }
 }
 
 /// A list of hittable objects.
+///
+/// Rays are traced through a [`Bvh`] over the objects, which is built on first use.
 pub struct HittableList {
-    objects: Vec<Box<dyn Hittable + Send + Sync>>,
+    objects: Vec<Arc<dyn Hittable + Send + Sync>>,
+    bvh: OnceLock<Bvh>,
 }
 
 impl HittableList {
     pub fn new() -> Self {
         HittableList {
             objects: Vec::new(),
+            bvh: OnceLock::new(),
         }
     }
 
     pub fn add(&mut self, object: impl Hittable + Sync + Send + 'static) {
-        self.objects.push(Box::new(object));
+        self.objects.push(Arc::new(object));
+        self.bvh = OnceLock::new();
+    }
+
+    /// The objects in the list, indexed by the `object_id` of their hits.
+    pub fn objects(&self) -> &[Arc<dyn Hittable + Send + Sync>] {
+        &self.objects
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.bvh = OnceLock::new();
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            let boxes: Vec<_> = self.objects.iter().map(|o| o.bounding_box()).collect();
+            Bvh::new(&boxes)
+        })
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut record = None;
         let mut closest_so_far = t_max;
 
-        for object in &self.objects {
-            if let Some(rec) = object.hit(ray, t_min, closest_so_far) {
-                closest_so_far = rec.t;
-                record.replace(rec);
+        self.bvh()
+            .traverse(ray, t_min, &mut closest_so_far, |id, closest_so_far| {
+                if let Some(mut rec) = self.objects[id].hit(ray, t_min, *closest_so_far) {
+                    *closest_so_far = rec.t;
+                    rec.object_id = id;
+                    record.replace(rec);
+                }
+                true
+            });
+
+        record
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, mut t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        self.bvh().traverse(ray, t_min, &mut t_max, |id, t_max| {
+            transmittance *= self.objects[id].transmittance(ray, t_min, *t_max);
+            transmittance > 0.0
+        });
+        transmittance.max(0.0)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|object| object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.union(b?)))
+    }
+
+    fn area(&self) -> f64 {
+        self.objects.iter().map(|object| object.area()).sum()
+    }
+
+    /// Picks an object proportionally to its area with `u1`, which is then reused to sample
+    /// a point uniformly on it.
+    fn sample_surface(&self, u1: f64, u2: f64) -> Option<HitRecord<'_>> {
+        let total = self.area();
+        let mut target = u1 * total;
+        let mut last = None;
+        for (id, object) in self.objects.iter().enumerate() {
+            let area = object.area();
+            if area <= 0.0 {
+                continue;
             }
+            last = Some((id, object, target / area));
+            if target < area {
+                break;
+            }
+            target -= area;
         }
 
-        record
+        let (id, object, u1) = last?;
+        let mut rec = object.sample_surface(u1.clamp(0.0, 1.0), u2)?;
+        rec.object_id = id;
+        Some(rec)
     }
 }
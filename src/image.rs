@@ -0,0 +1,216 @@
+//! Floating point [`Image`] buffers.
+use crate::prelude::*;
+use crate::tonemap::ToneMapping;
+use std::fmt;
+use std::io;
+
+/// The largest Radiance HDR image read, in pixels.
+const MAX_HDR_PIXELS: usize = 1 << 28;
+
+/// A linear, floating point RGB image stored in row-major order from the top-left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    /// Creates a black image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Rgb::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> Rgb {
+        self.pixels[j * self.width + i]
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, color: Rgb) {
+        self.pixels[j * self.width + i] = color;
+    }
+
+    pub fn pixels(&self) -> &[Rgb] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Rgb] {
+        &mut self.pixels
+    }
+
+    /// Tone maps the image and writes it into `stream` in the plain PPM format.
+    pub fn write_ppm<W: io::Write + fmt::Debug>(
+        &self,
+        stream: &mut W,
+        tone_mapping: &ToneMapping,
+    ) -> Result<()> {
+        writeln!(stream, "P3\n{} {}\n255", self.width, self.height)
+            .map_err(|_| ErrorKind::WriteColor(format!("{:?}", stream)))?;
+
+        for &pixel in &self.pixels {
+            tone_mapping.apply(pixel).write_display(stream)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the raw linear values into `stream` in the Portable Float Map format.
+    ///
+    /// Unlike [`write_ppm`], this preserves values outside [0, 1], which makes it suitable for
+    /// radiance and auxiliary buffers such as depth and position.
+    ///
+    /// [`write_ppm`]: Image::write_ppm
+    pub fn write_pfm<W: io::Write>(&self, stream: &mut W) -> Result<()> {
+        // A negative scale marks the data as little-endian.
+        write!(stream, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        // PFM stores scanlines from bottom to top.
+        for row in self.pixels.chunks(self.width.max(1)).rev() {
+            for pixel in row {
+                for &c in &[pixel.r, pixel.g, pixel.b] {
+                    stream.write_all(&(c as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads an image in the Radiance HDR (`.hdr`) format from `stream`, e.g. an
+    /// equirectangular environment map.
+    ///
+    /// Both flat and run-length encoded scanlines are supported, but only the standard
+    /// `-Y height +X width` orientation is.
+    pub fn read_hdr<R: io::BufRead>(stream: &mut R) -> Result<Image> {
+        let invalid = |message: &str| ErrorKind::ReadImage(message.to_string());
+
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+        if !line.starts_with("#?") {
+            return Err(invalid("missing Radiance HDR signature"));
+        }
+
+        // The header ends with an empty line.
+        loop {
+            line.clear();
+            if stream.read_line(&mut line)? == 0 {
+                return Err(invalid("unexpected end of header"));
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid("unsupported pixel format"));
+                }
+            }
+        }
+
+        line.clear();
+        stream.read_line(&mut line)?;
+        let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (height.parse().ok(), width.parse().ok()),
+            _ => (None, None),
+        };
+        let (width, height): (usize, usize) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(invalid("unsupported resolution line")),
+        };
+        // Corrupt headers must not make us allocate unbounded amounts of memory.
+        if width
+            .checked_mul(height)
+            .is_none_or(|pixels| pixels > MAX_HDR_PIXELS)
+        {
+            return Err(invalid("resolution too large"));
+        }
+
+        let mut image = Image::new(width, height);
+        let mut scanline = vec![[0u8; 4]; width];
+        for j in 0..height {
+            Self::read_hdr_scanline(stream, &mut scanline)?;
+            for (i, rgbe) in scanline.iter().enumerate() {
+                let color = if rgbe[3] == 0 {
+                    Rgb::default()
+                } else {
+                    let scale = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+                    Rgb::new(
+                        (rgbe[0] as f64 + 0.5) * scale,
+                        (rgbe[1] as f64 + 0.5) * scale,
+                        (rgbe[2] as f64 + 0.5) * scale,
+                    )
+                };
+                image.set(i, j, color);
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Reads one scanline of RGBE pixels, decoding the run-length encoding if present.
+    fn read_hdr_scanline<R: io::Read>(stream: &mut R, scanline: &mut [[u8; 4]]) -> Result<()> {
+        let width = scanline.len();
+        if width == 0 {
+            return Ok(());
+        }
+
+        let mut first = [0u8; 4];
+        stream.read_exact(&mut first)?;
+        let run_length_encoded =
+            (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] & 0x80 == 0;
+        if !run_length_encoded {
+            scanline[0] = first;
+            for pixel in &mut scanline[1..] {
+                stream.read_exact(pixel)?;
+            }
+            return Ok(());
+        }
+
+        if ((first[2] as usize) << 8 | first[3] as usize) != width {
+            return Err(ErrorKind::ReadImage("scanline width mismatch".to_string()));
+        }
+
+        // Each channel is encoded separately as a sequence of runs and literal spans.
+        let mut byte = [0u8; 1];
+        for channel in 0..4 {
+            let mut i = 0;
+            while i < width {
+                stream.read_exact(&mut byte)?;
+                let (count, run) = if byte[0] > 128 {
+                    (byte[0] as usize - 128, true)
+                } else {
+                    (byte[0] as usize, false)
+                };
+                if count == 0 || i + count > width {
+                    return Err(ErrorKind::ReadImage("bad run length".to_string()));
+                }
+
+                if run {
+                    stream.read_exact(&mut byte)?;
+                    for pixel in &mut scanline[i..i + count] {
+                        pixel[channel] = byte[0];
+                    }
+                } else {
+                    for pixel in &mut scanline[i..i + count] {
+                        stream.read_exact(&mut byte)?;
+                        pixel[channel] = byte[0];
+                    }
+                }
+                i += count;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,579 @@
+//! Integrators that estimate the radiance arriving along camera rays.
+use crate::aabb::Aabb;
+use crate::distribution::AliasTable;
+use crate::environment::Environment;
+use crate::light::*;
+use crate::onb::Onb;
+use crate::prelude::*;
+use crate::spectrum::*;
+use crate::util::random_f64;
+use std::sync::{Arc, OnceLock};
+
+/// Everything that is rendered: the geometry, the environment surrounding it and the lights.
+///
+/// Emissive objects of the world become area lights when the scene is created.
+pub struct Scene {
+    world: HittableList,
+    environment: Box<dyn Environment>,
+    lights: Vec<Arc<dyn Light>>,
+    /// The light of each object in the world, indexed by `object_id`.
+    object_lights: Vec<Option<usize>>,
+    light_sampler: OnceLock<LightSampler>,
+    emitter_sampler: OnceLock<EmitterSampler>,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, environment: impl Environment + 'static) -> Self {
+        let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+        let object_lights = world
+            .objects()
+            .iter()
+            .map(|object| {
+                let light = AreaLight::new(Arc::clone(object))?;
+                lights.push(Arc::new(light));
+                Some(lights.len() - 1)
+            })
+            .collect();
+
+        Scene {
+            world,
+            environment: Box::new(environment),
+            lights,
+            object_lights,
+            light_sampler: OnceLock::new(),
+            emitter_sampler: OnceLock::new(),
+        }
+    }
+
+    pub fn add_light(&mut self, light: impl Light + 'static) {
+        self.lights.push(Arc::new(light));
+        self.light_sampler = OnceLock::new();
+        self.emitter_sampler = OnceLock::new();
+    }
+
+    pub fn world(&self) -> &HittableList {
+        &self.world
+    }
+
+    pub fn environment(&self) -> &dyn Environment {
+        self.environment.as_ref()
+    }
+
+    /// The bounds of the bounded objects of the world, leaving out unbounded ones such as
+    /// [`Plane`](crate::plane::Plane)s, which would otherwise make the whole scene unbounded.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.world
+            .objects()
+            .iter()
+            .filter_map(|object| object.bounding_box())
+            .reduce(Aabb::union)
+    }
+
+    /// The area lights of the emissive objects followed by the lights added to the scene.
+    pub fn lights(&self) -> &[Arc<dyn Light>] {
+        &self.lights
+    }
+
+    /// Returns the index of the area light of the hit object, if it emits light.
+    pub fn object_light(&self, rec: &HitRecord) -> Option<usize> {
+        self.object_lights.get(rec.object_id).copied().flatten()
+    }
+
+    /// The sampler over [`Scene::lights`], built on first use.
+    pub fn light_sampler(&self) -> &LightSampler {
+        self.light_sampler
+            .get_or_init(|| LightSampler::new(&self.lights, self.bounds()))
+    }
+
+    /// The sampler of the emitters that integrators trace light from, built on first use.
+    pub(crate) fn emitter_sampler(&self) -> &EmitterSampler {
+        self.emitter_sampler
+            .get_or_init(|| EmitterSampler::new(self))
+    }
+
+    /// Estimates the fraction of light that travels from `origin` towards `direction` up to
+    /// `max_t` without being blocked, or absorbed or scattered away by a medium.
+    pub fn transmittance(&self, origin: Point3, direction: Vec3, max_t: f64) -> f64 {
+        // 0.001 here is for fixing shadow acne.
+        self.world
+            .transmittance(&Ray::new(origin, direction), 0.001, max_t)
+    }
+}
+
+/// Where light enters the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Emitter {
+    /// A light of the scene, by index.
+    Light(usize),
+    Environment,
+}
+
+/// Picks the emitters that paths traced from the lights start from, proportionally to their
+/// power.
+#[derive(Debug, Clone)]
+pub(crate) struct EmitterSampler {
+    emitters: Vec<Emitter>,
+    distribution: AliasTable,
+    scene_bounds: Option<Aabb>,
+}
+
+impl EmitterSampler {
+    fn new(scene: &Scene) -> Self {
+        let scene_bounds = scene.bounds();
+        let (_, radius) = bounding_sphere(scene_bounds);
+        let disk_area = PI * radius * radius;
+
+        let mut emitters = Vec::new();
+        let mut powers = Vec::new();
+        for (index, light) in scene.lights.iter().enumerate() {
+            emitters.push(Emitter::Light(index));
+            powers.push(match light.bounds() {
+                Some(_) => light.power(),
+                None => disk_area * light.power(),
+            });
+        }
+
+        // Estimate the power entering the scene from the average radiance of the environment.
+        let n = 32;
+        let mut radiance = 0.0;
+        for j in 0..n {
+            for i in 0..2 * n {
+                let z = 1.0 - 2.0 * (j as f64 + 0.5) / n as f64;
+                let r = (1.0 - z * z).sqrt();
+                let phi = 2.0 * PI * (i as f64 + 0.5) / (2 * n) as f64;
+                let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                radiance += scene.environment.radiance(direction).luminance();
+            }
+        }
+        emitters.push(Emitter::Environment);
+        powers.push(PI * disk_area * radiance / (2 * n * n) as f64);
+
+        EmitterSampler {
+            emitters,
+            distribution: AliasTable::new(&powers),
+            scene_bounds,
+        }
+    }
+
+    /// The box enclosing the world, which determines where light from infinitely far away
+    /// enters the scene.
+    pub(crate) fn scene_bounds(&self) -> Option<Aabb> {
+        self.scene_bounds
+    }
+
+    /// Picks an emitter from the uniform sample `u`, returning it along with its probability.
+    pub(crate) fn sample(&self, u: f64) -> Option<(Emitter, f64)> {
+        let (index, pmf) = self.distribution.sample(u);
+        if pmf > 0.0 {
+            Some((self.emitters[index], pmf))
+        } else {
+            None
+        }
+    }
+
+    /// The probability of [`EmitterSampler::sample`] picking `emitter`.
+    pub(crate) fn pmf(&self, emitter: Emitter) -> f64 {
+        match emitter {
+            Emitter::Light(index) => self.distribution.pmf(index),
+            Emitter::Environment => self.distribution.pmf(self.emitters.len() - 1),
+        }
+    }
+
+    /// Samples a ray leaving `emitter`, like [`Light::sample_le`].
+    pub(crate) fn sample_le(
+        &self,
+        scene: &Scene,
+        emitter: Emitter,
+        u_pos: (f64, f64),
+        u_dir: (f64, f64),
+    ) -> Option<EmissionSample> {
+        let (direction, pdf_dir) = match emitter {
+            Emitter::Light(index) => {
+                return scene.lights[index].sample_le(u_pos, u_dir, self.scene_bounds)
+            }
+            Emitter::Environment => scene.environment.sample(u_dir.0, u_dir.1),
+        };
+
+        // Enter the scene through a disk perpendicular to the direction, like distant lights.
+        let (center, radius) = bounding_sphere(self.scene_bounds);
+        let r = u_pos.0.sqrt();
+        let phi = 2.0 * PI * u_pos.1;
+        let disk = Onb::from_w(direction).to_world(Vec3::new(r * phi.cos(), r * phi.sin(), 0.0));
+        let origin = center + radius * (disk + direction);
+        Some(EmissionSample {
+            ray: Ray::new(origin, -direction),
+            normal: -direction,
+            radiance: scene.environment.radiance(direction),
+            pdf_pos: 1.0 / (PI * radius * radius),
+            pdf_dir,
+        })
+    }
+
+    /// The density of light from infinitely far away travelling in direction `w`.
+    pub(crate) fn infinite_light_density(&self, scene: &Scene, w: Vec3) -> f64 {
+        // Distant lights have no density of arriving from a given direction.
+        self.pmf(Emitter::Environment) * scene.environment.pdf(-w)
+    }
+}
+
+/// A unidirectional path tracer.
+///
+/// At every hit on a material that can be evaluated, the environment and one of the lights,
+/// picked with the [`LightSampling`] strategy, are sampled directly with shadow rays. Unless
+/// they are delta lights, light samples are combined with the directions sampled by the
+/// material through multiple importance sampling.
+///
+/// Paths are extended iteratively. After `rr_depth` bounces, they are terminated by Russian
+/// roulette with a probability that grows as their throughput drops, and the throughput of the
+/// surviving paths is scaled up to compensate.
+#[derive(Debug, Clone, Copy)]
+pub struct PathTracer {
+    /// The maximum number of bounces of a path, which stops paths that keep surviving Russian
+    /// roulette.
+    pub max_depth: u32,
+    /// The number of bounces before Russian roulette may terminate a path.
+    pub rr_depth: u32,
+    pub light_sampling: LightSampling,
+}
+
+/// The vertex that scattered a ray, needed to weight the light found by the ray.
+#[derive(Debug, Clone, Copy)]
+struct Scattering {
+    p: Point3,
+    /// The solid angle density of the material sampling the ray.
+    pdf: f64,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: u32) -> Self {
+        PathTracer {
+            max_depth,
+            rr_depth: 3,
+            light_sampling: LightSampling::Tree,
+        }
+    }
+
+    /// Estimates the radiance arriving along `ray`.
+    pub fn radiance(&self, ray: &Ray, scene: &Scene) -> Rgb {
+        self.radiance_with_first_hit(ray, scene, |_| {})
+    }
+
+    /// Like [`PathTracer::radiance`], also passing the first hit of `ray`, or `None` if it
+    /// escapes, to `first_hit`, e.g. to collect AOVs without tracing the ray again.
+    pub fn radiance_with_first_hit(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        mut first_hit: impl FnMut(Option<&HitRecord>),
+    ) -> Rgb {
+        let mut ray = *ray;
+        let mut color = Rgb::default();
+        let mut beta = Rgb::new(1.0, 1.0, 1.0);
+        // The vertex that sampled `ray`, or `None` for camera rays and materials that cannot
+        // be evaluated.
+        let mut scattering = None;
+
+        for depth in 0..self.max_depth {
+            // 0.001 here is for fixing shadow acne.
+            let rec = scene.world.hit(&ray, 0.001, INIFINTY);
+            if depth == 0 {
+                first_hit(rec.as_ref());
+            }
+            let rec = match rec {
+                Some(rec) => rec,
+                None => {
+                    let direction = ray.direction.normal();
+                    let weight = environment_weight(scene, direction, scattering);
+                    color += weight * beta * scene.environment.radiance(direction);
+                    break;
+                }
+            };
+
+            let material = rec.material.as_ref().unwrap();
+            let emitted = material.emitted(&rec);
+            color += self.emission_weight(scene, &rec, scattering) * beta * emitted;
+            let scatter = material.scatter(&ray, &rec);
+            if !scatter.is_some_and(|scatter| scatter.below_surface) {
+                self.direct_lighting(scene, &ray, &rec, |f, radiance| {
+                    color += beta * f * radiance
+                });
+            }
+
+            let scatter = match scatter {
+                Some(scatter) => scatter,
+                None => break,
+            };
+            beta *= scatter.attenuation;
+            match self.survival_probability(depth, beta.max()) {
+                Some(survival) => beta /= survival,
+                None => break,
+            }
+            scattering = scattering_of(&ray, &rec, &scatter);
+            ray = scatter.ray;
+        }
+        color
+    }
+
+    /// The spectral counterpart of [`PathTracer::radiance`], which tracks radiance at the
+    /// wavelengths of the path rather than in RGB.
+    pub fn spectral_radiance(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        wavelengths: &mut SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.spectral_radiance_with_first_hit(ray, scene, wavelengths, |_| {})
+    }
+
+    /// Like [`PathTracer::spectral_radiance`], also passing the first hit of `ray` to
+    /// `first_hit` like [`PathTracer::radiance_with_first_hit`].
+    pub fn spectral_radiance_with_first_hit(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        wavelengths: &mut SampledWavelengths,
+        mut first_hit: impl FnMut(Option<&HitRecord>),
+    ) -> SampledSpectrum {
+        let mut ray = ray.with_wavelength(Some(wavelengths.hero()));
+        let mut color = SampledSpectrum::default();
+        let mut beta = SampledSpectrum::from_fn(wavelengths, |_| 1.0);
+        let mut scattering = None;
+
+        for depth in 0..self.max_depth {
+            let rec = scene.world.hit(&ray, 0.001, INIFINTY);
+            if depth == 0 {
+                first_hit(rec.as_ref());
+            }
+            let rec = match rec {
+                Some(rec) => rec,
+                None => {
+                    let direction = ray.direction.normal();
+                    let weight = environment_weight(scene, direction, scattering);
+                    let radiance = scene.environment.radiance(direction);
+                    color += weight
+                        * beta
+                        * SampledSpectrum::from_fn(wavelengths, |lambda| {
+                            rgb_illuminant(radiance, lambda)
+                        });
+                    break;
+                }
+            };
+
+            let material = rec.material.as_ref().unwrap();
+            if material.dispersive() {
+                wavelengths.terminate_secondary();
+            }
+
+            let emitted = self.emission_weight(scene, &rec, scattering) * material.emitted(&rec);
+            color += beta
+                * SampledSpectrum::from_fn(wavelengths, |lambda| rgb_illuminant(emitted, lambda));
+            let scatter = material.scatter(&ray, &rec);
+            if !scatter.is_some_and(|scatter| scatter.below_surface) {
+                self.direct_lighting(scene, &ray, &rec, |f, radiance| {
+                    color += beta
+                        * SampledSpectrum::from_fn(wavelengths, |lambda| {
+                            rgb_albedo(f, lambda) * rgb_illuminant(radiance, lambda)
+                        });
+                });
+            }
+
+            let scatter = match scatter {
+                Some(scatter) => scatter,
+                None => break,
+            };
+            beta *= SampledSpectrum::from_fn(wavelengths, |lambda| {
+                rgb_albedo(scatter.attenuation, lambda)
+            });
+            match self.survival_probability(depth, beta.max()) {
+                Some(survival) => beta /= survival,
+                None => break,
+            }
+            scattering = scattering_of(&ray, &rec, &scatter);
+            ray = scatter.ray.with_wavelength(ray.wavelength);
+        }
+        color
+    }
+
+    /// Plays Russian roulette after the bounce at `depth` of a path whose throughput peaks at
+    /// `max_throughput`, and returns the probability that the path survived, or `None` if it
+    /// was terminated.
+    fn survival_probability(&self, depth: u32, max_throughput: f64) -> Option<f64> {
+        if depth + 1 < self.rr_depth {
+            return Some(1.0);
+        }
+        let survival = max_throughput.min(1.0);
+        if survival > 0.0 && random_f64() < survival {
+            Some(survival)
+        } else {
+            None
+        }
+    }
+
+    /// Samples the environment and one of the lights from the hit point of `ray`.
+    ///
+    /// For every sample that reaches the hit point, `contribute` receives the BSDF times the
+    /// cosine divided by the density of the sample, weighted for multiple importance sampling
+    /// and by the transmittance of the shadow ray, and the unoccluded radiance. Nothing is
+    /// sampled if the material cannot be evaluated.
+    fn direct_lighting(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        rec: &HitRecord,
+        mut contribute: impl FnMut(Rgb, Rgb),
+    ) {
+        let material = match rec.material {
+            Some(material) => material,
+            None => return,
+        };
+
+        let (direction, light_pdf) = scene.environment.sample(random_f64(), random_f64());
+        if light_pdf > 0.0 {
+            if let Some((f, scatter_pdf)) = material.eval(ray, rec, direction) {
+                let transmittance = if f.luminance() > 0.0 {
+                    scene.transmittance(rec.p, direction, INIFINTY)
+                } else {
+                    0.0
+                };
+                if transmittance > 0.0 {
+                    let weight = power_heuristic(light_pdf, scatter_pdf) / light_pdf;
+                    contribute(
+                        weight * transmittance * f,
+                        scene.environment.radiance(direction),
+                    );
+                }
+            }
+        }
+
+        let sampler = scene.light_sampler();
+        let (index, pmf) = match sampler.sample(self.light_sampling, rec.p, random_f64()) {
+            Some((index, pmf)) if pmf > 0.0 => (index, pmf),
+            _ => return,
+        };
+        let light = &scene.lights[index];
+        let sample = match light.sample_li(rec.p, random_f64(), random_f64()) {
+            Some(sample) if sample.pdf > 0.0 => sample,
+            _ => return,
+        };
+        let (f, scatter_pdf) = match material.eval(ray, rec, sample.direction) {
+            Some(eval) => eval,
+            None => return,
+        };
+
+        // Stop short of the light so that it does not occlude itself.
+        let max_t = sample.distance * (1.0 - 1.0e-4);
+        if f.luminance() <= 0.0 {
+            return;
+        }
+        let transmittance = scene.transmittance(rec.p, sample.direction, max_t);
+        if transmittance > 0.0 {
+            let light_pdf = pmf * sample.pdf;
+            let weight = if light.is_delta() {
+                1.0
+            } else {
+                power_heuristic(light_pdf, scatter_pdf)
+            };
+            contribute(weight * transmittance * f / light_pdf, sample.radiance);
+        }
+    }
+
+    /// The multiple importance sampling weight of the light emitted by the hit object towards
+    /// the vertex that scattered the ray.
+    fn emission_weight(
+        &self,
+        scene: &Scene,
+        rec: &HitRecord,
+        scattering: Option<Scattering>,
+    ) -> f64 {
+        match (scattering, scene.object_light(rec)) {
+            (Some(scattering), Some(index)) => {
+                let pmf = scene
+                    .light_sampler()
+                    .pmf(self.light_sampling, scattering.p, index);
+                let light_pdf = pmf * scene.lights[index].pdf_li(scattering.p, rec);
+                power_heuristic(scattering.pdf, light_pdf)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// Describes the vertex at `rec` that scattered `ray_in` by `scatter`, if its material can be
+/// evaluated there, i.e. it did not scatter below the surface.
+fn scattering_of(ray_in: &Ray, rec: &HitRecord, scatter: &Scatter) -> Option<Scattering> {
+    if scatter.below_surface {
+        return None;
+    }
+    let (_, pdf) = rec.material?.eval(ray_in, rec, scatter.ray.direction)?;
+    Some(Scattering { p: rec.p, pdf })
+}
+
+/// The multiple importance sampling weight of the environment reached by a sampled direction.
+fn environment_weight(scene: &Scene, direction: Vec3, scattering: Option<Scattering>) -> f64 {
+    match scattering {
+        Some(scattering) => power_heuristic(scattering.pdf, scene.environment.pdf(direction)),
+        None => 1.0,
+    }
+}
+
+/// The power heuristic of Veach with an exponent of two, weighting a sample from a strategy of
+/// density `pdf` against another strategy of density `other_pdf`.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::{with_sampler, IndependentSampler};
+    use std::cell::RefCell;
+    use std::f64::consts::PI;
+    use std::rc::Rc;
+
+    #[test]
+    fn russian_roulette_keeps_the_mean() {
+        // The inside of a diffuse sphere lit by a point light at its center. The light reflected
+        // once is `albedo * intensity / (PI * radius^2)`, and interreflections divide it by
+        // `1 - albedo`, which makes the radiance 1 everywhere.
+        let albedo = 0.8;
+        let mut world = HittableList::new();
+        world.add(Sphere::new(
+            Point3::default(),
+            1.0,
+            Lambertian::new(Rgb::from(albedo)),
+        ));
+        let mut scene = Scene::new(world, Rgb::default());
+        scene.add_light(PointLight::new(
+            Point3::default(),
+            Rgb::from((1.0 - albedo) * PI / albedo),
+        ));
+
+        let samples = 4000;
+        let mean = |rr_depth: u32| {
+            let integrator = PathTracer {
+                rr_depth,
+                ..PathTracer::new(100)
+            };
+            let sampler = Rc::new(RefCell::new(IndependentSampler::new(rr_depth as u64)));
+            with_sampler(sampler, || {
+                let mut total = Rgb::default();
+                for _ in 0..samples {
+                    let ray = Ray::new(Point3::default(), Vec3::random_unit_vector());
+                    total += integrator.radiance(&ray, &scene);
+                }
+                total.luminance() / samples as f64
+            })
+        };
+
+        let without = mean(100);
+        let with = mean(1);
+        assert!((without - 1.0).abs() < 0.01, "{}", without);
+        assert!((with - 1.0).abs() < 0.03, "{}", with);
+    }
+}
@@ -1,15 +1,48 @@
 //! Ray tracing utilities.
+pub mod aabb;
+pub mod aov;
+pub mod bdpt;
+pub mod bvh;
 pub mod camera;
 pub mod color;
+pub mod cone;
 pub mod consts;
+pub mod csg;
+pub mod cylinder;
+pub mod denoise;
+pub mod disk;
+pub mod distribution;
+pub mod environment;
 pub mod error;
+pub mod film;
+pub mod filter;
 pub mod hittable;
+pub mod image;
+pub mod integrator;
+pub mod light;
+pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod microfacet;
+pub mod mlt;
+pub mod noise;
+pub mod onb;
+pub mod photon;
+pub mod plane;
 pub mod prelude;
 pub mod ray;
+pub mod sampler;
+pub mod sdf;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
+pub mod subdivision;
+pub mod texture;
+pub mod tonemap;
+pub mod torus;
+pub mod triangle;
 pub mod util;
 pub mod vec;
-pub mod material;
 
 #[macro_use]
 mod macros;
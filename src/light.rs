@@ -0,0 +1,1021 @@
+//! [`Light`]s that are sampled explicitly with shadow rays, and a [`LightSampler`] that picks
+//! one of many lights.
+use crate::aabb::Aabb;
+use crate::distribution::AliasTable;
+use crate::onb::Onb;
+use crate::prelude::*;
+use std::fmt;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// Light arriving at a point from a sampled position on a [`Light`].
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    /// The unit direction from the receiving point towards the light.
+    pub direction: Vec3,
+    /// The distance to the light along `direction`, which is infinite for distant lights.
+    pub distance: f64,
+    /// The radiance arriving at the receiving point if nothing blocks the light, or the
+    /// irradiance for lights described by a delta distribution.
+    pub radiance: Rgb,
+    /// The solid angle density of the sample, which is one for delta lights.
+    pub pdf: f64,
+    /// The outward normal of the surface at the sampled position, which is zero for lights
+    /// that are not surfaces.
+    pub normal: Vec3,
+}
+
+/// A ray of light leaving a [`Light`], sampled to trace paths starting from the lights.
+#[derive(Debug, Clone, Copy)]
+pub struct EmissionSample {
+    /// The ray leaving the light, with a unit direction.
+    pub ray: Ray,
+    /// The outward normal of the surface at the origin of the ray, or the direction of the ray
+    /// for distant lights and zero for point lights.
+    pub normal: Vec3,
+    /// The radiance emitted along the ray, or the intensity for point lights and the
+    /// irradiance for distant lights.
+    pub radiance: Rgb,
+    /// The density of the origin of the ray, per unit area on the light or on a disk
+    /// perpendicular to distant lights, which is one for point lights.
+    pub pdf_pos: f64,
+    /// The solid angle density of the direction of the ray, which is one for distant lights.
+    pub pdf_dir: f64,
+}
+
+/// A source of light in the scene.
+pub trait Light: Debug + Send + Sync {
+    /// Samples the light arriving at `p` from the uniform samples `u1` and `u2`, or returns
+    /// `None` if `p` receives no light.
+    fn sample_li(&self, p: Point3, u1: f64, u2: f64) -> Option<LightSample>;
+
+    /// Whether the light is described by a delta distribution, such as a point light, which
+    /// cannot be hit by rays and is only found by [`Light::sample_li`].
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    /// The solid angle density of [`Light::sample_li`] sampling the point of `rec` from `p`,
+    /// which is zero for delta lights.
+    fn pdf_li(&self, _p: Point3, _rec: &HitRecord) -> f64 {
+        0.0
+    }
+
+    /// The luminance of the total power emitted by the light, used to pick among many lights.
+    ///
+    /// Lights without [`Light::bounds`] return the power per unit area perpendicular to the
+    /// light instead.
+    fn power(&self) -> f64;
+
+    /// Returns a box enclosing the light, or `None` for lights infinitely far away.
+    fn bounds(&self) -> Option<Aabb>;
+
+    /// Samples a ray leaving the light from the uniform samples `u_pos` for its origin and
+    /// `u_dir` for its direction, or returns `None` if no light is emitted.
+    ///
+    /// Lights infinitely far away emit through a disk covering the scene enclosed by
+    /// `scene_bounds`.
+    fn sample_le(
+        &self,
+        u_pos: (f64, f64),
+        u_dir: (f64, f64),
+        scene_bounds: Option<Aabb>,
+    ) -> Option<EmissionSample>;
+
+    /// The position and direction densities of [`Light::sample_le`] sampling `ray`, leaving a
+    /// point of the light with outward normal `normal`.
+    fn pdf_le(&self, ray: &Ray, normal: Vec3, scene_bounds: Option<Aabb>) -> (f64, f64);
+}
+
+/// Returns the center and the radius of a sphere enclosing the scene.
+pub(crate) fn bounding_sphere(scene_bounds: Option<Aabb>) -> (Point3, f64) {
+    scene_bounds.map_or((Point3::default(), 1.0), |b| {
+        (b.centroid(), 0.5 * b.diagonal().len())
+    })
+}
+
+/// Maps uniform samples to a direction uniformly distributed within the cone of directions
+/// around `+z` whose cosine is at least `cos_max`.
+fn uniform_cone(u1: f64, u2: f64, cos_max: f64) -> Vec3 {
+    let cos_theta = 1.0 - u1 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Maps uniform samples to a point uniformly distributed on the unit disk.
+fn uniform_disk(u1: f64, u2: f64) -> (f64, f64) {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    (r * phi.cos(), r * phi.sin())
+}
+
+/// A light emitting uniformly in all directions from a single point.
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: Point3,
+    /// The radiant intensity, i.e. power per unit solid angle.
+    pub intensity: Rgb,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Rgb) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Point3, _u1: f64, _u2: f64) -> Option<LightSample> {
+        let offset = self.position - p;
+        let distance = offset.len();
+        if distance <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: offset / distance,
+            distance,
+            radiance: self.intensity / (distance * distance),
+            pdf: 1.0,
+            normal: Vec3::default(),
+        })
+    }
+
+    fn power(&self) -> f64 {
+        4.0 * PI * self.intensity.luminance()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_point(self.position))
+    }
+
+    fn sample_le(
+        &self,
+        _u_pos: (f64, f64),
+        u_dir: (f64, f64),
+        _scene_bounds: Option<Aabb>,
+    ) -> Option<EmissionSample> {
+        Some(EmissionSample {
+            ray: Ray::new(self.position, uniform_cone(u_dir.0, u_dir.1, -1.0)),
+            normal: Vec3::default(),
+            radiance: self.intensity,
+            pdf_pos: 1.0,
+            pdf_dir: 1.0 / (4.0 * PI),
+        })
+    }
+
+    fn pdf_le(&self, _ray: &Ray, _normal: Vec3, _scene_bounds: Option<Aabb>) -> (f64, f64) {
+        (0.0, 1.0 / (4.0 * PI))
+    }
+}
+
+/// A point light emitting within a cone, e.g. a stage light or a flashlight.
+///
+/// The intensity is constant within `falloff_start` degrees of the axis and falls off smoothly
+/// to zero at `total_width` degrees.
+#[derive(Debug, Clone, Copy)]
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Rgb,
+    cos_falloff_start: f64,
+    cos_total_width: f64,
+}
+
+impl SpotLight {
+    /// Creates a spot light at `position` pointing towards `direction`.
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Rgb,
+        falloff_start: f64,
+        total_width: f64,
+    ) -> Self {
+        let total_width = total_width.to_radians();
+        let falloff_start = falloff_start.to_radians().min(total_width);
+        SpotLight {
+            position,
+            direction: direction.normal(),
+            intensity,
+            cos_falloff_start: falloff_start.cos(),
+            cos_total_width: total_width.cos(),
+        }
+    }
+
+    /// The fraction of the intensity emitted towards the unit direction `w`.
+    fn falloff(&self, w: Vec3) -> f64 {
+        let cos_theta = w.dot(self.direction);
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_total_width {
+            return 0.0;
+        }
+
+        let t =
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// The density of directions sampled uniformly within the cone of the light.
+    fn cone_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_total_width))
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point3, _u1: f64, _u2: f64) -> Option<LightSample> {
+        let offset = self.position - p;
+        let distance = offset.len();
+        if distance <= 0.0 {
+            return None;
+        }
+
+        let direction = offset / distance;
+        let falloff = self.falloff(-direction);
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: falloff * self.intensity / (distance * distance),
+            pdf: 1.0,
+            normal: Vec3::default(),
+        })
+    }
+
+    fn power(&self) -> f64 {
+        // Integrates the intensity over the cone, approximating the falloff as linear in the
+        // cosine.
+        let cone = 1.0 - 0.5 * (self.cos_falloff_start + self.cos_total_width);
+        2.0 * PI * cone * self.intensity.luminance()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_point(self.position))
+    }
+
+    fn sample_le(
+        &self,
+        _u_pos: (f64, f64),
+        u_dir: (f64, f64),
+        _scene_bounds: Option<Aabb>,
+    ) -> Option<EmissionSample> {
+        let local = uniform_cone(u_dir.0, u_dir.1, self.cos_total_width);
+        let direction = Onb::from_w(self.direction).to_world(local);
+        Some(EmissionSample {
+            ray: Ray::new(self.position, direction),
+            normal: Vec3::default(),
+            radiance: self.falloff(direction) * self.intensity,
+            pdf_pos: 1.0,
+            pdf_dir: self.cone_pdf(),
+        })
+    }
+
+    fn pdf_le(&self, ray: &Ray, _normal: Vec3, _scene_bounds: Option<Aabb>) -> (f64, f64) {
+        if ray.direction.dot(self.direction) > self.cos_total_width {
+            (0.0, self.cone_pdf())
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+/// A light infinitely far away arriving from a single direction, such as the sun.
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    /// The unit direction the light travels in.
+    direction: Vec3,
+    /// The irradiance on a surface perpendicular to the light.
+    irradiance: Rgb,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Rgb) -> Self {
+        DirectionalLight {
+            direction: direction.normal(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point3, _u1: f64, _u2: f64) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction,
+            distance: INIFINTY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+            normal: Vec3::default(),
+        })
+    }
+
+    fn power(&self) -> f64 {
+        self.irradiance.luminance()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    fn sample_le(
+        &self,
+        u_pos: (f64, f64),
+        _u_dir: (f64, f64),
+        scene_bounds: Option<Aabb>,
+    ) -> Option<EmissionSample> {
+        // Start on a disk perpendicular to the light, behind the scene.
+        let (center, radius) = bounding_sphere(scene_bounds);
+        let (x, y) = uniform_disk(u_pos.0, u_pos.1);
+        let basis = Onb::from_w(self.direction);
+        let origin = center + radius * (basis.to_world(Vec3::new(x, y, 0.0)) - self.direction);
+        Some(EmissionSample {
+            ray: Ray::new(origin, self.direction),
+            normal: self.direction,
+            radiance: self.irradiance,
+            pdf_pos: 1.0 / (PI * radius * radius),
+            pdf_dir: 1.0,
+        })
+    }
+
+    fn pdf_le(&self, _ray: &Ray, _normal: Vec3, scene_bounds: Option<Aabb>) -> (f64, f64) {
+        let (_, radius) = bounding_sphere(scene_bounds);
+        (1.0 / (PI * radius * radius), 0.0)
+    }
+}
+
+/// An emissive object in the world, sampled uniformly over its surface area.
+///
+/// The emission of partially opaque surfaces is weighted by their opacity, which is how often
+/// rays hit them rather than passing through.
+pub struct AreaLight {
+    shape: Arc<dyn Hittable + Send + Sync>,
+    area: f64,
+    power: f64,
+}
+
+impl AreaLight {
+    /// Creates a light from `shape`, or returns `None` if its material emits no light or the
+    /// shape cannot be sampled.
+    pub fn new(shape: Arc<dyn Hittable + Send + Sync>) -> Option<Self> {
+        let area = shape.area();
+        if area <= 0.0 {
+            return None;
+        }
+
+        // Estimate the average emitted radiance from both sides of a grid of surface samples.
+        let n = 8;
+        let mut radiance = 0.0;
+        for j in 0..n {
+            for i in 0..n {
+                let u1 = (i as f64 + 0.5) / n as f64;
+                let u2 = (j as f64 + 0.5) / n as f64;
+                let mut rec = shape.sample_surface(u1, u2)?;
+                let material = rec.material?;
+                let opacity = material.opacity(&rec);
+                radiance += opacity * material.emitted(&rec).luminance();
+                rec.front_face = false;
+                rec.normal = -rec.normal;
+                rec.geometric_normal = -rec.geometric_normal;
+                radiance += opacity * material.emitted(&rec).luminance();
+            }
+        }
+        let radiance = radiance / (n * n) as f64;
+        if radiance <= 0.0 {
+            return None;
+        }
+
+        Some(AreaLight {
+            power: PI * radiance * area,
+            shape,
+            area,
+        })
+    }
+}
+
+impl Debug for AreaLight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AreaLight")
+            .field("area", &self.area)
+            .field("power", &self.power)
+            .finish()
+    }
+}
+
+impl Light for AreaLight {
+    fn sample_li(&self, p: Point3, u1: f64, u2: f64) -> Option<LightSample> {
+        let mut rec = self.shape.sample_surface(u1, u2)?;
+        let offset = rec.p - p;
+        let distance_squared = offset.len_squared();
+        if distance_squared <= 0.0 {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        let direction = offset / distance;
+
+        // Emission depends on the side of the surface facing `p`.
+        let cosine = -direction.dot(rec.geometric_normal);
+        if cosine < 0.0 {
+            rec.front_face = false;
+            rec.normal = -rec.normal;
+            rec.geometric_normal = -rec.geometric_normal;
+        }
+        if cosine.abs() < 1.0e-9 {
+            return None;
+        }
+
+        // Cut-out parts of the surface are seen through as often as they are transparent.
+        let material = rec.material?;
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: material.opacity(&rec) * material.emitted(&rec),
+            pdf: distance_squared / (cosine.abs() * self.area),
+            normal: if rec.front_face {
+                rec.geometric_normal
+            } else {
+                -rec.geometric_normal
+            },
+        })
+    }
+
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    fn pdf_li(&self, p: Point3, rec: &HitRecord) -> f64 {
+        let offset = rec.p - p;
+        let cosine = offset.normal().dot(rec.geometric_normal).abs();
+        if cosine < 1.0e-9 {
+            return 0.0;
+        }
+        offset.len_squared() / (cosine * self.area)
+    }
+
+    fn power(&self) -> f64 {
+        self.power
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.shape.bounding_box()
+    }
+
+    /// Light leaves from the front side of the surface only, where materials such as
+    /// [`Principled`](crate::material::Principled) emit.
+    fn sample_le(
+        &self,
+        u_pos: (f64, f64),
+        u_dir: (f64, f64),
+        _scene_bounds: Option<Aabb>,
+    ) -> Option<EmissionSample> {
+        let rec = self.shape.sample_surface(u_pos.0, u_pos.1)?;
+        let material = rec.material?;
+        let radiance = material.opacity(&rec) * material.emitted(&rec);
+
+        // Cosine weighted directions around the normal.
+        let (x, y) = uniform_disk(u_dir.0, u_dir.1);
+        let local = Vec3::new(x, y, (1.0 - x * x - y * y).max(0.0).sqrt());
+        Some(EmissionSample {
+            ray: Ray::new(rec.p, Onb::from_w(rec.geometric_normal).to_world(local)),
+            normal: rec.geometric_normal,
+            radiance,
+            pdf_pos: 1.0 / self.area,
+            pdf_dir: local.z / PI,
+        })
+    }
+
+    fn pdf_le(&self, ray: &Ray, normal: Vec3, _scene_bounds: Option<Aabb>) -> (f64, f64) {
+        (1.0 / self.area, ray.direction.dot(normal).max(0.0) / PI)
+    }
+}
+
+/// Strategies for picking one of many lights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightSampling {
+    /// Every light is equally likely.
+    Uniform,
+    /// Lights are picked proportionally to their power.
+    Power,
+    /// Lights are picked by descending a bounding volume hierarchy over the lights, estimating
+    /// the importance of each subtree from its power and its distance to the receiving point.
+    Tree,
+}
+
+/// Picks one of many lights to sample from a receiving point, with any [`LightSampling`]
+/// strategy.
+#[derive(Debug, Clone)]
+pub struct LightSampler {
+    len: usize,
+    power: AliasTable,
+    tree: LightTree,
+}
+
+impl LightSampler {
+    /// Creates a sampler for `lights` in a scene enclosed by `scene_bounds`, which determines
+    /// the power of lights infinitely far away.
+    pub fn new(lights: &[Arc<dyn Light>], scene_bounds: Option<Aabb>) -> Self {
+        let (_, scene_radius) = bounding_sphere(scene_bounds);
+        let powers: Vec<f64> = lights
+            .iter()
+            .map(|light| match light.bounds() {
+                Some(_) => light.power(),
+                None => PI * scene_radius * scene_radius * light.power(),
+            })
+            .collect();
+
+        LightSampler {
+            len: lights.len(),
+            power: AliasTable::new(&powers),
+            tree: LightTree::new(lights, &powers),
+        }
+    }
+
+    /// Picks a light for the receiving point `p` from the uniform sample `u`, returning its
+    /// index and probability, or `None` if there are no lights.
+    pub fn sample(&self, strategy: LightSampling, p: Point3, u: f64) -> Option<(usize, f64)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        match strategy {
+            LightSampling::Uniform => {
+                let index = ((u * self.len as f64) as usize).min(self.len - 1);
+                Some((index, 1.0 / self.len as f64))
+            }
+            LightSampling::Power => Some(self.power.sample(u)),
+            LightSampling::Tree => self.tree.sample(p, u),
+        }
+    }
+
+    /// The probability of [`LightSampler::sample`] picking the light at `index` for `p`.
+    pub fn pmf(&self, strategy: LightSampling, p: Point3, index: usize) -> f64 {
+        match strategy {
+            LightSampling::Uniform => 1.0 / self.len as f64,
+            LightSampling::Power => self.power.pmf(index),
+            LightSampling::Tree => self.tree.pmf(p, index),
+        }
+    }
+}
+
+/// A node of a [`LightTree`]. The first child of an interior node follows it directly.
+#[derive(Debug, Clone, Copy)]
+struct LightNode {
+    bounds: Aabb,
+    power: f64,
+    /// The light of a leaf, or the index of the second child of an interior node.
+    light_or_child: usize,
+    is_leaf: bool,
+}
+
+/// A bounding volume hierarchy over the lights with bounds, with lights infinitely far away
+/// kept aside and picked proportionally to their power.
+#[derive(Debug, Clone)]
+struct LightTree {
+    nodes: Vec<LightNode>,
+    infinite: Vec<usize>,
+    infinite_power: AliasTable,
+    /// The probability of picking a light infinitely far away rather than descending the tree.
+    infinite_probability: f64,
+    /// The path from the root to the leaf of each bounded light, one bit per level with set
+    /// bits taking the second child.
+    trails: Vec<Option<(u64, u32)>>,
+}
+
+impl LightTree {
+    fn new(lights: &[Arc<dyn Light>], powers: &[f64]) -> Self {
+        let mut bounded = Vec::new();
+        let mut infinite = Vec::new();
+        for (index, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if powers[index] > 0.0 => bounded.push((index, bounds)),
+                Some(_) => {}
+                None => infinite.push(index),
+            }
+        }
+
+        let infinite_weights: Vec<f64> = infinite.iter().map(|&i| powers[i]).collect();
+        let infinite_total: f64 = infinite_weights.iter().sum();
+        let bounded_total: f64 = bounded.iter().map(|&(i, _)| powers[i]).sum();
+        let infinite_probability = if infinite.is_empty() {
+            0.0
+        } else if bounded.is_empty() || infinite_total + bounded_total <= 0.0 {
+            1.0
+        } else {
+            infinite_total / (infinite_total + bounded_total)
+        };
+
+        let mut tree = LightTree {
+            nodes: Vec::new(),
+            infinite,
+            infinite_power: AliasTable::new(&infinite_weights),
+            infinite_probability,
+            trails: vec![None; lights.len()],
+        };
+        if !bounded.is_empty() {
+            tree.build(&mut bounded, powers, 0, 0);
+        }
+        tree
+    }
+
+    /// Appends the subtree over `lights` to the nodes, reached from the root through `trail`
+    /// at `depth`.
+    fn build(&mut self, lights: &mut [(usize, Aabb)], powers: &[f64], trail: u64, depth: u32) {
+        let bounds = lights.iter().map(|&(_, b)| b).reduce(Aabb::union).unwrap();
+        let power = lights.iter().map(|&(i, _)| powers[i]).sum();
+        let node = self.nodes.len();
+
+        if lights.len() == 1 {
+            let light = lights[0].0;
+            self.nodes.push(LightNode {
+                bounds,
+                power,
+                light_or_child: light,
+                is_leaf: true,
+            });
+            self.trails[light] = Some((trail, depth));
+            return;
+        }
+
+        // Split at the median centroid along the longest axis of the centroids, which keeps the
+        // depth logarithmic so that the trails fit into 64 bits.
+        let centroids = lights
+            .iter()
+            .map(|&(_, b)| Aabb::from_point(b.centroid()))
+            .reduce(Aabb::union)
+            .unwrap();
+        let axis = centroids.longest_axis();
+        let key = |b: &Aabb| {
+            let c = b.centroid();
+            [c.x, c.y, c.z][axis]
+        };
+        lights.sort_by(|a, b| key(&a.1).total_cmp(&key(&b.1)));
+        let (first, second) = lights.split_at_mut(lights.len() / 2);
+
+        self.nodes.push(LightNode {
+            bounds,
+            power,
+            light_or_child: 0,
+            is_leaf: false,
+        });
+        self.build(first, powers, trail, depth + 1);
+        self.nodes[node].light_or_child = self.nodes.len();
+        self.build(second, powers, trail | 1 << depth, depth + 1);
+    }
+
+    /// Estimates the contribution of the lights below `node` to the receiving point `p`.
+    fn importance(&self, node: usize, p: Point3) -> f64 {
+        let node = &self.nodes[node];
+        let half_diagonal = 0.5 * node.bounds.diagonal().len();
+        let distance_squared = (node.bounds.centroid() - p).len_squared();
+        node.power
+            / distance_squared
+                .max(half_diagonal * half_diagonal)
+                .max(1.0e-8)
+    }
+
+    /// The probabilities of descending from the interior `node` into its first and second child.
+    fn child_probabilities(&self, node: usize, p: Point3) -> Option<[f64; 2]> {
+        let first = self.importance(node + 1, p);
+        let second = self.importance(self.nodes[node].light_or_child, p);
+        let total = first + second;
+        if total > 0.0 {
+            Some([first / total, second / total])
+        } else {
+            None
+        }
+    }
+
+    fn sample(&self, p: Point3, u: f64) -> Option<(usize, f64)> {
+        let mut u = u;
+        if u < self.infinite_probability {
+            let (index, pmf) = self.infinite_power.sample(u / self.infinite_probability);
+            return Some((self.infinite[index], self.infinite_probability * pmf));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+        u = (u - self.infinite_probability) / (1.0 - self.infinite_probability);
+
+        let mut pmf = 1.0 - self.infinite_probability;
+        let mut node = 0;
+        while !self.nodes[node].is_leaf {
+            let [first, second] = self.child_probabilities(node, p)?;
+            if u < first {
+                u /= first;
+                pmf *= first;
+                node += 1;
+            } else {
+                u = ((u - first) / second).min(1.0 - f64::EPSILON);
+                pmf *= second;
+                node = self.nodes[node].light_or_child;
+            }
+        }
+        Some((self.nodes[node].light_or_child, pmf))
+    }
+
+    fn pmf(&self, p: Point3, index: usize) -> f64 {
+        let (trail, depth) = match self.trails.get(index) {
+            Some(&Some(trail)) => trail,
+            _ => {
+                return match self.infinite.iter().position(|&i| i == index) {
+                    Some(i) => self.infinite_probability * self.infinite_power.pmf(i),
+                    None => 0.0,
+                };
+            }
+        };
+
+        let mut pmf = 1.0 - self.infinite_probability;
+        let mut node = 0;
+        for level in 0..depth {
+            let probabilities = match self.child_probabilities(node, p) {
+                Some(probabilities) => probabilities,
+                None => return 0.0,
+            };
+            if trail & 1 << level == 0 {
+                pmf *= probabilities[0];
+                node += 1;
+            } else {
+                pmf *= probabilities[1];
+                node = self.nodes[node].light_or_child;
+            }
+        }
+        pmf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::Scene;
+    use crate::material::{Lambertian, Masked, Principled};
+    use crate::triangle::Triangle;
+
+    #[test]
+    fn tree_pmf_matches_sampling() {
+        let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+        for i in 0..37 {
+            let position = Point3::new((i % 7) as f64 * 3.0, 4.0, (i / 7) as f64 * 3.0);
+            let intensity = Rgb::from(1.0 + (i % 5) as f64);
+            lights.push(Arc::new(PointLight::new(position, intensity)));
+        }
+        lights.push(Arc::new(DirectionalLight::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Rgb::from(0.01),
+        )));
+        let sampler = LightSampler::new(&lights, None);
+
+        for &strategy in &[
+            LightSampling::Uniform,
+            LightSampling::Power,
+            LightSampling::Tree,
+        ] {
+            let p = Point3::new(2.0, 0.0, 5.0);
+            let total: f64 = (0..lights.len()).map(|i| sampler.pmf(strategy, p, i)).sum();
+            assert!((total - 1.0).abs() < 1.0e-9, "{:?}: {}", strategy, total);
+
+            let n = 20000;
+            let mut counts = vec![0usize; lights.len()];
+            for k in 0..n {
+                let (index, pmf) = sampler
+                    .sample(strategy, p, (k as f64 + 0.5) / n as f64)
+                    .unwrap();
+                assert!((pmf - sampler.pmf(strategy, p, index)).abs() < 1.0e-12);
+                counts[index] += 1;
+            }
+            for (index, &count) in counts.iter().enumerate() {
+                let expected = sampler.pmf(strategy, p, index);
+                assert!((count as f64 / n as f64 - expected).abs() < 2.0e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_squared_distance() {
+        let light = PointLight::new(Point3::new(1.0, 2.0, 3.0), Rgb::new(4.0, 8.0, 12.0));
+        let sample = light
+            .sample_li(Point3::new(1.0, 2.0, 1.0), 0.3, 0.7)
+            .unwrap();
+        assert!((sample.direction - Vec3::new(0.0, 0.0, 1.0)).len() < 1.0e-12);
+        assert!((sample.distance - 2.0).abs() < 1.0e-12);
+        assert!((sample.radiance - Rgb::new(1.0, 2.0, 3.0)).len() < 1.0e-12);
+        assert_eq!(sample.pdf, 1.0);
+        assert!(light.sample_li(light.position, 0.3, 0.7).is_none());
+
+        let power = 4.0 * PI * Rgb::new(4.0, 8.0, 12.0).luminance();
+        assert!((light.power() - power).abs() < 1.0e-9);
+        assert!((emitted_power(&light) - power).abs() < 1.0e-9 * power);
+    }
+
+    #[test]
+    fn spot_lights_fall_off_towards_the_edge_of_their_cone() {
+        let light = SpotLight::new(
+            Point3::default(),
+            Vec3::new(0.0, 0.0, -2.0),
+            Rgb::from(8.0),
+            20.0,
+            40.0,
+        );
+        let below = |degrees: f64| {
+            let theta = f64::to_radians(degrees);
+            Point3::new(2.0 * theta.sin(), 0.0, -2.0 * theta.cos())
+        };
+
+        let sample = light.sample_li(below(10.0), 0.5, 0.5).unwrap();
+        assert!((sample.radiance - Rgb::from(2.0)).len() < 1.0e-12);
+        assert!((sample.direction + below(10.0) / 2.0).len() < 1.0e-12);
+        assert_eq!(sample.pdf, 1.0);
+        assert!(light.sample_li(below(45.0), 0.5, 0.5).is_none());
+        assert!(light
+            .sample_li(Point3::new(0.0, 0.0, 2.0), 0.5, 0.5)
+            .is_none());
+
+        // Halfway between the cosines of both angles the smooth step is at one half.
+        let cos_theta = 0.5 * (f64::to_radians(20.0).cos() + f64::to_radians(40.0).cos());
+        let sample = light.sample_li(below(cos_theta.acos().to_degrees()), 0.5, 0.5);
+        assert!((sample.unwrap().radiance.r - 1.0).abs() < 1.0e-9);
+        let inner = light.sample_li(below(25.0), 0.5, 0.5).unwrap().radiance.r;
+        let outer = light.sample_li(below(35.0), 0.5, 0.5).unwrap().radiance.r;
+        assert!(2.0 > inner && inner > outer && outer > 0.0);
+
+        // Emitted rays stay within the cone and carry the power of the light.
+        for k in 0..100 {
+            let u = ((k as f64 + 0.5) / 100.0, (k as f64 * 0.618_034).fract());
+            let ray = light.sample_le((0.5, 0.5), u, None).unwrap().ray;
+            let cos_theta = ray.direction.dot(Vec3::new(0.0, 0.0, -1.0));
+            assert!(cos_theta >= f64::to_radians(40.0).cos() - 1.0e-12);
+            assert_eq!(
+                light.pdf_le(&ray, Vec3::default(), None).1,
+                light.cone_pdf()
+            );
+        }
+        let power = emitted_power(&light);
+        assert!((light.power() - power).abs() < 1.0e-3 * power, "{}", power);
+    }
+
+    #[test]
+    fn directional_lights_arrive_from_one_direction_everywhere() {
+        let light = DirectionalLight::new(Vec3::new(0.0, -2.0, 0.0), Rgb::from(3.0));
+        for &p in &[Point3::default(), Point3::new(5.0, -7.0, 1.0)] {
+            let sample = light.sample_li(p, 0.2, 0.9).unwrap();
+            assert!((sample.direction - Vec3::new(0.0, 1.0, 0.0)).len() < 1.0e-12);
+            assert_eq!(sample.distance, INIFINTY);
+            assert_eq!(sample.radiance, Rgb::from(3.0));
+            assert_eq!(sample.pdf, 1.0);
+        }
+        assert!((light.power() - 3.0).abs() < 1.0e-12);
+
+        // Emitted rays cross the scene from a disk behind it.
+        let bounds = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let (center, radius) = bounding_sphere(Some(bounds));
+        for k in 0..100 {
+            let u = ((k as f64 + 0.5) / 100.0, (k as f64 * 0.618_034).fract());
+            let sample = light.sample_le(u, (0.5, 0.5), Some(bounds)).unwrap();
+            assert_eq!(sample.ray.direction, Vec3::new(0.0, -1.0, 0.0));
+            let offset = sample.ray.origin - center;
+            assert!((offset.y - radius).abs() < 1.0e-9);
+            assert!(offset.x * offset.x + offset.z * offset.z <= radius * radius + 1.0e-9);
+            let (pdf_pos, _) = light.pdf_le(&sample.ray, sample.normal, Some(bounds));
+            assert!((pdf_pos - sample.pdf_pos).abs() < 1.0e-12);
+        }
+    }
+
+    /// A unit square of triangles in the `z = 0` plane emitting `radiance` upwards, with the
+    /// given opacity.
+    fn emitter(radiance: f64, opacity: f64) -> HittableList {
+        let material = Arc::new(Masked::new(
+            Principled {
+                emission: Box::new(Rgb::from(radiance)),
+                base_color: Box::new(Rgb::default()),
+                ..Default::default()
+            },
+            opacity,
+        ));
+        let corners = [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ];
+        let mut list = HittableList::new();
+        list.add(Triangle::new(
+            corners[0],
+            corners[1],
+            corners[2],
+            Arc::clone(&material),
+        ));
+        list.add(Triangle::new(corners[0], corners[2], corners[3], material));
+        list
+    }
+
+    #[test]
+    fn lists_of_emitters_are_area_lights() {
+        let mut list = emitter(2.0, 1.0);
+        list.add(Sphere::new(
+            Point3::new(0.0, 0.0, 5.0),
+            1.0,
+            Lambertian::new(Rgb::default()),
+        ));
+        assert!((list.area() - (1.0 + 4.0 * PI)).abs() < 1.0e-12);
+
+        // Points are spread over the objects proportionally to their area.
+        let n = 1000;
+        let on_square = (0..n)
+            .filter(|&k| {
+                let u = ((k as f64 + 0.5) / n as f64, (k as f64 * 0.618_034).fract());
+                list.sample_surface(u.0, u.1).unwrap().p.z.abs() < 1.0e-12
+            })
+            .count();
+        let expected = n as f64 / (1.0 + 4.0 * PI);
+        assert!((on_square as f64 - expected).abs() <= 1.0, "{}", on_square);
+
+        // An emissive mesh grouped in a list is lit like its triangles.
+        let mut world = HittableList::new();
+        world.add(emitter(2.0, 1.0));
+        let scene = Scene::new(world, Rgb::default());
+        assert_eq!(scene.lights().len(), 1);
+        let light = &scene.lights()[0];
+        assert!(
+            (light.power() - 2.0 * PI).abs() < 1.0e-9,
+            "{}",
+            light.power()
+        );
+        let sample = light
+            .sample_li(Point3::new(0.5, 0.5, 1.0), 0.3, 0.6)
+            .unwrap();
+        assert!((sample.radiance - Rgb::from(2.0)).len() < 1.0e-12);
+    }
+
+    #[test]
+    fn area_lights_weight_emission_by_opacity() {
+        let light = AreaLight::new(Arc::new(emitter(2.0, 0.25))).unwrap();
+        assert!(
+            (light.power() - 0.5 * PI).abs() < 1.0e-9,
+            "{}",
+            light.power()
+        );
+        let sample = light
+            .sample_li(Point3::new(0.5, 0.5, 1.0), 0.3, 0.6)
+            .unwrap();
+        assert!((sample.radiance - Rgb::from(0.5)).len() < 1.0e-12);
+        let sample = light.sample_le((0.3, 0.6), (0.5, 0.5), None).unwrap();
+        assert!((sample.radiance - Rgb::from(0.5)).len() < 1.0e-12);
+
+        assert!(AreaLight::new(Arc::new(emitter(2.0, 0.0))).is_none());
+    }
+
+    #[test]
+    fn area_light_densities_use_the_geometric_normal() {
+        // Interpolated normals tilted far away from the geometric normal `(0, 0, 1)`.
+        let tilted = Vec3::new(0.8, 0.0, 0.6);
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Principled {
+                emission: Box::new(Rgb::from(1.0)),
+                base_color: Box::new(Rgb::default()),
+                ..Default::default()
+            },
+        )
+        .with_normals([tilted; 3]);
+        let light = AreaLight::new(Arc::new(triangle)).unwrap();
+
+        for &p in &[Point3::new(0.2, 0.3, 1.0), Point3::new(-0.5, 0.8, -2.0)] {
+            for k in 0..20 {
+                let u = ((k as f64 + 0.5) / 20.0, (k as f64 * 0.618_034).fract());
+                let sample = light.sample_li(p, u.0, u.1).unwrap();
+                let ray = Ray::new(p, sample.direction);
+                let rec = light.shape.hit(&ray, 1.0e-9, INIFINTY).unwrap();
+                assert!((rec.t - sample.distance).abs() < 1.0e-9);
+                assert!(rec.normal.dot(rec.geometric_normal) < 0.9);
+                let pdf = light.pdf_li(p, &rec);
+                assert!(
+                    (pdf - sample.pdf).abs() < 1.0e-9 * sample.pdf,
+                    "{} {}",
+                    pdf,
+                    sample.pdf
+                );
+            }
+        }
+    }
+
+    /// Integrates the luminance of the intensity of a point-like light over all directions,
+    /// by sampling emitted rays on a stratified grid.
+    fn emitted_power(light: &dyn Light) -> f64 {
+        let n = 400;
+        let mut power = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let sample = light.sample_le((0.5, 0.5), u, None).unwrap();
+                power += sample.radiance.luminance() / sample.pdf_dir;
+            }
+        }
+        power / (n * n) as f64
+    }
+}
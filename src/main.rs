@@ -1,34 +1,26 @@
 #[macro_use]
 extern crate ray_tracing;
 
+use ray_tracing::aov::{Aov, AovBuffers};
+use ray_tracing::bdpt::Bdpt;
+use ray_tracing::denoise::{Denoiser, Features};
+use ray_tracing::environment::*;
+use ray_tracing::film::Film;
+use ray_tracing::filter::MitchellFilter;
+use ray_tracing::integrator::*;
 use ray_tracing::material::*;
+use ray_tracing::mlt::Mlt;
+use ray_tracing::photon::PhotonMapper;
 use ray_tracing::prelude::*;
+use ray_tracing::sky::PhysicalSky;
+use ray_tracing::spectrum::*;
+use ray_tracing::tonemap::*;
 use ray_tracing::util::*;
+use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-
-fn ray_color(ray: &Ray, world: &impl Hittable, depth: u32) -> Rgb {
-    // Gathers no more light if we exceeded the ray bounce limit.
-    if depth <= 0 {
-        return rgb!(0, 0, 0);
-    }
-
-    // 0.001 here is for fixing shadow acne.
-    if let Some(rec) = world.hit(ray, 0.001, INIFINTY) {
-        if let Some((attenuation, scattered)) = rec.material.as_ref().unwrap().scatter(&ray, &rec) {
-            return attenuation * ray_color(&scattered, world, depth - 1)
-        } else {
-            return Rgb::default()
-        }
-    } else {
-        let unit_direction = ray.direction.normal();
-
-        // A trick that converts range from [-1, 1) to [0, 1)
-        let t = 0.5 * (unit_direction.y + 1.0);
-
-        return (1.0 - t) * rgb!(1.0, 1.0, 1.0) + t * rgb!(0.5, 0.7, 1.0);
-    }
-}
+use std::io::BufWriter;
+use std::path::PathBuf;
 
 fn main() {
     let stdout = io::stdout();
@@ -36,16 +28,6 @@ fn main() {
     let stderr = io::stderr();
     let mut stderr = stderr.lock();
 
-    macro_rules! stdout {
-        ($fmt:expr, $($arg:tt)*) => {
-            write!(stdout, $fmt, $($arg)*).unwrap()
-        };
-
-        ($arg:tt) => {
-            write!(stdout, "{}", $arg).unwrap()
-        };
-    }
-
     macro_rules! stderr {
         ($fmt:expr, $($arg:tt)*) => {
             write!(stderr, $fmt, $($arg)*).unwrap()
@@ -56,10 +38,63 @@ fn main() {
         };
     }
 
+    // Auxiliary buffers are only written when an output directory is given with `--aov-dir`,
+    // and `--denoise` filters the beauty image guided by them. `--spectral` switches to spectral
+    // light transport, `--bdpt` to bidirectional path tracing, `--photons` to photon mapping
+    // and `--mlt` to Metropolis light transport, which collects no AOVs; at most one of them
+    // can be given. The scene is lit by a physical sky, whose sun position is set in degrees
+    // with `--sun-elevation` and `--sun-azimuth`, and whose haze with `--turbidity`. `--env`
+    // lights the scene with an equirectangular HDR image instead, optionally rotated with
+    // `--env-rotation` (in degrees) and scaled with `--env-intensity`. Unknown arguments are
+    // rejected.
+    let mut args = std::env::args().skip(1);
+    let mut aov_dir = None;
+    let mut denoise = false;
+    let mut spectral = false;
+    let mut bidirectional = false;
+    let mut photons = false;
+    let mut metropolis = false;
+    let mut env_path = None;
+    let mut env_rotation = 0.0;
+    let mut env_intensity = 1.0;
+    let mut sun_elevation = 35.0;
+    let mut sun_azimuth = 60.0;
+    let mut turbidity = 3.0;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--aov-dir" => aov_dir = args.next().map(PathBuf::from),
+            "--denoise" => denoise = true,
+            "--spectral" => spectral = true,
+            "--bdpt" => bidirectional = true,
+            "--photons" => photons = true,
+            "--mlt" => metropolis = true,
+            "--env" => env_path = args.next().map(PathBuf::from),
+            "--env-rotation" => env_rotation = parse_number(&arg, args.next()),
+            "--env-intensity" => env_intensity = parse_number(&arg, args.next()),
+            "--sun-elevation" => sun_elevation = parse_number(&arg, args.next()),
+            "--sun-azimuth" => sun_azimuth = parse_number(&arg, args.next()),
+            "--turbidity" => turbidity = parse_number(&arg, args.next()),
+            _ => {
+                eprintln!("error: unknown argument {}", arg);
+                std::process::exit(2);
+            }
+        }
+    }
+    let integrators = [spectral, bidirectional, photons, metropolis];
+    if integrators.iter().filter(|&&enabled| enabled).count() > 1 {
+        eprintln!("error: only one of --spectral, --bdpt, --photons and --mlt can be given");
+        std::process::exit(2);
+    }
+    let collect_aovs = aov_dir.is_some() || denoise;
+    if metropolis && collect_aovs {
+        eprintln!("error: --mlt cannot be combined with --denoise or --aov-dir");
+        std::process::exit(2);
+    }
+
     // Image
     let aspect_ratio = 16.0 / 9.0;
     let image_width = 700;
-    let image_height = (image_width as f64 / aspect_ratio) as i32;
+    let image_height = (image_width as f64 / aspect_ratio) as usize;
     let samples_per_pixel = 100;
     let max_depth = 50;
 
@@ -70,30 +105,135 @@ fn main() {
     let material_left = Metal::new(rgb!(0.8, 0.8, 0.8), 0.3);
     let material_right = Metal::new(rgb!(0.8, 0.8, 0.8), 1.0);
 
-    world.add(Sphere::new(v3!(0.0, -100.5, -1.0), 100.0, material_ground));
+    world.add(Plane::new(
+        v3!(0.0, -0.5, 0.0),
+        v3!(0.0, 1.0, 0.0),
+        material_ground,
+    ));
     world.add(Sphere::new(v3!(0.0, 0.0, -1.0), 0.5, material_center));
     world.add(Sphere::new(v3!(-1.0, 0.0, -1.0), 0.5, material_left));
     world.add(Sphere::new(v3!(1.0, 0.0, -1.0), 0.5, material_right));
 
+    // The physical sky is in kcd/m², which is compensated by the exposure.
+    let (scene, exposure) = match env_path {
+        Some(path) => {
+            let map = match EquirectMap::open(&path) {
+                Ok(map) => map,
+                Err(error) => {
+                    eprintln!("error: cannot open {}: {}", path.display(), error);
+                    std::process::exit(2);
+                }
+            };
+            let map = map
+                .with_rotation(env_rotation)
+                .with_intensity(env_intensity);
+            (Scene::new(world, map), 0.0)
+        }
+        None => {
+            let sun = PhysicalSky::sun_direction(sun_elevation, sun_azimuth);
+            let sky = PhysicalSky::new(sun, turbidity, rgb!(0.3, 0.3, 0.3));
+            (Scene::new(world, sky), -5.0)
+        }
+    };
+    let integrator = PathTracer::new(max_depth);
+    let bdpt = Bdpt::new(max_depth);
+    let photon_mapper = if photons {
+        Some(PhotonMapper::new(&scene, 200_000, 100_000, max_depth))
+    } else {
+        None
+    };
+
     // Camera
     let camera = Camera::default();
 
+    // Film
+    let mut film = Film::new(image_width, image_height, MitchellFilter::default());
+    film.set_splat_scale(1.0 / samples_per_pixel as f64);
+    let tone_mapping = ToneMapping {
+        exposure,
+        operator: ToneMapOperator::AcesFilmic,
+        transfer: TransferFunction::Srgb,
+    };
+    let mut aovs = AovBuffers::new(image_width, image_height);
+
     // Render
-    stdout!("P3\n{} {}\n255\n", image_width, image_height);
-
-    for h in (0..image_height).rev() {
-        // Prints how many scanlines left.
-        stderr!("\rScanlines remaining: {:<5}", h);
-
-        for w in 0..image_width {
-            let mut pixel_color = Rgb::default();
-            for _s in 0..samples_per_pixel {
-                let u = (w as f64 + random_f64()) / (image_width - 1) as f64;
-                let v = (h as f64 + random_f64()) / (image_height - 1) as f64;
-                let ray = camera.get_ray(u, v);
-                pixel_color += ray_color(&ray, &world, max_depth);
+    if metropolis {
+        let mut mlt = Mlt::new(max_depth);
+        mlt.mutations_per_pixel = samples_per_pixel;
+        mlt.render(&scene, &camera, &mut film);
+    } else {
+        for j in 0..image_height {
+            // Prints how many scanlines left.
+            stderr!("\rScanlines remaining: {:<5}", image_height - j);
+
+            for i in 0..image_width {
+                for _s in 0..samples_per_pixel {
+                    let x = i as f64 + random_f64();
+                    let y = j as f64 + random_f64();
+                    let u = x / image_width as f64;
+                    let v = 1.0 - y / image_height as f64;
+                    let ray = camera.get_ray(u, v);
+                    // The AOVs are taken from the first hit of the integrator.
+                    let first_hit = |rec: Option<&HitRecord>| {
+                        if collect_aovs {
+                            aovs.add_sample(i, j, &ray, rec);
+                        }
+                    };
+                    let color = if let Some(photon_mapper) = &photon_mapper {
+                        photon_mapper.radiance_with_first_hit(&ray, &scene, first_hit)
+                    } else if bidirectional {
+                        let (width, height) = (image_width as f64, image_height as f64);
+                        let splat = |u: f64, v: f64, color| {
+                            film.add_splat(u * width, (1.0 - v) * height, color)
+                        };
+                        bdpt.radiance_with_first_hit(&ray, &camera, &scene, splat, first_hit)
+                    } else if spectral {
+                        let mut wavelengths = SampledWavelengths::sample_visible(random_f64());
+                        integrator
+                            .spectral_radiance_with_first_hit(
+                                &ray,
+                                &scene,
+                                &mut wavelengths,
+                                first_hit,
+                            )
+                            .to_rgb(&wavelengths)
+                    } else {
+                        integrator.radiance_with_first_hit(&ray, &scene, first_hit)
+                    };
+                    film.add_sample(x, y, color);
+                }
             }
-            pixel_color.write(&mut stdout, samples_per_pixel).unwrap();
+        }
+    }
+
+    let mut image = film.to_image();
+    if denoise {
+        image = Denoiser::default().denoise(&image, &Features::from_aovs(&aovs));
+    }
+    image.write_ppm(&mut stdout, &tone_mapping).unwrap();
+
+    if let Some(dir) = aov_dir {
+        for &aov in &Aov::ALL {
+            let file = File::create(dir.join(format!("{}.pfm", aov.name()))).unwrap();
+            let mut file = BufWriter::new(file);
+            aovs.image(aov).write_pfm(&mut file).unwrap();
+        }
+        for id in aovs.object_ids() {
+            let file = File::create(dir.join(format!("mask_{}.pfm", id))).unwrap();
+            let mut file = BufWriter::new(file);
+            aovs.object_mask(id).write_pfm(&mut file).unwrap();
+        }
+    }
+}
+
+/// Parses the value of the command line option `name`, exiting with a usage error if it is
+/// missing or not a number.
+fn parse_number(name: &str, value: Option<String>) -> f64 {
+    match value.as_deref().map(str::parse) {
+        Some(Ok(number)) => number,
+        _ => {
+            eprintln!("error: {} expects a number", name);
+            std::process::exit(2);
         }
     }
 }
@@ -1,60 +0,0 @@
-//! Materials.
-use crate::prelude::*;
-use std::fmt::Debug;
-
-pub trait Material: Debug + Send + Sync {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Rgb, Ray)>;
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct Lambertian {
-    albedo: Rgb,
-}
-
-impl Lambertian {
-    pub fn new(albedo: Rgb) -> Lambertian {
-        Lambertian { albedo }
-    }
-}
-
-impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<(Rgb, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
-
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
-        }
-
-        let scattered = Ray::new(rec.p, scatter_direction);
-        let attenuation = self.albedo;
-        Some((attenuation, scattered))
-    }
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct Metal {
-    albedo: Rgb,
-    fuzz: f64,
-}
-
-impl Metal {
-    pub fn new(albedo: Rgb, fuzz: f64) -> Self {
-        Metal { albedo, fuzz }
-    }
-}
-
-impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<(Rgb, Ray)> {
-        let reflected = ray_in.direction.normal().reflect(rec.normal);
-        let scattered = Ray::new(
-            rec.p,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(&mut rand::thread_rng()),
-        );
-        let attenuation = self.albedo;
-        if scattered.direction.dot(rec.normal) > 0.0 {
-            Some((attenuation, scattered))
-        } else {
-            None
-        }
-    }
-}
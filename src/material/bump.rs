@@ -0,0 +1,236 @@
+//! Surface detail through perturbed shading normals.
+use crate::prelude::*;
+use crate::texture::Texture;
+
+/// Perturbs the shading normal of `rec` within its tangent frame.
+///
+/// `perturb` receives the unit normal and the unit tangents along `u` and `v`, and returns the
+/// new normal, which is then oriented to the same side as the original.
+fn with_normal<'world>(
+    rec: &HitRecord<'world>,
+    perturb: impl FnOnce(Vec3, Vec3, Vec3) -> Vec3,
+) -> HitRecord<'world> {
+    let mut rec = rec.clone();
+    if rec.dpdu.near_zero() || rec.dpdv.near_zero() {
+        return rec;
+    }
+
+    let normal = rec.normal;
+    let tangent = (rec.dpdu - rec.dpdu.dot(normal) * normal).normal();
+    let bitangent = normal.cross(tangent);
+    let bitangent = if bitangent.dot(rec.dpdv) < 0.0 {
+        -bitangent
+    } else {
+        bitangent
+    };
+
+    let perturbed = perturb(normal, tangent, bitangent);
+    if !perturbed.near_zero() {
+        let perturbed = perturbed.normal();
+        rec.normal = if perturbed.dot(normal) < 0.0 {
+            -perturbed
+        } else {
+            perturbed
+        };
+    }
+    rec
+}
+
+/// Bump mapping: treats a scalar texture as a height field over the surface and shades `base`
+/// with the normals of the displaced surface, without moving any geometry.
+#[derive(Debug)]
+pub struct BumpMapped {
+    base: Box<dyn Material>,
+    height: Box<dyn Texture>,
+    /// Heights are scaled by this factor, in world units.
+    strength: f64,
+}
+
+impl BumpMapped {
+    pub fn new(
+        base: impl Material + 'static,
+        height: impl Texture + 'static,
+        strength: f64,
+    ) -> Self {
+        BumpMapped {
+            base: Box::new(base),
+            height: Box::new(height),
+            strength,
+        }
+    }
+
+    fn perturb<'world>(&self, rec: &HitRecord<'world>) -> HitRecord<'world> {
+        // The finite difference step in surface coordinates.
+        const DELTA: f64 = 1.0e-4;
+
+        let height = |du: f64, dv: f64| {
+            let p = rec.p + du * rec.dpdu + dv * rec.dpdv;
+            self.strength * self.height.scalar(rec.u + du, rec.v + dv, p)
+        };
+        let h = height(0.0, 0.0);
+        let dhdu = (height(DELTA, 0.0) - h) / DELTA;
+        let dhdv = (height(0.0, DELTA) - h) / DELTA;
+
+        let (dpdu, dpdv) = (rec.dpdu, rec.dpdv);
+        with_normal(rec, |normal, _, _| {
+            // Displace the tangents along the unperturbed normal, oriented like `dpdu x dpdv`.
+            let outward = if dpdu.cross(dpdv).dot(normal) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            (dpdu + dhdu * outward).cross(dpdv + dhdv * outward)
+        })
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        self.base.scatter(ray_in, &self.perturb(rec))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        self.base.eval(ray_in, &self.perturb(rec), direction)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Rgb {
+        self.base.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.base.shading_normal(&self.perturb(rec))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Rgb {
+        self.base.emitted(&self.perturb(rec))
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.base.opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+}
+
+/// Tangent-space normal mapping: shades `base` with normals read from a texture, whose red,
+/// green and blue channels encode the tangent, bitangent and normal components in [0, 1].
+///
+/// Follows the OpenGL convention where green points along increasing `v`.
+#[derive(Debug)]
+pub struct NormalMapped {
+    base: Box<dyn Material>,
+    normal_map: Box<dyn Texture>,
+}
+
+impl NormalMapped {
+    pub fn new(base: impl Material + 'static, normal_map: impl Texture + 'static) -> Self {
+        NormalMapped {
+            base: Box::new(base),
+            normal_map: Box::new(normal_map),
+        }
+    }
+
+    fn perturb<'world>(&self, rec: &HitRecord<'world>) -> HitRecord<'world> {
+        let encoded = self.normal_map.value(rec.u, rec.v, rec.p);
+        let local = 2.0 * Vec3::from(encoded) - Vec3::new(1.0, 1.0, 1.0);
+        with_normal(rec, |normal, tangent, bitangent| {
+            local.x * tangent + local.y * bitangent + local.z * normal
+        })
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        self.base.scatter(ray_in, &self.perturb(rec))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        self.base.eval(ray_in, &self.perturb(rec), direction)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Rgb {
+        self.base.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.base.shading_normal(&self.perturb(rec))
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Rgb {
+        self.base.emitted(&self.perturb(rec))
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.base.opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    /// A height field rising along `u`.
+    #[derive(Debug)]
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _p: Point3) -> Rgb {
+            Rgb::from(u)
+        }
+    }
+
+    fn record(material: &dyn Material, normal: Vec3) -> HitRecord<'_> {
+        let mut rec = HitRecord::new(Point3::default(), 1.0, Some(material));
+        rec.normal = normal;
+        rec.dpdu = Vec3::new(2.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 1.0, 0.0);
+        rec.u = 0.5;
+        rec.v = 0.5;
+        rec
+    }
+
+    #[test]
+    fn bumps_tilt_the_normal_away_from_the_slope() {
+        let material = BumpMapped::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5)), Ramp, 0.5);
+
+        // The height rises by 0.5 over 2 units of x.
+        let expected = Vec3::new(-0.25, 0.0, 1.0).normal();
+        let rec = record(&material, Vec3::new(0.0, 0.0, 1.0));
+        let normal = material.shading_normal(&rec);
+        assert!((normal - expected).len() < 1.0e-9, "{:?}", normal);
+
+        // Seen from below, the same surface is shaded with the opposite normal.
+        let rec = record(&material, Vec3::new(0.0, 0.0, -1.0));
+        let normal = material.shading_normal(&rec);
+        assert!((normal + expected).len() < 1.0e-9, "{:?}", normal);
+
+        // Flat height fields leave the normal alone.
+        let flat = BumpMapped::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5)), 0.3, 1.0);
+        let rec = record(&flat, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(flat.shading_normal(&rec), Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn normal_maps_are_read_in_the_tangent_frame() {
+        let local = Vec3::new(0.3, -0.4, 1.0).normal();
+        let encoded = Rgb::from(0.5 * (local + Vec3::new(1.0, 1.0, 1.0)));
+        let material = NormalMapped::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5)), encoded);
+
+        let rec = record(&material, Vec3::new(0.0, 0.0, 1.0));
+        let normal = material.shading_normal(&rec);
+        assert!((normal - local).len() < 1.0e-9, "{:?}", normal);
+
+        // The bitangent keeps pointing along `v` when the normal is flipped.
+        let rec = record(&material, Vec3::new(0.0, 0.0, -1.0));
+        let normal = material.shading_normal(&rec);
+        let expected = Vec3::new(local.x, local.y, -local.z);
+        assert!((normal - expected).len() < 1.0e-9, "{:?}", normal);
+    }
+}
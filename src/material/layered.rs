@@ -0,0 +1,405 @@
+//! Combinators that build new [`Material`]s out of existing ones.
+use crate::microfacet::*;
+use crate::onb::Onb;
+use crate::prelude::*;
+use crate::texture::Texture;
+use crate::util::random_f64;
+
+/// A blend of two materials, e.g. dirt over metal.
+///
+/// Each scattering event stochastically picks `second` with probability `weight` and `first`
+/// otherwise, which on average yields the linear blend of both.
+#[derive(Debug)]
+pub struct Mix {
+    first: Box<dyn Material>,
+    second: Box<dyn Material>,
+    weight: Box<dyn Texture>,
+}
+
+impl Mix {
+    /// Creates a blend of `first` and `second`, where the scalar `weight` in [0, 1] is the
+    /// amount of `second`.
+    pub fn new(
+        first: impl Material + 'static,
+        second: impl Material + 'static,
+        weight: impl Texture + 'static,
+    ) -> Self {
+        Mix {
+            first: Box::new(first),
+            second: Box::new(second),
+            weight: Box::new(weight),
+        }
+    }
+
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        self.weight.scalar(rec.u, rec.v, rec.p).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        if random_f64() < self.weight(rec) {
+            self.second.scatter(ray_in, rec)
+        } else {
+            self.first.scatter(ray_in, rec)
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        let weight = self.weight(rec);
+        let (first, first_pdf) = self.first.eval(ray_in, rec, direction)?;
+        let (second, second_pdf) = self.second.eval(ray_in, rec, direction)?;
+        Some((
+            (1.0 - weight) * first + weight * second,
+            (1.0 - weight) * first_pdf + weight * second_pdf,
+        ))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Rgb {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.albedo(rec) + weight * self.second.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        let weight = self.weight(rec);
+        let normal = (1.0 - weight) * self.first.shading_normal(rec)
+            + weight * self.second.shading_normal(rec);
+        if normal.near_zero() {
+            rec.normal
+        } else {
+            normal.normal()
+        }
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Rgb {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.emitted(rec) + weight * self.second.emitted(rec)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let weight = self.weight(rec);
+        (1.0 - weight) * self.first.opacity(rec) + weight * self.second.opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.first.dispersive() || self.second.dispersive()
+    }
+}
+
+/// A thin dielectric clear coat over any base material, e.g. car paint or varnished wood.
+///
+/// Light is reflected off the coat with the Fresnel reflectance of a GGX interface; the rest,
+/// given by the Fresnel reflectance towards the viewer at the macroscopic normal, passes through
+/// the coat, is scattered by the base and leaves through the coat again, being
+/// attenuated by its `color` on the way. Refraction at the coat and reflections between the coat
+/// and the base are neglected.
+#[derive(Debug)]
+pub struct Coated {
+    base: Box<dyn Material>,
+    ior: f64,
+    distribution: Ggx,
+    color: Rgb,
+}
+
+impl Coated {
+    /// Coats `base` with a clear layer of index of refraction `ior` and perceptual `roughness`.
+    pub fn new(base: impl Material + 'static, ior: f64, roughness: f64) -> Self {
+        Coated {
+            base: Box::new(base),
+            ior,
+            distribution: Ggx::from_roughness(roughness),
+            color: Rgb::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Coats `base` with a tinted layer, whose transmittance for a single pass at normal
+    /// incidence is `color`.
+    pub fn tinted(base: impl Material + 'static, ior: f64, roughness: f64, color: Rgb) -> Self {
+        Coated {
+            color,
+            ..Coated::new(base, ior, roughness)
+        }
+    }
+
+    /// The transmittance of the coat for light travelling at `cos_theta` to the normal outside
+    /// the coat.
+    fn transmittance(&self, cos_theta: f64) -> Rgb {
+        // The path length through the coat depends on the refracted angle.
+        let sin2_theta_t = (1.0 - cos_theta * cos_theta) / (self.ior * self.ior);
+        let cos_theta_t = (1.0 - sin2_theta_t).max(1.0e-4).sqrt();
+        self.color.map(|c| c.powf(1.0 / cos_theta_t))
+    }
+
+    /// The relative index of refraction of the coat, which is seen from its other side on back
+    /// faces.
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        }
+    }
+
+    /// The attenuation of light passing through the coat at `cos_theta_o` to the normal and
+    /// scattered by the base towards `cos_theta_i`, besides the reflectance of the coat towards
+    /// `cos_theta_o`.
+    fn base_weight(&self, cos_theta_o: f64, cos_theta_i: f64, eta: f64) -> Rgb {
+        if cos_theta_i <= 0.0 {
+            // Transmission through the base is not affected by the coat.
+            return Rgb::from(1.0);
+        }
+        let exit = 1.0 - fresnel_dielectric(cos_theta_i, eta);
+        self.transmittance(cos_theta_o) * self.transmittance(cos_theta_i) * exit
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // Choosing the coat proportionally to its reflectance towards `wo` cancels the Fresnel
+        // term from the base.
+        let eta = self.eta(rec);
+        let coat_probability = fresnel_dielectric(wo.z, eta);
+        if random_f64() < coat_probability {
+            let wm = self
+                .distribution
+                .sample_visible_normal(wo, random_f64(), random_f64());
+            let wi = -wo.reflect(wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            let weight = self.distribution.g(wo, wi) / self.distribution.g1(wo)
+                * fresnel_dielectric(wo.dot(wm), eta)
+                / coat_probability;
+            return Some(Scatter::new(
+                Rgb::from(weight),
+                Ray::new(rec.p, frame.to_world(wi)),
+            ));
+        }
+
+        let mut scatter = self.base.scatter(ray_in, rec)?;
+        let cos_theta_i = scatter.ray.direction.normal().dot(rec.normal);
+        scatter.attenuation *= self.base_weight(wo.z, cos_theta_i, eta);
+        Some(scatter)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        let wi = frame.to_local(direction.normal());
+        if wo.z <= 0.0 {
+            return Some((Rgb::default(), 0.0));
+        }
+
+        let eta = self.eta(rec);
+        let coat_probability = fresnel_dielectric(wo.z, eta);
+        let (base, base_pdf) = self.base.eval(ray_in, rec, direction)?;
+        let mut value = (1.0 - coat_probability) * base * self.base_weight(wo.z, wi.z, eta);
+        let mut pdf = (1.0 - coat_probability) * base_pdf;
+
+        if let Some((f, p, wm)) = self.distribution.reflection(wo, wi) {
+            value += Rgb::from(f * fresnel_dielectric(wo.dot(wm), eta));
+            pdf += coat_probability * p;
+        }
+
+        Some((value, pdf))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Rgb {
+        self.base.albedo(rec) * self.color * self.color
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.base.shading_normal(rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Rgb {
+        self.base.emitted(rec) * self.color
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.base.opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sampler::{with_sampler, IndependentSampler};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn record(front_face: bool) -> HitRecord<'static> {
+        let mut rec = HitRecord::new(Point3::default(), 1.0, None);
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.front_face = front_face;
+        rec
+    }
+
+    /// A ray arriving at the origin at `cos_theta` to the normal.
+    fn incoming(cos_theta: f64) -> Ray {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        Ray::new(
+            Point3::new(sin_theta, 0.0, cos_theta),
+            Vec3::new(-sin_theta, 0.0, -cos_theta),
+        )
+    }
+
+    fn seeded<R>(seed: u64, f: impl FnOnce() -> R) -> R {
+        with_sampler(Rc::new(RefCell::new(IndependentSampler::new(seed))), f)
+    }
+
+    #[test]
+    fn mix_blends_both_materials_by_weight() {
+        let mix = Mix::new(
+            Lambertian::new(Rgb::new(1.0, 0.0, 0.0)),
+            Lambertian::new(Rgb::new(0.0, 0.0, 1.0)),
+            0.25,
+        );
+        let rec = record(true);
+        let ray = incoming(0.8);
+
+        let direction = Vec3::new(0.0, 0.6, 0.8);
+        let (value, pdf) = mix.eval(&ray, &rec, direction).unwrap();
+        let expected = Rgb::new(0.75, 0.0, 0.25) * 0.8 / PI;
+        assert!((value - expected).len() < 1.0e-12, "{:?}", value);
+        assert!((pdf - 0.8 / PI).abs() < 1.0e-12);
+        assert!((mix.albedo(&rec) - Rgb::new(0.75, 0.0, 0.25)).len() < 1.0e-12);
+
+        // Scattering picks the second material a quarter of the time.
+        let n = 20_000;
+        let second = seeded(33, || {
+            (0..n)
+                .filter(|_| mix.scatter(&ray, &rec).unwrap().attenuation.b > 0.0)
+                .count()
+        });
+        let fraction = second as f64 / n as f64;
+        assert!((fraction - 0.25).abs() < 0.01, "{}", fraction);
+    }
+
+    #[test]
+    fn coats_do_not_create_energy() {
+        let coated = Coated::new(Lambertian::new(Rgb::from(1.0)), 1.5, 0.3);
+        for &front_face in &[true, false] {
+            for &cos_theta in &[1.0, 0.5, 0.1] {
+                let rec = record(front_face);
+                let ray = incoming(cos_theta);
+                let n = 20_000;
+                let reflected = seeded(34, || {
+                    (0..n)
+                        .filter_map(|_| coated.scatter(&ray, &rec))
+                        .fold(Rgb::default(), |sum, scatter| sum + scatter.attenuation)
+                        / n as f64
+                });
+                assert!(
+                    reflected.r <= 1.0,
+                    "front face {} at cos {}: {:?}",
+                    front_face,
+                    cos_theta,
+                    reflected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn coats_reflect_by_the_fresnel_reflectance_of_the_side_they_are_seen_from() {
+        // A smooth coat reflects into the mirror direction, the base everywhere else.
+        let coated = Coated::new(Lambertian::new(Rgb::from(1.0)), 1.5, 0.0);
+        let ray = incoming(0.5);
+        let mirror = Vec3::new(ray.direction.x, 0.0, -ray.direction.z);
+        let coat_fraction = |front_face: bool| {
+            let rec = record(front_face);
+            let n = 20_000;
+            let coat = seeded(35, || {
+                (0..n)
+                    .filter_map(|_| coated.scatter(&ray, &rec))
+                    .filter(|scatter| (scatter.ray.direction.normal() - mirror).len() < 1.0e-2)
+                    .count()
+            });
+            coat as f64 / n as f64
+        };
+
+        let fraction = coat_fraction(true);
+        let expected = fresnel_dielectric(0.5, 1.5);
+        assert!(
+            (fraction - expected).abs() < 0.01,
+            "{} != {}",
+            fraction,
+            expected
+        );
+        // From inside, light at 60° is totally reflected.
+        let fraction = coat_fraction(false);
+        assert!(fraction > 0.999, "{}", fraction);
+    }
+
+    #[test]
+    fn coats_are_evaluated_like_they_scatter() {
+        let coated = Coated::tinted(
+            Lambertian::new(Rgb::new(0.9, 0.5, 0.2)),
+            1.5,
+            0.5,
+            Rgb::new(0.9, 0.9, 0.6),
+        );
+        for &front_face in &[true, false] {
+            for &cos_theta in &[0.9, 0.4] {
+                let rec = record(front_face);
+                let ray = incoming(cos_theta);
+                let n = 100_000;
+                let (scattered, evaluated, pdf, fraction) = seeded(36, || {
+                    let samples: Vec<_> =
+                        (0..n).filter_map(|_| coated.scatter(&ray, &rec)).collect();
+                    let scattered = samples
+                        .iter()
+                        .fold(Rgb::default(), |sum, scatter| sum + scatter.attenuation);
+
+                    // Integrate eval over uniformly distributed directions.
+                    let (mut evaluated, mut pdf) = (Rgb::default(), 0.0);
+                    for _ in 0..n {
+                        let z = 1.0 - 2.0 * random_f64();
+                        let r = (1.0 - z * z).sqrt();
+                        let phi = 2.0 * PI * random_f64();
+                        let direction = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                        let (value, density) = coated.eval(&ray, &rec, direction).unwrap();
+                        evaluated += value * 4.0 * PI;
+                        pdf += density * 4.0 * PI;
+                    }
+                    let fraction = samples.len() as f64 / n as f64;
+                    (
+                        scattered / n as f64,
+                        evaluated / n as f64,
+                        pdf / n as f64,
+                        fraction,
+                    )
+                });
+
+                let message = format!("front face {} at cos {}", front_face, cos_theta);
+                assert!(
+                    (scattered - evaluated).len() < 0.02,
+                    "{}: {:?} != {:?}",
+                    message,
+                    scattered,
+                    evaluated
+                );
+                // Directions scattered below the surface by the coat are lost.
+                assert!(
+                    (pdf - fraction).abs() < 0.03,
+                    "{}: {} != {}",
+                    message,
+                    pdf,
+                    fraction
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Opacity masks for cutout geometry.
+use crate::prelude::*;
+use crate::texture::Texture;
+
+/// Cuts out the parts of a surface where a scalar opacity texture is below one, e.g. for leaves,
+/// fences and decals. The remaining parts are shaded with `base`.
+#[derive(Debug)]
+pub struct Masked {
+    base: Box<dyn Material>,
+    opacity: Box<dyn Texture>,
+}
+
+impl Masked {
+    pub fn new(base: impl Material + 'static, opacity: impl Texture + 'static) -> Self {
+        Masked {
+            base: Box::new(base),
+            opacity: Box::new(opacity),
+        }
+    }
+}
+
+impl Material for Masked {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        self.base.scatter(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        self.base.eval(ray_in, rec, direction)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Rgb {
+        self.base.albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.base.shading_normal(rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Rgb {
+        self.base.emitted(rec)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.opacity.scalar(rec.u, rec.v, rec.p).clamp(0.0, 1.0) * self.base.opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+}
@@ -0,0 +1,270 @@
+//! Materials.
+mod bump;
+mod layered;
+mod masked;
+mod principled;
+mod rough;
+mod subsurface;
+
+pub use self::bump::{BumpMapped, NormalMapped};
+pub use self::layered::{Coated, Mix};
+pub use self::masked::Masked;
+pub use self::principled::Principled;
+pub use self::rough::{RoughConductor, RoughDielectric};
+pub use self::subsurface::Subsurface;
+
+use crate::prelude::*;
+use crate::sampler::SamplerRng;
+use crate::util::random_f64;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+pub trait Material: Debug + Send + Sync {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter>;
+
+    /// Evaluates scattering from `ray_in` towards `direction`, which integrators use to connect
+    /// the hit point to light sources.
+    ///
+    /// Returns the BSDF times the cosine between `direction` and the shading normal, and the
+    /// solid angle density of [`Material::scatter`] sampling `direction`. Materials with
+    /// perfectly specular components cannot be evaluated and return `None`, the default.
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Option<(Rgb, f64)> {
+        None
+    }
+
+    /// Returns the surface albedo at the hit point, as reported in the albedo AOV.
+    ///
+    /// Defaults to white for materials without a meaningful albedo.
+    fn albedo(&self, _rec: &HitRecord) -> Rgb {
+        Rgb::new(1.0, 1.0, 1.0)
+    }
+
+    /// Returns the normal the hit point is shaded with, as reported in the normal AOV.
+    ///
+    /// Defaults to the normal of the hit, which materials perturbing it override.
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.normal
+    }
+
+    /// Returns the radiance emitted from the hit point towards the incoming ray.
+    fn emitted(&self, _rec: &HitRecord) -> Rgb {
+        Rgb::default()
+    }
+
+    /// Returns the opacity in [0, 1] at the hit point.
+    ///
+    /// Hittables discard hits on transparent parts and keep searching along the ray, which cuts
+    /// the geometry out. Partial opacity keeps that fraction of the hits, chosen by a hash of the
+    /// hit point.
+    fn opacity(&self, _rec: &HitRecord) -> f64 {
+        1.0
+    }
+
+    /// Whether scattering depends on the wavelength carried by the incoming ray.
+    ///
+    /// Spectral integrators keep only the hero wavelength of a path that hits a dispersive
+    /// material.
+    fn dispersive(&self) -> bool {
+        false
+    }
+}
+
+/// Light scattered by a [`Material`] at a hit point.
+#[derive(Debug, Clone, Copy)]
+pub struct Scatter {
+    /// The throughput of the scattered ray, i.e. the BSDF times the cosine divided by the
+    /// density of sampling the ray.
+    pub attenuation: Rgb,
+    pub ray: Ray,
+    /// Whether the ray continues below the surface, e.g. a random walk through a translucent
+    /// object, rather than leaving the hit point. Light does not interact with the surface at
+    /// such vertices, so integrators neither sample lights nor evaluate the material there.
+    pub below_surface: bool,
+}
+
+impl Scatter {
+    pub fn new(attenuation: Rgb, ray: Ray) -> Self {
+        Scatter {
+            attenuation,
+            ray,
+            below_surface: false,
+        }
+    }
+
+    /// Creates a scatter continuing below the surface.
+    pub fn below_surface(attenuation: Rgb, ray: Ray) -> Self {
+        Scatter {
+            attenuation,
+            ray,
+            below_surface: true,
+        }
+    }
+}
+
+/// Shared materials, e.g. one material referenced by every triangle of a mesh.
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        self.as_ref().scatter(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        self.as_ref().eval(ray_in, rec, direction)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Rgb {
+        self.as_ref().albedo(rec)
+    }
+
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.as_ref().shading_normal(rec)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Rgb {
+        self.as_ref().emitted(rec)
+    }
+
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.as_ref().opacity(rec)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.as_ref().dispersive()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lambertian {
+    albedo: Rgb,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Rgb) -> Lambertian {
+        Lambertian { albedo }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+
+        let scattered = Ray::new(rec.p, scatter_direction);
+        let attenuation = self.albedo;
+        Some(Scatter::new(attenuation, scattered))
+    }
+
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        let cosine = direction.normal().dot(rec.normal).max(0.0);
+        Some((self.albedo * cosine / PI, cosine / PI))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Rgb {
+        self.albedo
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metal {
+    albedo: Rgb,
+    fuzz: f64,
+}
+
+impl Metal {
+    pub fn new(albedo: Rgb, fuzz: f64) -> Self {
+        Metal { albedo, fuzz }
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let reflected = ray_in.direction.normal().reflect(rec.normal);
+        let scattered = Ray::new(
+            rec.p,
+            reflected + self.fuzz * Vec3::random_in_unit_sphere(&mut SamplerRng),
+        );
+        let attenuation = self.albedo;
+        if scattered.direction.dot(rec.normal) > 0.0 {
+            Some(Scatter::new(attenuation, scattered))
+        } else {
+            None
+        }
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Rgb {
+        self.albedo
+    }
+}
+
+/// A clear dielectric, such as glass or water, that both reflects and refracts.
+#[derive(Debug, Clone, Copy)]
+pub struct Dielectric {
+    /// The index of refraction at the sodium D line (589.3nm).
+    ior: f64,
+    /// The `B` coefficient of Cauchy's equation `n(λ) = A + B / λ²`, in square micrometers.
+    cauchy_b: f64,
+}
+
+impl Dielectric {
+    pub fn new(ior: f64) -> Self {
+        Dielectric { ior, cauchy_b: 0.0 }
+    }
+
+    /// Creates a dispersive dielectric following Cauchy's equation, e.g. `cauchy_b = 0.0042`
+    /// for BK7 crown glass or `0.0134` for dense flint glass.
+    ///
+    /// Dispersion is only visible in spectral mode; RGB rendering uses `ior`.
+    pub fn with_dispersion(ior: f64, cauchy_b: f64) -> Self {
+        Dielectric { ior, cauchy_b }
+    }
+
+    /// Returns the index of refraction at `wavelength` in nanometers.
+    pub fn ior(&self, wavelength: Option<f64>) -> f64 {
+        const SODIUM_D: f64 = 0.5893;
+        match wavelength {
+            Some(lambda) if self.cauchy_b != 0.0 => {
+                let lambda = lambda / 1000.0;
+                self.ior + self.cauchy_b * (1.0 / (lambda * lambda) - 1.0 / (SODIUM_D * SODIUM_D))
+            }
+            _ => self.ior,
+        }
+    }
+
+    /// Schlick's approximation of the Fresnel reflectance.
+    fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
+        let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
+        let r0 = r0 * r0;
+        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let ior = self.ior(ray_in.wavelength);
+        let refraction_ratio = if rec.front_face { 1.0 / ior } else { ior };
+
+        let unit_direction = ray_in.direction.normal();
+        let cos_theta = (-unit_direction).dot(rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        // Total internal reflection, or a Fresnel reflection chosen stochastically.
+        let direction = if refraction_ratio * sin_theta > 1.0
+            || Self::reflectance(cos_theta, refraction_ratio) > random_f64()
+        {
+            unit_direction.reflect(rec.normal)
+        } else {
+            unit_direction.refract(rec.normal, refraction_ratio)
+        };
+
+        Some(Scatter::new(
+            Rgb::new(1.0, 1.0, 1.0),
+            Ray::new(rec.p, direction),
+        ))
+    }
+
+    fn dispersive(&self) -> bool {
+        self.cauchy_b != 0.0
+    }
+}
@@ -0,0 +1,421 @@
+//! A Disney-style [`Principled`] uber material.
+use crate::microfacet::*;
+use crate::onb::Onb;
+use crate::prelude::*;
+use crate::sampler::SamplerRng;
+use crate::texture::Texture;
+use crate::util::random_f64;
+
+/// A single material covering most real-world surfaces, loosely following Burley,
+/// "Physically Based Shading at Disney" (2012) and its 2015 extension to transmission.
+///
+/// Every parameter is a [`Texture`]; plain [`Rgb`] and `f64` values act as constants. Scalar
+/// parameters are expected within [0, 1].
+///
+/// The material is a weighted sum of lobes:
+///
+/// - a Lambertian diffuse lobe with a grazing sheen taking its energy from it, scaled by
+///   `(1 - metallic) * (1 - transmission)` and by the energy not reflected by the specular lobe,
+/// - a GGX specular reflection with Schlick Fresnel, whose normal incidence reflectance blends
+///   from the dielectric `0.08 * specular` to `base_color` as `metallic` increases,
+/// - a GGX clear coat with a fixed index of refraction of 1.5, over all the other lobes, which
+///   are scaled by the energy it does not reflect,
+/// - a GGX rough glass lobe with index of refraction `ior`, scaled by
+///   `(1 - metallic) * transmission`.
+///
+/// Unlike the original Disney model, the clear coat also uses the GGX distribution.
+#[derive(Debug)]
+pub struct Principled {
+    pub base_color: Box<dyn Texture>,
+    pub metallic: Box<dyn Texture>,
+    pub roughness: Box<dyn Texture>,
+    pub specular: Box<dyn Texture>,
+    /// Tints the dielectric specular reflection towards the hue of `base_color`.
+    pub specular_tint: Box<dyn Texture>,
+    pub clearcoat: Box<dyn Texture>,
+    pub clearcoat_roughness: Box<dyn Texture>,
+    pub sheen: Box<dyn Texture>,
+    /// Tints the sheen towards the hue of `base_color`.
+    pub sheen_tint: Box<dyn Texture>,
+    pub transmission: Box<dyn Texture>,
+    pub ior: f64,
+    pub emission: Box<dyn Texture>,
+    pub emission_strength: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        Principled {
+            base_color: Box::new(Rgb::new(0.8, 0.8, 0.8)),
+            metallic: Box::new(0.0),
+            roughness: Box::new(0.5),
+            specular: Box::new(0.5),
+            specular_tint: Box::new(0.0),
+            clearcoat: Box::new(0.0),
+            clearcoat_roughness: Box::new(0.03),
+            sheen: Box::new(0.0),
+            sheen_tint: Box::new(0.5),
+            transmission: Box::new(0.0),
+            ior: 1.45,
+            emission: Box::new(Rgb::default()),
+            emission_strength: 1.0,
+        }
+    }
+}
+
+impl Principled {
+    /// Evaluates every parameter at the hit point.
+    fn lobes(&self, rec: &HitRecord, wo: Vec3) -> Lobes {
+        let (u, v, p) = (rec.u, rec.v, rec.p);
+        let scalar = |texture: &dyn Texture| texture.scalar(u, v, p).clamp(0.0, 1.0);
+
+        let base_color = self.base_color.value(u, v, p);
+        let roughness = scalar(self.roughness.as_ref());
+        let metallic = scalar(self.metallic.as_ref());
+        let transmission = scalar(self.transmission.as_ref());
+        let luminance = base_color.luminance();
+        let tint = if luminance > 0.0 {
+            base_color / luminance
+        } else {
+            Rgb::from(1.0)
+        };
+
+        let white = Rgb::from(1.0);
+        let specular_tint = scalar(self.specular_tint.as_ref());
+        let dielectric_f0 = 0.08
+            * scalar(self.specular.as_ref())
+            * (white * (1.0 - specular_tint) + tint * specular_tint);
+        let sheen_tint = scalar(self.sheen_tint.as_ref());
+
+        let specular_f0 = dielectric_f0 * (1.0 - metallic) + base_color * metallic;
+
+        // Approximates the energy taken by the dielectric specular lobe, so that the diffuse
+        // lobe below it does not gain energy at grazing angles.
+        let specular_energy = fresnel_schlick(wo.z, dielectric_f0).luminance();
+
+        let eta = if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+
+        // Approximates the energy reflected by the clear coat, which does not reach the lobes
+        // below it.
+        let clearcoat_weight = 0.25 * scalar(self.clearcoat.as_ref());
+        let base_weight = 1.0 - clearcoat_weight * fresnel_dielectric(wo.z.clamp(0.0, 1.0), 1.5);
+
+        let mut lobes = Lobes {
+            base_color,
+            base_weight,
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission) * (1.0 - specular_energy),
+            sheen: scalar(self.sheen.as_ref()) * (white * (1.0 - sheen_tint) + tint * sheen_tint),
+            // A bound on the directional albedo of the sheen, fitted to a numerical integration.
+            sheen_albedo: 0.1 * (1.0 - wo.z.clamp(0.0, 1.0)).powi(2),
+            specular_f0,
+            specular: Ggx::from_roughness(roughness),
+            clearcoat_weight,
+            clearcoat: Ggx::from_roughness(scalar(self.clearcoat_roughness.as_ref())),
+            glass_weight: (1.0 - metallic) * transmission,
+            eta,
+            probabilities: [0.0; 4],
+        };
+
+        // Choose lobes proportionally to a rough estimate of their reflectance towards `wo`.
+        let cos_theta_o = wo.z.clamp(0.0, 1.0);
+        let mut weights = [
+            base_weight * lobes.diffuse_weight * (luminance + lobes.sheen.luminance()),
+            base_weight
+                * (1.0 - lobes.glass_weight)
+                * fresnel_schlick(cos_theta_o, lobes.specular_f0).luminance(),
+            lobes.clearcoat_weight * fresnel_schlick(cos_theta_o, Rgb::from(0.04)).r,
+            base_weight * lobes.glass_weight,
+        ];
+        let total: f64 = weights.iter().sum();
+        if total > 0.0 {
+            weights.iter_mut().for_each(|w| *w /= total);
+        }
+        lobes.probabilities = weights;
+
+        lobes
+    }
+}
+
+/// The lobes of a [`Principled`] material evaluated at a hit point.
+struct Lobes {
+    base_color: Rgb,
+    /// The fraction of the light passing through the clear coat to the other lobes.
+    base_weight: f64,
+    diffuse_weight: f64,
+    sheen: Rgb,
+    /// The energy reflected by a white sheen towards `wo`, which the diffuse lobe gives up.
+    sheen_albedo: f64,
+    specular_f0: Rgb,
+    specular: Ggx,
+    clearcoat_weight: f64,
+    clearcoat: Ggx,
+    glass_weight: f64,
+    /// The ratio of the refractive index inside the surface over the outside.
+    eta: f64,
+    /// The probabilities of sampling the diffuse, specular, clear coat and glass lobes.
+    probabilities: [f64; 4],
+}
+
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const GLASS: usize = 3;
+
+impl Lobes {
+    /// Samples an incident direction from one of the lobes.
+    fn sample(&self, wo: Vec3) -> Vec3 {
+        let mut u = random_f64();
+        let mut lobe = GLASS;
+        for (i, &p) in self.probabilities.iter().enumerate() {
+            if u < p {
+                lobe = i;
+                break;
+            }
+            u -= p;
+        }
+
+        match lobe {
+            DIFFUSE => Vec3::random_cosine_direction(&mut SamplerRng),
+            SPECULAR | CLEARCOAT => {
+                let distribution = if lobe == SPECULAR {
+                    self.specular
+                } else {
+                    self.clearcoat
+                };
+                let wm = distribution.sample_visible_normal(wo, random_f64(), random_f64());
+                -wo.reflect(wm)
+            }
+            _ => {
+                let wm = self
+                    .specular
+                    .sample_visible_normal(wo, random_f64(), random_f64());
+                if random_f64() < fresnel_dielectric(wo.dot(wm), self.eta) {
+                    -wo.reflect(wm)
+                } else {
+                    (-wo).refract(wm, 1.0 / self.eta)
+                }
+            }
+        }
+    }
+
+    /// Returns the BSDF times the cosine of `wi`, and the density of [`Lobes::sample`]
+    /// generating `wi`.
+    fn eval(&self, wo: Vec3, wi: Vec3) -> (Rgb, f64) {
+        // The lobes below the clear coat, and the clear coat.
+        let mut value = Rgb::default();
+        let mut clearcoat = Rgb::default();
+        let mut pdf = 0.0;
+
+        if wi.z > 0.0 {
+            let wh = (wo + wi).normal();
+            let schlick_weight = (1.0 - wi.dot(wh).clamp(0.0, 1.0)).powi(5);
+            let remaining = |sheen: f64| (1.0 - sheen * self.sheen_albedo).max(0.0);
+            let diffuse = Rgb::new(
+                self.base_color.r * remaining(self.sheen.r),
+                self.base_color.g * remaining(self.sheen.g),
+                self.base_color.b * remaining(self.sheen.b),
+            );
+            value += self.diffuse_weight * (diffuse / PI + self.sheen * schlick_weight) * wi.z;
+            pdf += self.probabilities[DIFFUSE] * wi.z / PI;
+
+            if let Some((f, p, wm)) = self.specular.reflection(wo, wi) {
+                let fresnel = fresnel_schlick(wo.dot(wm), self.specular_f0);
+                value += (1.0 - self.glass_weight) * f * fresnel;
+                pdf += self.probabilities[SPECULAR] * p;
+
+                let fresnel = fresnel_dielectric(wo.dot(wm), self.eta);
+                value += Rgb::from(self.glass_weight * f * fresnel);
+                pdf += self.probabilities[GLASS] * p * fresnel;
+            }
+
+            if let Some((f, p, wm)) = self.clearcoat.reflection(wo, wi) {
+                let fresnel = fresnel_dielectric(wo.dot(wm), 1.5);
+                clearcoat = Rgb::from(self.clearcoat_weight * f * fresnel);
+                pdf += self.probabilities[CLEARCOAT] * p;
+            }
+        } else if let Some((f, p, wm)) = self.specular.transmission(wo, wi, self.eta) {
+            let transmittance = 1.0 - fresnel_dielectric(wo.dot(wm), self.eta);
+            value += self.glass_weight * f * transmittance * self.base_color;
+            pdf += self.probabilities[GLASS] * p * transmittance;
+        }
+
+        (self.base_weight * value + clearcoat, pdf)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(rec, wo);
+        let wi = lobes.sample(wo);
+        let (value, pdf) = lobes.eval(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(Scatter::new(
+            value / pdf,
+            Ray::new(rec.p, frame.to_world(wi)),
+        ))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        if wo.z <= 0.0 {
+            return Some((Rgb::default(), 0.0));
+        }
+
+        let lobes = self.lobes(rec, wo);
+        Some(lobes.eval(wo, frame.to_local(direction.normal())))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Rgb {
+        self.base_color.value(rec.u, rec.v, rec.p)
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Rgb {
+        if rec.front_face {
+            self.emission_strength * self.emission.value(rec.u, rec.v, rec.p)
+        } else {
+            Rgb::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::{with_sampler, IndependentSampler};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn record() -> HitRecord<'static> {
+        let mut rec = HitRecord::new(Point3::default(), 1.0, None);
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.front_face = true;
+        rec
+    }
+
+    /// Materials covering every lobe, with a white base color.
+    fn materials() -> Vec<Principled> {
+        let white = || Box::new(Rgb::from(1.0));
+        vec![
+            Principled {
+                base_color: white(),
+                ..Default::default()
+            },
+            Principled {
+                base_color: white(),
+                roughness: Box::new(0.1),
+                sheen: Box::new(1.0),
+                clearcoat: Box::new(1.0),
+                ..Default::default()
+            },
+            Principled {
+                base_color: white(),
+                metallic: Box::new(1.0),
+                roughness: Box::new(0.3),
+                ..Default::default()
+            },
+            Principled {
+                base_color: white(),
+                transmission: Box::new(1.0),
+                roughness: Box::new(0.2),
+                ..Default::default()
+            },
+        ]
+    }
+
+    /// Runs `f` with a seeded sampler providing the random numbers.
+    fn seeded<R>(seed: u64, f: impl FnOnce() -> R) -> R {
+        with_sampler(Rc::new(RefCell::new(IndependentSampler::new(seed))), f)
+    }
+
+    #[test]
+    fn white_materials_do_not_create_energy() {
+        for (i, material) in materials().iter().enumerate() {
+            for &cos_theta in &[1.0, 0.5, 0.1] {
+                let direction = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, -cos_theta);
+                let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), direction);
+                let n = 100_000;
+                let reflected = seeded(i as u64, || {
+                    (0..n)
+                        .filter_map(|_| material.scatter(&ray, &record()))
+                        .fold(Rgb::default(), |sum, scatter| sum + scatter.attenuation)
+                }) / n as f64;
+                assert!(
+                    reflected.max() < 1.02,
+                    "material {} at cos {}: {:?}",
+                    i,
+                    cos_theta,
+                    reflected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scattered_directions_match_their_evaluation() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vec3::new(0.6, 0.0, -0.8));
+        let rec = record();
+        for (i, material) in materials().iter().enumerate() {
+            seeded(i as u64, || {
+                for _ in 0..1000 {
+                    let Scatter {
+                        attenuation,
+                        ray: scattered,
+                        ..
+                    } = match material.scatter(&ray, &rec) {
+                        Some(scatter) => scatter,
+                        None => continue,
+                    };
+                    let (f, pdf) = material.eval(&ray, &rec, scattered.direction).unwrap();
+                    assert!(pdf > 0.0);
+                    assert!((f / pdf - attenuation).len() < 1.0e-9 * attenuation.len().max(1.0));
+                }
+            });
+
+            // The density integrates to the fraction of samples that scatter. Directions are
+            // drawn half uniformly and half by the material, which bounds the weights of the
+            // estimate even for sharp lobes.
+            let (mut integral, mut attempts, mut scattered) = (0.0, 0, 0);
+            seeded(i as u64, || {
+                for _ in 0..100_000 {
+                    let direction = if random_f64() < 0.5 {
+                        Vec3::random_unit_vector()
+                    } else {
+                        attempts += 1;
+                        match material.scatter(&ray, &rec) {
+                            Some(Scatter { ray, .. }) => {
+                                scattered += 1;
+                                ray.direction
+                            }
+                            None => continue,
+                        }
+                    };
+                    let pdf = material.eval(&ray, &rec, direction).unwrap().1;
+                    integral += pdf / (0.5 / (4.0 * PI) + 0.5 * pdf);
+                }
+            });
+            let integral = integral / 100_000.0;
+            let fraction = scattered as f64 / attempts as f64;
+            assert!(
+                (integral - fraction).abs() < 0.02,
+                "material {}: {} {}",
+                i,
+                integral,
+                fraction
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Physically based rough [`Material`]s built on the GGX microfacet distribution.
+use crate::microfacet::*;
+use crate::onb::Onb;
+use crate::prelude::*;
+use crate::util::random_f64;
+
+/// A rough metal described by its complex index of refraction `eta + i k`.
+///
+/// Reflection directions are importance sampled from the distribution of visible normals, so the
+/// sample weight reduces to `F * G2 / G1`. Only single scattering between microfacets is
+/// modeled, which loses some energy at high roughness.
+#[derive(Debug, Clone, Copy)]
+pub struct RoughConductor {
+    eta: Rgb,
+    k: Rgb,
+    distribution: Ggx,
+}
+
+impl RoughConductor {
+    /// Creates a conductor with per-channel complex index of refraction `eta + i k` and a
+    /// perceptual `roughness` in [0, 1].
+    pub fn new(eta: Rgb, k: Rgb, roughness: f64) -> Self {
+        RoughConductor {
+            eta,
+            k,
+            distribution: Ggx::from_roughness(roughness),
+        }
+    }
+
+    pub fn gold(roughness: f64) -> Self {
+        Self::new(
+            Rgb::new(0.143, 0.374, 1.442),
+            Rgb::new(3.983, 2.386, 1.603),
+            roughness,
+        )
+    }
+
+    pub fn silver(roughness: f64) -> Self {
+        Self::new(
+            Rgb::new(0.155, 0.117, 0.138),
+            Rgb::new(4.828, 3.122, 2.147),
+            roughness,
+        )
+    }
+
+    pub fn copper(roughness: f64) -> Self {
+        Self::new(
+            Rgb::new(0.200, 0.924, 1.102),
+            Rgb::new(3.912, 2.452, 2.142),
+            roughness,
+        )
+    }
+
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::new(
+            Rgb::new(1.657, 0.880, 0.521),
+            Rgb::new(9.224, 6.270, 4.837),
+            roughness,
+        )
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let wm = self
+            .distribution
+            .sample_visible_normal(wo, random_f64(), random_f64());
+        let wi = -wo.reflect(wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        let fresnel = fresnel_conductor(wo.dot(wm), self.eta, self.k);
+        let weight = self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        Some(Scatter::new(
+            fresnel * weight,
+            Ray::new(rec.p, frame.to_world(wi)),
+        ))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        let wi = frame.to_local(direction.normal());
+        match self.distribution.reflection(wo, wi) {
+            Some((value, pdf, wm)) => {
+                let fresnel = fresnel_conductor(wo.dot(wm), self.eta, self.k);
+                Some((fresnel * value, pdf))
+            }
+            None => Some((Rgb::default(), 0.0)),
+        }
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Rgb {
+        fresnel_conductor(1.0, self.eta, self.k)
+    }
+}
+
+/// A rough dielectric, such as frosted glass, that reflects and refracts through GGX
+/// microfacets following Walter et al., "Microfacet Models for Refraction through Rough
+/// Surfaces" (2007).
+#[derive(Debug, Clone, Copy)]
+pub struct RoughDielectric {
+    ior: f64,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    /// Creates a dielectric with index of refraction `ior` and a perceptual `roughness` in
+    /// [0, 1].
+    pub fn new(ior: f64, roughness: f64) -> Self {
+        RoughDielectric {
+            ior,
+            distribution: Ggx::from_roughness(roughness),
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        // The ratio of the refractive index on the far side over the near side.
+        let eta = if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+
+        let wm = self
+            .distribution
+            .sample_visible_normal(wo, random_f64(), random_f64());
+        let cos_theta_o = wo.dot(wm);
+        let fresnel = fresnel_dielectric(cos_theta_o, eta);
+
+        // Choosing between reflection and refraction proportionally to the Fresnel term cancels
+        // it from the sample weight.
+        let wi = if random_f64() < fresnel {
+            let wi = -wo.reflect(wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = (-wo).refract(wm, 1.0 / eta);
+            if wi.z >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let weight = self.distribution.g(wo, wi) / self.distribution.g1(wo);
+        Some(Scatter::new(
+            Rgb::from(weight),
+            Ray::new(rec.p, frame.to_world(wi)),
+        ))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        let frame = Onb::from_w(rec.normal);
+        let wo = frame.to_local(-ray_in.direction.normal());
+        let wi = frame.to_local(direction.normal());
+        let eta = if rec.front_face {
+            self.ior
+        } else {
+            1.0 / self.ior
+        };
+
+        let lobe = if wi.z > 0.0 {
+            self.distribution
+                .reflection(wo, wi)
+                .map(|(value, pdf, wm)| (value, pdf, fresnel_dielectric(wo.dot(wm), eta)))
+        } else {
+            self.distribution
+                .transmission(wo, wi, eta)
+                .map(|(value, pdf, wm)| (value, pdf, 1.0 - fresnel_dielectric(wo.dot(wm), eta)))
+        };
+        match lobe {
+            Some((value, pdf, probability)) => {
+                Some((Rgb::from(value * probability), pdf * probability))
+            }
+            None => Some((Rgb::default(), 0.0)),
+        }
+    }
+}
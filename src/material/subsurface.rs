@@ -0,0 +1,239 @@
+//! A [`Material`] scattering light below the surface of closed objects.
+use super::Material;
+use crate::onb::Onb;
+use crate::prelude::*;
+use crate::sampler::SamplerRng;
+use crate::util::random_f64;
+
+/// A translucent material such as skin, wax, marble or milk, where light enters the object,
+/// scatters many times inside it and leaves it somewhere else.
+///
+/// Light performs a random walk through a homogeneous medium filling the object, which must be
+/// closed and have outward facing normals. Crossing the surface transmits light diffusely in
+/// either direction. Inside, distances are sampled from one color channel picked at random and
+/// weighted against all three, and scattering is isotropic.
+///
+/// Every step of the walk is a bounce of the integrator, so media with a mean free path much
+/// shorter than the object need a higher maximum depth. Only the points where light leaves the
+/// object can be evaluated, so the other steps are scattered below the surface.
+#[derive(Debug, Clone, Copy)]
+pub struct Subsurface {
+    /// The color of the surface seen from afar, for the albedo AOV.
+    albedo: Rgb,
+    /// The probability of scattering rather than absorption at each interaction.
+    single_scattering_albedo: Rgb,
+    /// The extinction coefficient, i.e. the inverse of the mean free path.
+    sigma_t: Rgb,
+}
+
+impl Subsurface {
+    /// Creates a material with the given surface `albedo` and per-channel `mean_free_path`, in
+    /// scene units.
+    ///
+    /// The albedo is converted to the single-scattering albedo of the medium with the fit of
+    /// Chiang et al., "Practical and Controllable Subsurface Scattering for Production Path
+    /// Tracing" (2016).
+    pub fn new(albedo: Rgb, mean_free_path: Rgb) -> Self {
+        Subsurface {
+            albedo,
+            single_scattering_albedo: albedo.map(single_scattering_albedo),
+            sigma_t: mean_free_path.map(|d| 1.0 / d),
+        }
+    }
+
+    /// Creates a material from the absorption and (reduced) scattering coefficients of its
+    /// medium, in inverse scene units.
+    pub fn from_coefficients(sigma_a: Rgb, sigma_s: Rgb) -> Self {
+        let sigma_t = sigma_a + sigma_s;
+        let single_scattering_albedo = sigma_s / sigma_t;
+        Subsurface {
+            albedo: single_scattering_albedo.map(surface_albedo),
+            single_scattering_albedo,
+            sigma_t,
+        }
+    }
+
+    /// Marble measured by Jensen et al., "A Practical Model for Subsurface Light Transport"
+    /// (2001), in a scene measured in units of `unit` millimeters.
+    pub fn marble(unit: f64) -> Self {
+        Self::from_coefficients(
+            unit * Rgb::new(0.0021, 0.0041, 0.0071),
+            unit * Rgb::new(2.19, 2.62, 3.00),
+        )
+    }
+
+    /// Skin measured by Jensen et al. (2001), in a scene measured in units of `unit`
+    /// millimeters.
+    pub fn skin(unit: f64) -> Self {
+        Self::from_coefficients(
+            unit * Rgb::new(0.032, 0.17, 0.48),
+            unit * Rgb::new(0.74, 0.88, 1.01),
+        )
+    }
+
+    /// An off-white candle wax, in a scene measured in units of `unit` millimeters.
+    pub fn wax(unit: f64) -> Self {
+        Self::new(Rgb::new(0.92, 0.85, 0.70), Rgb::new(4.0, 3.0, 1.8) / unit)
+    }
+
+    /// Whole milk measured by Jensen et al. (2001), in a scene measured in units of `unit`
+    /// millimeters.
+    pub fn whole_milk(unit: f64) -> Self {
+        Self::from_coefficients(
+            unit * Rgb::new(0.0011, 0.0024, 0.014),
+            unit * Rgb::new(2.55, 3.21, 3.77),
+        )
+    }
+
+    fn transmittance(&self, distance: f64) -> Rgb {
+        self.sigma_t.map(|sigma_t| (-sigma_t * distance).exp())
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        // The normal faces the incoming ray, so both entering and leaving light is transmitted
+        // around the opposite direction.
+        let frame = Onb::from_w(-rec.normal);
+        let diffuse = frame.to_world(Vec3::random_cosine_direction(&mut SamplerRng));
+        if rec.front_face {
+            // Entering light is only evaluated where it leaves the object again, as it cannot be
+            // connected through the medium to a point further along the walk.
+            return Some(Scatter::below_surface(
+                Rgb::new(1.0, 1.0, 1.0),
+                Ray::new(rec.p, diffuse),
+            ));
+        }
+
+        // The ray travelled inside the object up to the hit point.
+        let length = ray_in.direction.len();
+        let distance = rec.t * length;
+        let sigma_t = match (random_f64() * 3.0) as usize {
+            0 => self.sigma_t.r,
+            1 => self.sigma_t.g,
+            _ => self.sigma_t.b,
+        };
+        let t = -(1.0 - random_f64()).ln() / sigma_t;
+
+        if t < distance {
+            let transmittance = self.transmittance(t);
+            let pdf = (self.sigma_t * transmittance).sum() / 3.0;
+            if pdf <= 0.0 {
+                return None;
+            }
+            let weight = self.single_scattering_albedo * self.sigma_t * transmittance / pdf;
+            let scattered = Ray::new(ray_in.at(t / length), Vec3::random_unit_vector());
+            Some(Scatter::below_surface(weight, scattered))
+        } else {
+            let transmittance = self.transmittance(distance);
+            let pdf = transmittance.sum() / 3.0;
+            if pdf <= 0.0 {
+                return None;
+            }
+            Some(Scatter::new(transmittance / pdf, Ray::new(rec.p, diffuse)))
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        // Only transmission is possible, so light from outside the object is never scattered
+        // back out at the same point.
+        let cosine = direction.normal().dot(-rec.normal).max(0.0);
+        if rec.front_face {
+            return Some((Rgb::new(cosine, cosine, cosine) / PI, cosine / PI));
+        }
+        // Light leaves the object here only if it was not scattered on its way to the surface,
+        // which `scatter` decides with the average transmittance over the channels.
+        let transmittance = self.transmittance(rec.t * ray_in.direction.len());
+        let pdf = transmittance.sum() / 3.0;
+        if pdf <= 0.0 {
+            return Some((Rgb::default(), 0.0));
+        }
+        Some((transmittance / pdf * cosine / PI, cosine / PI))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Rgb {
+        self.albedo
+    }
+}
+
+/// Converts the multiple-scattering albedo of a semi-infinite medium to the single-scattering
+/// albedo of its particles.
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let albedo = albedo.clamp(0.0, 1.0);
+    let s = 4.09712 + 4.20863 * albedo
+        - (9.59217 + 41.6808 * albedo + 17.7126 * albedo * albedo).sqrt();
+    1.0 - s * s
+}
+
+/// Inverts [`single_scattering_albedo`] by bisection, as it increases monotonically.
+fn surface_albedo(target: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..32 {
+        let mid = 0.5 * (low + high);
+        if single_scattering_albedo(mid) < target {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    0.5 * (low + high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrator::{PathTracer, Scene};
+    use crate::sampler::{with_sampler, IndependentSampler};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn albedo_conversion_round_trips() {
+        // The fit is almost flat near an albedo of one, where it cannot be inverted precisely.
+        for i in 0..10 {
+            let albedo = i as f64 / 10.0;
+            let single = single_scattering_albedo(albedo);
+            assert!((0.0..=1.0).contains(&single));
+            assert!((surface_albedo(single) - albedo).abs() < 1.0e-6);
+        }
+    }
+
+    /// Renders a unit sphere of `material` in a white furnace, and returns the mean radiance
+    /// of rays hitting it.
+    fn furnace(material: Subsurface) -> Rgb {
+        let mut world = HittableList::new();
+        world.add(Sphere::new(Point3::default(), 1.0, material));
+        let scene = Scene::new(world, Rgb::from(1.0));
+        let integrator = PathTracer::new(1000);
+
+        let samples = 2000;
+        let sampler = Rc::new(RefCell::new(IndependentSampler::new(3)));
+        with_sampler(sampler, || {
+            let mut total = Rgb::default();
+            for _ in 0..samples {
+                let target = Point3::new(random_f64() - 0.5, random_f64() - 0.5, 0.0);
+                let origin = Point3::new(0.0, 0.0, 3.0);
+                total += integrator.radiance(&Ray::new(origin, target - origin), &scene);
+            }
+            total / samples as f64
+        })
+    }
+
+    #[test]
+    fn random_walks_without_absorption_conserve_energy() {
+        // Every ray entering the sphere leaves it again, so it disappears into the environment.
+        let mean = furnace(Subsurface::from_coefficients(
+            Rgb::default(),
+            Rgb::from(2.0),
+        ));
+        for channel in &[mean.r, mean.g, mean.b] {
+            assert!((channel - 1.0).abs() < 0.02, "{:?}", mean);
+        }
+
+        // Absorption only darkens the channels it affects.
+        let sigma_a = Rgb::new(0.5, 0.0, 0.0);
+        let mean = furnace(Subsurface::from_coefficients(sigma_a, Rgb::from(2.0)));
+        assert!(mean.r < 0.9, "{:?}", mean);
+        assert!((mean.g - 1.0).abs() < 0.02, "{:?}", mean);
+    }
+}
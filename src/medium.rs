@@ -0,0 +1,419 @@
+//! Participating media, such as clouds, smoke and fog, that scatter light inside a volume.
+use crate::aabb::Aabb;
+use crate::noise;
+use crate::onb::Onb;
+use crate::prelude::*;
+use crate::util::random_f64;
+use std::fmt::Debug;
+
+/// A spatially varying density that scales the extinction coefficient of a [`Volume`].
+///
+/// Plain `f64` values are constant densities.
+pub trait Density: Debug + Send + Sync {
+    fn density(&self, p: Point3) -> f64;
+
+    /// An upper bound of the density over the whole volume, which collisions are sampled
+    /// against. Tighter bounds waste fewer samples on empty space.
+    fn max_density(&self) -> f64;
+}
+
+impl Density for f64 {
+    fn density(&self, _p: Point3) -> f64 {
+        *self
+    }
+
+    fn max_density(&self) -> f64 {
+        *self
+    }
+}
+
+/// A density given by voxels filling a box, e.g. simulated smoke, interpolated trilinearly
+/// between voxel centers and zero outside of the box.
+#[derive(Debug, Clone)]
+pub struct GridDensity {
+    bounds: Aabb,
+    resolution: [usize; 3],
+    /// The values of the voxels, with x varying fastest and z slowest.
+    values: Vec<f64>,
+    max: f64,
+}
+
+impl GridDensity {
+    /// Creates a grid of `resolution[0] × resolution[1] × resolution[2]` voxels over `bounds`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of `values` does not match the resolution.
+    pub fn new(bounds: Aabb, resolution: [usize; 3], values: Vec<f64>) -> Self {
+        assert_eq!(
+            values.len(),
+            resolution.iter().product::<usize>(),
+            "grid values do not match the resolution"
+        );
+        let max = values.iter().copied().fold(0.0, f64::max);
+        GridDensity {
+            bounds,
+            resolution,
+            values,
+            max,
+        }
+    }
+
+    /// Creates a grid by evaluating `f` at the center of every voxel.
+    pub fn from_fn(bounds: Aabb, resolution: [usize; 3], f: impl Fn(Point3) -> f64) -> Self {
+        let [nx, ny, nz] = resolution;
+        let size = bounds.diagonal();
+        let mut values = Vec::with_capacity(nx * ny * nz);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    let offset = Vec3::new(
+                        size.x * (i as f64 + 0.5) / nx as f64,
+                        size.y * (j as f64 + 0.5) / ny as f64,
+                        size.z * (k as f64 + 0.5) / nz as f64,
+                    );
+                    values.push(f(bounds.min + offset));
+                }
+            }
+        }
+        Self::new(bounds, resolution, values)
+    }
+
+    /// The value of the voxel at `(i, j, k)`, clamped to the grid.
+    fn voxel(&self, i: i64, j: i64, k: i64) -> f64 {
+        let [nx, ny, nz] = self.resolution;
+        let i = i.clamp(0, nx as i64 - 1) as usize;
+        let j = j.clamp(0, ny as i64 - 1) as usize;
+        let k = k.clamp(0, nz as i64 - 1) as usize;
+        self.values[(k * ny + j) * nx + i]
+    }
+}
+
+impl Density for GridDensity {
+    fn density(&self, p: Point3) -> f64 {
+        let (min, max) = (self.bounds.min, self.bounds.max);
+        if self.values.is_empty()
+            || p.x < min.x
+            || p.y < min.y
+            || p.z < min.z
+            || p.x > max.x
+            || p.y > max.y
+            || p.z > max.z
+        {
+            return 0.0;
+        }
+
+        // The position in voxels, relative to the center of the first voxel.
+        let size = self.bounds.diagonal();
+        let [nx, ny, nz] = self.resolution;
+        let x = (p.x - min.x) / size.x * nx as f64 - 0.5;
+        let y = (p.y - min.y) / size.y * ny as f64 - 0.5;
+        let z = (p.z - min.z) / size.z * nz as f64 - 0.5;
+        let (i, j, k) = (x.floor(), y.floor(), z.floor());
+        let (dx, dy, dz) = (x - i, y - j, z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+        let plane = |k| {
+            lerp(
+                dy,
+                lerp(dx, self.voxel(i, j, k), self.voxel(i + 1, j, k)),
+                lerp(dx, self.voxel(i, j + 1, k), self.voxel(i + 1, j + 1, k)),
+            )
+        };
+        lerp(dz, plane(k), plane(k + 1))
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max
+    }
+}
+
+/// A procedural density from fractal noise, for clouds and explosions.
+#[derive(Debug, Clone, Copy)]
+pub struct NoiseDensity {
+    /// The density of the thickest parts of the medium.
+    pub density: f64,
+    /// The number of noise features per unit length.
+    pub frequency: f64,
+    /// The number of layers of noise, each adding detail at twice the frequency.
+    pub octaves: u32,
+    /// The rough fraction of space filled by the medium, in (0, 1]. Lower values break the
+    /// medium up into separate puffs.
+    pub coverage: f64,
+}
+
+impl NoiseDensity {
+    pub fn new(density: f64, frequency: f64) -> Self {
+        NoiseDensity {
+            density,
+            frequency,
+            octaves: 5,
+            coverage: 0.5,
+        }
+    }
+}
+
+impl Density for NoiseDensity {
+    fn density(&self, p: Point3) -> f64 {
+        let noise = 0.5 + 0.5 * noise::fbm(self.frequency * p, self.octaves);
+        let coverage = self.coverage.clamp(1.0e-3, 1.0);
+        self.density * ((noise - (1.0 - coverage)) / coverage).clamp(0.0, 1.0)
+    }
+
+    fn max_density(&self) -> f64 {
+        self.density
+    }
+}
+
+/// The Henyey-Greenstein phase function, which scatters the fraction `albedo` of the light
+/// colliding with a medium into directions around the direction of travel.
+///
+/// The asymmetry `g` in (-1, 1) is the average cosine of the scattering angle: positive values
+/// scatter forward like clouds, negative values backward, and zero scatters isotropically.
+#[derive(Debug, Clone, Copy)]
+pub struct HenyeyGreenstein {
+    albedo: Rgb,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Rgb, g: f64) -> Self {
+        HenyeyGreenstein {
+            albedo,
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    pub fn isotropic(albedo: Rgb) -> Self {
+        Self::new(albedo, 0.0)
+    }
+
+    /// The density of scattering by an angle whose cosine is `cos_theta`, per unit solid angle.
+    pub fn p(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Samples the cosine of the scattering angle from the uniform sample `u`.
+    fn sample_cos_theta(&self, u: f64) -> f64 {
+        let g = self.g;
+        if g.abs() < 1.0e-3 {
+            return 1.0 - 2.0 * u;
+        }
+        let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+        let cos_theta = self.sample_cos_theta(random_f64());
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_f64();
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        let direction = Onb::from_w(ray_in.direction.normal()).to_world(local);
+        Some(Scatter::new(self.albedo, Ray::new(rec.p, direction)))
+    }
+
+    fn eval(&self, ray_in: &Ray, _rec: &HitRecord, direction: Vec3) -> Option<(Rgb, f64)> {
+        let cos_theta = ray_in.direction.normal().dot(direction.normal());
+        let pdf = self.p(cos_theta);
+        Some((self.albedo * pdf, pdf))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Rgb {
+        self.albedo
+    }
+}
+
+/// A medium with a spatially varying density filling a closed boundary, e.g. a cloud or the
+/// fireball of an explosion.
+///
+/// Rays cross the boundary unaffected and collide with the medium at distances sampled by
+/// delta tracking: tentative collisions are drawn as if the medium had its maximum density
+/// everywhere, and each one is kept with the ratio of the actual density to the maximum. The
+/// hits reported for collisions lie inside the medium and have a zero normal, and their
+/// material is the phase function of the medium.
+///
+/// Shadow rays estimate the transmittance with ratio tracking instead, which draws the same
+/// tentative collisions but attenuates the light by the fraction passing each of them.
+///
+/// The material of the boundary is ignored, and the boundary may be concave or contain other
+/// objects.
+pub struct Volume {
+    boundary: Box<dyn Hittable + Send + Sync>,
+    density: Box<dyn Density>,
+    /// The extinction coefficient at unit density, in inverse scene units.
+    sigma_t: f64,
+    phase: HenyeyGreenstein,
+}
+
+impl Volume {
+    pub fn new(
+        boundary: impl Hittable + Send + Sync + 'static,
+        density: impl Density + 'static,
+        sigma_t: f64,
+        phase: HenyeyGreenstein,
+    ) -> Self {
+        Volume {
+            boundary: Box::new(boundary),
+            density: Box::new(density),
+            sigma_t,
+            phase,
+        }
+    }
+
+    /// The extinction coefficient of the densest point, in inverse scene units.
+    fn majorant(&self) -> f64 {
+        self.sigma_t * self.density.max_density()
+    }
+
+    /// Calls `f` with the intervals of `ray` between `t_min` and `t_max` that lie inside the
+    /// boundary, in order, until it returns `false`.
+    fn for_each_interval(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut f: impl FnMut(f64, f64) -> bool,
+    ) {
+        // Bounds the crossings in case the boundary is not closed, e.g. due to round-off.
+        const MAX_CROSSINGS: usize = 64;
+
+        let mut t = t_min;
+        for _ in 0..MAX_CROSSINGS {
+            let rec = match self.boundary.hit(ray, t, INIFINTY) {
+                Some(rec) => rec,
+                None => return,
+            };
+            // Leaving the boundary means the ray was inside since `t`.
+            if !rec.front_face && !f(t, rec.t.min(t_max)) {
+                return;
+            }
+            if rec.t >= t_max {
+                return;
+            }
+            t = rec.t + 1.0e-6;
+        }
+    }
+
+    /// Draws the distance in units of `ray` to the next tentative collision after `t`.
+    fn next_tentative(&self, ray: &Ray, t: f64) -> f64 {
+        t - (1.0 - random_f64()).ln() / (self.majorant() * ray.direction.len())
+    }
+}
+
+impl Hittable for Volume {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        if self.majorant() <= 0.0 {
+            return None;
+        }
+
+        let mut collision = None;
+        self.for_each_interval(ray, t_min, t_max, |start, end| {
+            let mut t = self.next_tentative(ray, start);
+            while t < end {
+                let p = ray.at(t);
+                if random_f64() * self.majorant() < self.sigma_t * self.density.density(p) {
+                    collision = Some((p, t));
+                    return false;
+                }
+                t = self.next_tentative(ray, t);
+            }
+            true
+        });
+
+        let (p, t) = collision?;
+        let mut rec = HitRecord::new(p, t, Some(&self.phase));
+        rec.normal = Vec3::default();
+        rec.geometric_normal = Vec3::default();
+        rec.front_face = true;
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if self.majorant() <= 0.0 {
+            return 1.0;
+        }
+
+        let mut transmittance = 1.0;
+        self.for_each_interval(ray, t_min, t_max, |start, end| {
+            let mut t = self.next_tentative(ray, start);
+            while t < end && transmittance > 0.0 {
+                let density = self.sigma_t * self.density.density(ray.at(t));
+                transmittance *= 1.0 - density / self.majorant();
+                t = self.next_tentative(ray, t);
+            }
+            transmittance > 0.0
+        });
+        transmittance.max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn henyey_greenstein_is_normalized_with_mean_cosine_g() {
+        for &g in &[-0.6, 0.0, 0.3, 0.85] {
+            let phase = HenyeyGreenstein::new(Rgb::new(1.0, 1.0, 1.0), g);
+            let n = 100_000;
+            let (mut integral, mut mean) = (0.0, 0.0);
+            for i in 0..n {
+                let u = (i as f64 + 0.5) / n as f64;
+                integral += 2.0 * PI * phase.p(1.0 - 2.0 * u) * 2.0 / n as f64;
+                mean += phase.sample_cos_theta(u) / n as f64;
+            }
+            assert!((integral - 1.0).abs() < 1.0e-3, "{} {}", g, integral);
+            assert!((mean - g).abs() < 1.0e-3, "{} {}", g, mean);
+        }
+    }
+
+    #[test]
+    fn grid_density_interpolates_between_voxel_centers() {
+        let bounds = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0));
+        let grid = GridDensity::new(bounds, [2, 1, 1], vec![1.0, 3.0]);
+        assert_eq!(grid.density(Point3::new(0.5, 0.5, 0.5)), 1.0);
+        assert_eq!(grid.density(Point3::new(1.0, 0.5, 0.5)), 2.0);
+        assert_eq!(grid.density(Point3::new(1.9, 0.2, 0.9)), 3.0);
+        assert_eq!(grid.density(Point3::new(2.1, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.max_density(), 3.0);
+    }
+
+    #[test]
+    fn tracking_matches_beer_lambert() {
+        let boundary = Sphere {
+            center: Point3::new(0.0, 0.0, 0.0),
+            radius: 1.0,
+            material: None,
+        };
+        let volume = Volume::new(
+            boundary,
+            0.5,
+            2.0,
+            HenyeyGreenstein::isotropic(Rgb::from(1.0)),
+        );
+        let ray = Ray::new(Point3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 2.0));
+        let expected = (-2.0 * 0.5 * 2.0_f64).exp();
+
+        let n = 20_000;
+        let ratio = (0..n)
+            .map(|_| volume.transmittance(&ray, 0.0, INIFINTY))
+            .sum::<f64>()
+            / n as f64;
+        let delta = (0..n)
+            .filter(|_| volume.hit(&ray, 0.0, INIFINTY).is_none())
+            .count() as f64
+            / n as f64;
+        assert!((ratio - expected).abs() < 0.01, "{} {}", ratio, expected);
+        assert!((delta - expected).abs() < 0.01, "{} {}", delta, expected);
+    }
+}
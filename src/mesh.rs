@@ -0,0 +1,472 @@
+//! Polygon [`Mesh`]es, turned into [`Triangle`]s for rendering.
+use crate::prelude::*;
+use crate::subdivision::{self, Scheme};
+use crate::texture::Texture;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::sync::Arc;
+
+/// A polygon mesh with shared vertices, e.g. a low-poly cage exported from a modelling tool.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    /// Per-vertex surface coordinates, if the mesh has any.
+    pub uvs: Option<Vec<(f64, f64)>>,
+    /// The faces as lists of at least three vertex indices, counter-clockwise seen from the
+    /// outside.
+    pub faces: Vec<Vec<usize>>,
+    /// The sharpness of creased edges, keyed by their vertex indices in increasing order.
+    pub creases: HashMap<(usize, usize), f64>,
+}
+
+/// Returns the key of the edge between vertices `a` and `b` in [`Mesh::creases`].
+pub(crate) fn edge(a: usize, b: usize) -> (usize, usize) {
+    (a.min(b), a.max(b))
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Point3>, faces: Vec<Vec<usize>>) -> Self {
+        Mesh {
+            positions,
+            uvs: None,
+            faces,
+            creases: HashMap::new(),
+        }
+    }
+
+    /// Reads the vertices, surface coordinates and faces of a Wavefront OBJ file, ignoring
+    /// everything else.
+    ///
+    /// Vertices take the surface coordinates of the first face corner using them, so seams
+    /// are not preserved. Repeated neighboring corners of a face are merged, and faces left
+    /// without area are dropped.
+    pub fn read_obj(reader: impl BufRead) -> Result<Self> {
+        let invalid = |line: usize, message: &str| {
+            ErrorKind::ReadMesh(format!("line {}: {}", line + 1, message))
+        };
+        // Resolves a one-based index, or a negative one counting back from the end.
+        let resolve = |index: &str, len: usize, line: usize| -> Result<usize> {
+            let index: i64 = index.parse().map_err(|_| invalid(line, "bad index"))?;
+            let resolved = if index < 0 {
+                len as i64 + index
+            } else {
+                index - 1
+            };
+            if resolved < 0 || resolved >= len as i64 {
+                return Err(invalid(line, "index out of range"));
+            }
+            Ok(resolved as usize)
+        };
+
+        let mut mesh = Mesh::default();
+        let mut texture_coordinates = Vec::new();
+        let mut uvs: Vec<Option<(f64, f64)>> = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            let numbers = |tokens: std::str::SplitWhitespace| -> Result<Vec<f64>> {
+                tokens
+                    .map(|token| token.parse().map_err(|_| invalid(number, "bad number")))
+                    .collect()
+            };
+            match tokens.next() {
+                Some("v") => {
+                    let v = numbers(tokens)?;
+                    if v.len() < 3 {
+                        return Err(invalid(number, "vertex needs three coordinates"));
+                    }
+                    mesh.positions.push(Point3::new(v[0], v[1], v[2]));
+                    uvs.push(None);
+                }
+                Some("vt") => {
+                    let vt = numbers(tokens)?;
+                    let u = *vt
+                        .first()
+                        .ok_or_else(|| invalid(number, "empty coordinates"))?;
+                    texture_coordinates.push((u, vt.get(1).copied().unwrap_or(0.0)));
+                }
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for corner in tokens {
+                        let mut indices = corner.split('/');
+                        let vertex = indices.next().unwrap_or("");
+                        let vertex = resolve(vertex, mesh.positions.len(), number)?;
+                        if let Some(uv) = indices.next().filter(|uv| !uv.is_empty()) {
+                            let uv = resolve(uv, texture_coordinates.len(), number)?;
+                            uvs[vertex].get_or_insert(texture_coordinates[uv]);
+                        }
+                        face.push(vertex);
+                    }
+                    if face.len() < 3 {
+                        return Err(invalid(number, "face needs three vertices"));
+                    }
+                    // Corners collapsed onto their neighbors leave a smaller polygon, or
+                    // nothing at all.
+                    face.dedup();
+                    while face.len() > 1 && face.first() == face.last() {
+                        face.pop();
+                    }
+                    if face.len() < 3 {
+                        continue;
+                    }
+                    let mut sorted = face.clone();
+                    sorted.sort_unstable();
+                    if sorted.windows(2).any(|w| w[0] == w[1]) {
+                        return Err(invalid(number, "face repeats a vertex"));
+                    }
+                    mesh.faces.push(face);
+                }
+                _ => {}
+            }
+        }
+
+        if uvs.iter().any(Option::is_some) {
+            mesh.uvs = Some(uvs.into_iter().map(Option::unwrap_or_default).collect());
+        }
+        Ok(mesh)
+    }
+
+    /// The sharpness of the edge between vertices `a` and `b`, zero if it is smooth.
+    pub fn crease(&self, a: usize, b: usize) -> f64 {
+        self.creases.get(&edge(a, b)).copied().unwrap_or(0.0)
+    }
+
+    /// Creases the edge between vertices `a` and `b`.
+    ///
+    /// The edge stays sharp for `sharpness` levels of subdivision and is smoothed afterwards,
+    /// so that it ends up as a rounded but tight fillet. Fractional values blend between
+    /// levels, and an infinite sharpness keeps it sharp for good.
+    pub fn set_crease(&mut self, a: usize, b: usize, sharpness: f64) {
+        if sharpness > 0.0 {
+            self.creases.insert(edge(a, b), sharpness);
+        } else {
+            self.creases.remove(&edge(a, b));
+        }
+    }
+
+    /// Subdivides the mesh `levels` times with `scheme`, approaching its smooth limit surface.
+    ///
+    /// Each level multiplies the number of faces by about four.
+    pub fn subdivide(&self, scheme: Scheme, levels: usize) -> Self {
+        let mut mesh = self.clone();
+        for _ in 0..levels {
+            mesh = subdivision::subdivide(&mesh, scheme);
+        }
+        mesh
+    }
+
+    /// Displaces the surface along its normals by `scale` times the scalar value of
+    /// `texture`, after splitting every triangle into four `levels` times.
+    ///
+    /// The displacement is looked up by the surface coordinates and the position of each
+    /// vertex before it moves. Its detail is limited by the tessellation, whose triangles
+    /// should be about as small as the features of the texture. Vertices are displaced along
+    /// normals interpolated from those of the original vertices, which keeps the surface
+    /// closed across creases.
+    ///
+    /// The [`Bvh`](crate::bvh::Bvh) of the list the triangles are added to only tests rays
+    /// against the triangles around them, so fine tessellations mostly cost memory.
+    pub fn displace(&self, texture: &dyn Texture, scale: f64, levels: usize) -> Self {
+        // The normal of each vertex, averaged over the faces around it.
+        let mut normals = vec![Vec3::default(); self.positions.len()];
+        for (face, normal) in self.faces.iter().zip(self.face_normals()) {
+            for &v in face {
+                normals[v] += normal;
+            }
+        }
+
+        // Linear subdivision only takes midpoints, so tessellating a copy of the mesh with the
+        // normals as positions interpolates them across the new vertices.
+        let mut mesh = self.subdivide(Scheme::Linear, levels);
+        let normals = Mesh {
+            positions: normals,
+            ..self.clone()
+        }
+        .subdivide(Scheme::Linear, levels)
+        .positions;
+
+        for (v, (p, normal)) in mesh.positions.iter_mut().zip(normals).enumerate() {
+            if normal.near_zero() {
+                continue;
+            }
+            let (u, uv_v) = mesh.uvs.as_ref().map_or((0.0, 0.0), |uvs| uvs[v]);
+            *p += scale * texture.scalar(u, uv_v, *p) * normal.normal();
+        }
+        mesh
+    }
+
+    /// Splits the faces into triangles sharing `material`, with shading normals smoothed
+    /// across the edges that are not creased.
+    pub fn triangles(&self, material: impl Material + 'static) -> Vec<Triangle> {
+        let material: Arc<dyn Material> = Arc::new(material);
+
+        let face_normals = self.face_normals();
+
+        // Group the face corners around each vertex that are connected through smooth edges,
+        // which share a normal.
+        let mut corners = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face {
+                let id = corners.len();
+                corners.entry((v, f)).or_insert(id);
+            }
+        }
+        let mut groups = DisjointSets::new(corners.len());
+        let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            for (i, &a) in face.iter().enumerate() {
+                let b = face[(i + 1) % face.len()];
+                edge_faces.entry(edge(a, b)).or_default().push(f);
+            }
+        }
+        for (&(a, b), faces) in &edge_faces {
+            if faces.len() == 2 && self.crease(a, b) <= 0.0 {
+                for &v in &[a, b] {
+                    groups.union(corners[&(v, faces[0])], corners[&(v, faces[1])]);
+                }
+            }
+        }
+        let roots: Vec<usize> = (0..corners.len()).map(|id| groups.find(id)).collect();
+        let mut normals = vec![Vec3::default(); corners.len()];
+        for (&(_, f), &id) in &corners {
+            normals[roots[id]] += face_normals[f];
+        }
+
+        let mut triangles = Vec::new();
+        for (f, face) in self.faces.iter().enumerate() {
+            let normal = |v: usize| {
+                let n = normals[roots[corners[&(v, f)]]];
+                if n.near_zero() {
+                    face_normals[f]
+                } else {
+                    n.normal()
+                }
+            };
+            let uv = |v: usize| match &self.uvs {
+                Some(uvs) => uvs[v],
+                None => (0.0, 0.0),
+            };
+            for w in face[1..].windows(2) {
+                let vertices = [face[0], w[0], w[1]];
+                let [p0, p1, p2] = vertices.map(|v| self.positions[v]);
+                let mut triangle = Triangle::new(p0, p1, p2, Arc::clone(&material))
+                    .with_normals(vertices.map(normal));
+                if self.uvs.is_some() {
+                    triangle = triangle.with_uvs(vertices.map(uv));
+                }
+                triangles.push(triangle);
+            }
+        }
+        triangles
+    }
+
+    /// The area weighted normal of each face, summed over a fan of triangles.
+    fn face_normals(&self) -> Vec<Vec3> {
+        self.faces
+            .iter()
+            .map(|face| {
+                let p0 = self.positions[face[0]];
+                face[1..]
+                    .windows(2)
+                    .map(|w| (self.positions[w[0]] - p0).cross(self.positions[w[1]] - p0))
+                    .fold(Vec3::default(), |sum, n| sum + n)
+            })
+            .collect()
+    }
+}
+
+/// Disjoint sets of indices, merged by union by size with path halving.
+struct DisjointSets {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        DisjointSets {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.sizes[a] < self.sizes[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parents[b] = a;
+        self.sizes[a] += self.sizes[b];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::CheckerTexture;
+
+    /// A square from -1 to 1 in the xy plane facing up the z axis.
+    fn square() -> Mesh {
+        let positions = vec![
+            Point3::new(-1.0, -1.0, 0.0),
+            Point3::new(1.0, -1.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(-1.0, 1.0, 0.0),
+        ];
+        Mesh::new(positions, vec![vec![0, 1, 2, 3]])
+    }
+
+    #[test]
+    fn displacement_moves_vertices_along_the_normals() {
+        let mesh = square().displace(&0.5, 2.0, 3);
+        assert_eq!(mesh.faces.len(), 2 * 64);
+        assert!(mesh.positions.iter().all(|p| (p.z - 1.0).abs() < 1.0e-12));
+        let mesh = square().displace(&0.5, 2.0, 5);
+        assert_eq!(mesh.faces.len(), 2 * 1024);
+
+        // A checkerboard raises every other unit cube, e.g. a row of steps.
+        let checker = CheckerTexture::new(1.0, 0.0, 0.5);
+        let mesh = square().displace(&checker, 0.25, 2);
+        for (p, q) in mesh
+            .positions
+            .iter()
+            .zip(&square().subdivide(Scheme::Linear, 2).positions)
+        {
+            let expected = 0.25 * checker.scalar(0.0, 0.0, *q);
+            assert!((p.x - q.x).abs() < 1.0e-12 && (p.z - expected).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn obj_files_are_read() {
+        let obj = "\
+# A quad and a triangle.
+o test
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0 1
+vt 0 0
+vt 1 0
+vt 1 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4//1
+f -4 -2 -1
+f 1/3 2 3
+";
+        let mesh = Mesh::read_obj(obj.as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[3], Point3::new(0.0, 1.0, 0.0));
+        assert_eq!(
+            mesh.faces,
+            vec![vec![0, 1, 2, 3], vec![0, 2, 3], vec![0, 1, 2]]
+        );
+        // The first corner using a vertex gives it its coordinates, and vertices without any
+        // get zeros.
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)];
+        assert_eq!(mesh.uvs, Some(uvs));
+
+        // Negative texture coordinate indices count back from the last ones read.
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.25\nvt 0.75\nf 1/-1 2/-2 3\n";
+        let mesh = Mesh::read_obj(obj.as_bytes()).unwrap();
+        let uvs = vec![(0.75, 0.0), (0.5, 0.25), (0.0, 0.0)];
+        assert_eq!(mesh.uvs, Some(uvs));
+
+        let mesh = Mesh::read_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n".as_bytes()).unwrap();
+        assert_eq!(mesh.uvs, None);
+    }
+
+    #[test]
+    fn malformed_obj_files_are_rejected() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        let cases = [
+            ("v 0 x 0\n", "line 1: bad number"),
+            ("v 0 0\n", "line 1: vertex needs three coordinates"),
+            ("vt\n", "line 1: empty coordinates"),
+            ("f 1 2 a\n", "line 4: bad index"),
+            ("f 1 2 4\n", "line 4: index out of range"),
+            ("f 0 1 2\n", "line 4: index out of range"),
+            ("f -4 1 2\n", "line 4: index out of range"),
+            ("f 1/1 2 3\n", "line 4: index out of range"),
+            ("f 1 2\n", "line 4: face needs three vertices"),
+            ("f 1 2 1 3\n", "line 4: face repeats a vertex"),
+        ];
+        for &(source, expected) in &cases {
+            let obj = if source.starts_with('f') {
+                format!("{}{}", vertices, source)
+            } else {
+                source.to_string()
+            };
+            match Mesh::read_obj(obj.as_bytes()) {
+                Err(ErrorKind::ReadMesh(message)) => assert_eq!(message, expected),
+                other => panic!("{:?} for {:?}", other, source),
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_obj_faces_are_cleaned_up() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 2 3 4 1\nf 1 1 2\nf 3 3 3\n";
+        let mesh = Mesh::read_obj(obj.as_bytes()).unwrap();
+        assert_eq!(mesh.faces, vec![vec![0, 1, 2, 3]]);
+
+        // Subdivision relies on every triangle having three distinct vertices.
+        for &scheme in &[Scheme::CatmullClark, Scheme::Loop] {
+            let subdivided = mesh.subdivide(scheme, 2);
+            assert!(subdivided
+                .faces
+                .iter()
+                .all(|face| face.iter().all(|&v| v < subdivided.positions.len())));
+        }
+    }
+
+    #[test]
+    fn normals_are_split_at_creased_edges() {
+        // Two faces folded along the edge from vertex 0 to vertex 1.
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, -1.0, 1.0),
+        ];
+        let mut mesh = Mesh::new(positions, vec![vec![0, 1, 2], vec![1, 0, 3]]);
+        let flat = Vec3::new(0.0, 0.0, 1.0);
+        let slanted = Vec3::new(0.0, 1.0, 1.0).normal();
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        let normals = |mesh: &Mesh| -> Vec<[Vec3; 3]> {
+            mesh.triangles(material)
+                .iter()
+                .map(|triangle| triangle.normals.unwrap())
+                .collect()
+        };
+        let assert_near = |a: Vec3, b: Vec3| assert!((a - b).len() < 1.0e-12, "{:?}", a);
+
+        // The vertices of the smooth edge share the normal of both faces, weighted by area.
+        let smooth = Vec3::new(0.0, 1.0, 2.0).normal();
+        let [first, second] = [normals(&mesh)[0], normals(&mesh)[1]];
+        for &normal in first[..2].iter().chain(&second[..2]) {
+            assert_near(normal, smooth);
+        }
+        assert_near(first[2], flat);
+        assert_near(second[2], slanted);
+
+        // Any crease gives each face its own normal.
+        mesh.set_crease(1, 0, 0.5);
+        let [first, second] = [normals(&mesh)[0], normals(&mesh)[1]];
+        for i in 0..3 {
+            assert_near(first[i], flat);
+            assert_near(second[i], slanted);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+//! Microfacet theory building blocks.
+//!
+//! All directions are given in a local shading frame where the macro surface normal is `+z`
+//! (see [`Onb`]), and point away from the surface.
+//!
+//! [`Onb`]: crate::onb::Onb
+use crate::prelude::*;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// The GGX / Trowbridge-Reitz microfacet distribution with Smith masking-shadowing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    /// The smallest roughness that is still sampled as a microfacet distribution; smoother
+    /// surfaces would cause numerical problems.
+    const MIN_ALPHA: f64 = 1.0e-4;
+
+    /// Creates a distribution from a perceptual `roughness` in [0, 1], mapped to `alpha` by
+    /// squaring as popularized by Burley.
+    pub fn from_roughness(roughness: f64) -> Self {
+        Ggx::new(roughness.clamp(0.0, 1.0).powi(2))
+    }
+
+    pub fn new(alpha: f64) -> Self {
+        Ggx {
+            alpha: alpha.max(Self::MIN_ALPHA),
+        }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Evaluates the distribution of microfacet normals `wm`.
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let cos2 = wm.z * wm.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        let a2 = self.alpha * self.alpha;
+        let e = 1.0 + tan2 / a2;
+        1.0 / (PI * a2 * cos2 * cos2 * e * e)
+    }
+
+    /// The Smith auxiliary function `Λ(w)`.
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) * 0.5
+    }
+
+    /// The Smith masking function for a single direction.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The height-correlated Smith masking-shadowing function.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// The density of visible normals `wm` as seen from `wo`.
+    pub fn visible_d(&self, wo: Vec3, wm: Vec3) -> f64 {
+        if wo.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) / wo.z.abs() * self.d(wm) * wo.dot(wm).abs()
+    }
+
+    /// Samples a microfacet normal visible from `wo` with density [`visible_d`], following
+    /// Heitz, "Sampling the GGX Distribution of Visible Normals" (2018).
+    ///
+    /// [`visible_d`]: Ggx::visible_d
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Transform the view direction to the hemisphere configuration.
+        let mut wh = Vec3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).normal();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+
+        // Build an orthonormal basis around the view direction.
+        let t1 = if wh.z < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(wh).normal()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(t1);
+
+        // Sample a point on the projected hemisphere.
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let mut p2 = r * phi.sin();
+        let s = 0.5 * (1.0 + wh.z);
+        p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+
+        // Reproject onto the hemisphere and back to the ellipsoid configuration.
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * wh;
+        Vec3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1.0e-6)).normal()
+    }
+
+    /// Evaluates a microfacet reflection from `wo` into `wi`, both above the surface.
+    ///
+    /// Returns the BSDF times the cosine of `wi` without the Fresnel term, the density of
+    /// sampling `wi` through [`sample_visible_normal`], and the microfacet normal.
+    ///
+    /// [`sample_visible_normal`]: Ggx::sample_visible_normal
+    pub fn reflection(&self, wo: Vec3, wi: Vec3) -> Option<(f64, f64, Vec3)> {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return None;
+        }
+        let wm = wo + wi;
+        if wm.near_zero() {
+            return None;
+        }
+        let wm = wm.normal();
+
+        let value = self.d(wm) * self.g(wo, wi) / (4.0 * wo.z);
+        let pdf = self.visible_d(wo, wm) / (4.0 * wo.dot(wm));
+        Some((value, pdf, wm))
+    }
+
+    /// Evaluates a microfacet refraction from `wo` above the surface into `wi` below it, where
+    /// `eta` is the ratio of the refractive index below the surface over the one above.
+    ///
+    /// Returns the BSDF times the cosine of `wi` without the Fresnel term, the density of
+    /// sampling `wi` through [`sample_visible_normal`], and the microfacet normal.
+    ///
+    /// [`sample_visible_normal`]: Ggx::sample_visible_normal
+    pub fn transmission(&self, wo: Vec3, wi: Vec3, eta: f64) -> Option<(f64, f64, Vec3)> {
+        if wo.z <= 0.0 || wi.z >= 0.0 {
+            return None;
+        }
+        let wm = wo + eta * wi;
+        if wm.near_zero() {
+            return None;
+        }
+        let mut wm = wm.normal();
+        if wm.z < 0.0 {
+            wm = -wm;
+        }
+
+        // Discard back-facing microfacets.
+        if wo.dot(wm) <= 0.0 || wi.dot(wm) >= 0.0 {
+            return None;
+        }
+
+        let denom = wi.dot(wm) + wo.dot(wm) / eta;
+        let denom = denom * denom;
+        let dwm_dwi = wi.dot(wm).abs() / denom;
+
+        let value = self.d(wm) * self.g(wo, wi) * wo.dot(wm).abs() * dwm_dwi / wo.z;
+        let pdf = self.visible_d(wo, wm) * dwm_dwi;
+        Some((value, pdf, wm))
+    }
+}
+
+/// The exact Fresnel reflectance of a dielectric interface for unpolarized light.
+///
+/// `cos_theta_i` is the cosine of the incident angle and `eta` the ratio of the refractive index
+/// on the transmitted side over the incident side. A negative cosine means the light arrives from
+/// the other side, in which case the interface is flipped.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = cos_theta_i.min(1.0);
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // Total internal reflection.
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) * 0.5
+}
+
+/// The exact Fresnel reflectance of a conductor with complex index of refraction `eta + i k`,
+/// evaluated per color channel.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: Rgb, k: Rgb) -> Rgb {
+    Rgb::new(
+        fresnel_complex(cos_theta_i, Complex::new(eta.r, k.r)),
+        fresnel_complex(cos_theta_i, Complex::new(eta.g, k.g)),
+        fresnel_complex(cos_theta_i, Complex::new(eta.b, k.b)),
+    )
+}
+
+/// Schlick's approximation of the Fresnel reflectance with normal incidence reflectance `f0`.
+pub fn fresnel_schlick(cos_theta_i: f64, f0: Rgb) -> Rgb {
+    let weight = (1.0 - cos_theta_i.clamp(0.0, 1.0)).powi(5);
+    f0 + (Rgb::new(1.0, 1.0, 1.0) - f0) * weight
+}
+
+fn fresnel_complex(cos_theta_i: f64, eta: Complex) -> f64 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let sin2_theta_t = Complex::from(sin2_theta_i) / (eta * eta);
+    let cos_theta_t = (Complex::from(1.0) - sin2_theta_t).sqrt();
+
+    let cos_i = Complex::from(cos_theta_i);
+    let r_parallel = (eta * cos_i - cos_theta_t) / (eta * cos_i + cos_theta_t);
+    let r_perpendicular = (cos_i - eta * cos_theta_t) / (cos_i + eta * cos_theta_t);
+    (r_parallel.norm() + r_perpendicular.norm()) * 0.5
+}
+
+/// A minimal complex number type for conductor Fresnel terms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    /// The squared magnitude.
+    fn norm(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The principal square root.
+    fn sqrt(self) -> Self {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::from(0.0);
+        }
+        let t1 = (0.5 * (n + self.re.abs())).sqrt();
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, rhs: Complex) -> Complex {
+        let scale = 1.0 / rhs.norm();
+        Complex::new(
+            scale * (self.re * rhs.re + self.im * rhs.im),
+            scale * (self.im * rhs.re - self.re * rhs.im),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::random_f64;
+
+    fn spherical(cos_theta: f64, phi: f64) -> Vec3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+
+    #[test]
+    fn ggx_projected_area_is_normalized() {
+        // ∫ D(m) cos(θm) dω = 1 over the hemisphere, estimated with uniform sampling.
+        for &alpha in &[0.1, 0.5, 1.0] {
+            let ggx = Ggx::new(alpha);
+            let n = 200_000;
+            let sum: f64 = (0..n)
+                .map(|_| {
+                    let wm = spherical(random_f64(), 2.0 * PI * random_f64());
+                    ggx.d(wm) * wm.z * 2.0 * PI
+                })
+                .sum();
+            let integral = sum / n as f64;
+            assert!(
+                (integral - 1.0).abs() < 0.05,
+                "alpha {}: {}",
+                alpha,
+                integral
+            );
+        }
+    }
+
+    #[test]
+    fn visible_normals_are_in_the_upper_hemisphere() {
+        let ggx = Ggx::new(0.6);
+        let wo = spherical(0.3, 1.0);
+        for _ in 0..1000 {
+            let wm = ggx.sample_visible_normal(wo, random_f64(), random_f64());
+            assert!(wm.z > 0.0);
+            assert!((wm.len() - 1.0).abs() < 1e-9);
+            assert!(ggx.visible_d(wo, wm) > 0.0);
+        }
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // Normal incidence on glass reflects about 4%.
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-9);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+
+        // A conductor without absorption behaves like a dielectric.
+        let f = fresnel_conductor(0.7, Rgb::from(1.5), Rgb::from(0.0));
+        assert!((f.r - fresnel_dielectric(0.7, 1.5)).abs() < 1e-9);
+
+        // Grazing incidence reflects everything.
+        let f = fresnel_conductor(0.0, Rgb::new(0.2, 0.9, 1.1), Rgb::new(3.9, 2.5, 2.1));
+        assert!((f - Rgb::from(1.0)).len() < 1e-9);
+    }
+}
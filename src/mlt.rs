@@ -0,0 +1,339 @@
+//! Primary sample space Metropolis light transport.
+use crate::bdpt::Bdpt;
+use crate::distribution::AliasTable;
+use crate::film::Film;
+use crate::integrator::Scene;
+use crate::prelude::*;
+use crate::sampler::{self, Sampler, Stream};
+use crate::util::random_f64;
+use rand::prelude::*;
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+/// Metropolis light transport in primary sample space, following Kelemen et al., "A Simple and
+/// Robust Mutation Strategy for the Metropolis Light Transport Algorithm" (2002).
+///
+/// A path is a deterministic function of the random numbers consumed while tracing it, so
+/// Markov chains can explore paths by mutating these numbers instead of the paths themselves.
+/// Chains propose either small perturbations of every dimension or completely new samples,
+/// which are accepted in proportion to the luminance they contribute to the image.
+/// Once a chain finds a difficult path, such as light leaking through a door crack, it keeps
+/// exploring its neighbourhood.
+///
+/// Paths are traced with [`Bdpt`], and the first two dimensions of the camera stream choose the
+/// point of the image.
+/// The brightness of the image, which the chains lose, is estimated beforehand from
+/// independent samples.
+#[derive(Debug, Clone, Copy)]
+pub struct Mlt {
+    /// The maximum number of bounces of a path.
+    pub max_depth: u32,
+    /// The number of independent samples estimating the brightness of the image.
+    pub bootstrap_samples: usize,
+    /// The number of Markov chains, each started from one of the bootstrap samples.
+    pub chains: usize,
+    /// The average number of mutations per pixel over all chains.
+    pub mutations_per_pixel: usize,
+    /// The standard deviation of the perturbation of a dimension by a small step.
+    pub sigma: f64,
+    /// The probability that a mutation draws a completely new sample.
+    pub large_step_probability: f64,
+}
+
+/// A dimension of the sample of a chain, with its value before the current iteration.
+#[derive(Debug, Clone, Copy, Default)]
+struct PrimarySample {
+    value: f64,
+    /// The iteration that last modified the value.
+    last_modification: u64,
+    value_backup: f64,
+    modification_backup: u64,
+}
+
+/// The sampler of a chain, which mutates its dimensions lazily as they are consumed.
+///
+/// The dimensions of the streams are interleaved, so that every stream can grow independently.
+/// Dimensions not consumed for a few iterations catch up on the small steps they missed at
+/// once, by perturbing them with the combined standard deviation, and on large steps by
+/// drawing a new value.
+#[derive(Debug, Clone)]
+struct MltSampler {
+    rng: StdRng,
+    sigma: f64,
+    large_step_probability: f64,
+    samples: Vec<PrimarySample>,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+    stream: Stream,
+    /// The next dimension within `stream`.
+    index: usize,
+}
+
+/// The contributions of a sample to the image in raster coordinates.
+type Contributions = Vec<(f64, f64, Rgb)>;
+
+impl Mlt {
+    pub fn new(max_depth: u32) -> Self {
+        Mlt {
+            max_depth,
+            bootstrap_samples: 100_000,
+            chains: 1000,
+            mutations_per_pixel: 100,
+            sigma: 0.01,
+            large_step_probability: 0.3,
+        }
+    }
+
+    /// Renders the `scene` seen from the `camera` into the splats of `film`, and sets their
+    /// scale.
+    pub fn render(&self, scene: &Scene, camera: &Camera, film: &mut Film) {
+        let bdpt = Bdpt::new(self.max_depth);
+        let (width, height) = (film.width() as f64, film.height() as f64);
+        let new_sampler = |seed| {
+            Rc::new(RefCell::new(MltSampler::new(
+                seed,
+                self.sigma,
+                self.large_step_probability,
+            )))
+        };
+
+        // The sampler of a bootstrap sample reproduces it when created again with its seed.
+        let weights: Vec<f64> = (0..self.bootstrap_samples)
+            .map(|seed| {
+                let sampler = new_sampler(seed as u64);
+                contributions(&bdpt, scene, camera, width, height, sampler).1
+            })
+            .collect();
+        let brightness = weights.iter().sum::<f64>() / self.bootstrap_samples as f64;
+        if brightness <= 0.0 || self.chains == 0 {
+            return;
+        }
+        let bootstrap = AliasTable::new(&weights);
+
+        let mutations = self.mutations_per_pixel * film.width() * film.height();
+        let mutations_per_chain = mutations.div_ceil(self.chains);
+        for chain in 0..self.chains {
+            // Seeds past those of the bootstrap samples keep the chains independent of them.
+            let mut rng = StdRng::seed_from_u64((self.bootstrap_samples + chain) as u64);
+            let (seed, _) = bootstrap.sample(rng.gen());
+            let sampler = new_sampler(seed as u64);
+            let (mut current, mut current_weight) =
+                contributions(&bdpt, scene, camera, width, height, sampler.clone());
+            // Chains starting from the same bootstrap sample mutate it differently.
+            sampler.borrow_mut().rng = StdRng::seed_from_u64(rng.gen());
+
+            for _ in 0..mutations_per_chain {
+                sampler.borrow_mut().start_iteration();
+                let (proposed, proposed_weight) =
+                    contributions(&bdpt, scene, camera, width, height, sampler.clone());
+                let accept = if current_weight > 0.0 {
+                    (proposed_weight / current_weight).min(1.0)
+                } else {
+                    1.0
+                };
+
+                // Both states contribute by their expected share, which reduces variance
+                // over only splatting the state the chain ends up in.
+                if accept > 0.0 && proposed_weight > 0.0 {
+                    for &(x, y, color) in &proposed {
+                        film.add_splat(x, y, accept / proposed_weight * color);
+                    }
+                }
+                if accept < 1.0 && current_weight > 0.0 {
+                    for &(x, y, color) in &current {
+                        film.add_splat(x, y, (1.0 - accept) / current_weight * color);
+                    }
+                }
+
+                if rng.gen::<f64>() < accept {
+                    current = proposed;
+                    current_weight = proposed_weight;
+                    sampler.borrow_mut().accept();
+                } else {
+                    sampler.borrow_mut().reject();
+                }
+            }
+        }
+
+        let total = (mutations_per_chain * self.chains) as f64;
+        film.set_splat_scale(brightness * film.width() as f64 * film.height() as f64 / total);
+    }
+}
+
+/// Traces the sample of `sampler` and returns its contributions to the image, with their total
+/// luminance.
+fn contributions(
+    bdpt: &Bdpt,
+    scene: &Scene,
+    camera: &Camera,
+    width: f64,
+    height: f64,
+    sampler: Rc<RefCell<MltSampler>>,
+) -> (Contributions, f64) {
+    let contributions = sampler::with_sampler(sampler, || {
+        let x = random_f64() * width;
+        let y = random_f64() * height;
+        let ray = camera.get_ray(x / width, 1.0 - y / height);
+        let mut contributions = Vec::new();
+        let color = bdpt.radiance(&ray, camera, scene, |u, v, color| {
+            contributions.push((u * width, (1.0 - v) * height, color))
+        });
+        contributions.push((x, y, color));
+        contributions
+    });
+
+    // Numerical failures such as NaNs would spread over the whole image through the splats.
+    let contributions: Contributions = contributions
+        .into_iter()
+        .filter(|(_, _, color)| color.r.is_finite() && color.g.is_finite() && color.b.is_finite())
+        .collect();
+    let weight = contributions
+        .iter()
+        .map(|(_, _, color)| color.luminance())
+        .filter(|&luminance| luminance > 0.0)
+        .sum();
+    (contributions, weight)
+}
+
+impl MltSampler {
+    /// Creates a sampler whose first iteration is a large step seeded by `seed`.
+    fn new(seed: u64, sigma: f64, large_step_probability: f64) -> Self {
+        MltSampler {
+            rng: StdRng::seed_from_u64(seed),
+            sigma,
+            large_step_probability,
+            samples: Vec::new(),
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+            stream: Stream::Camera,
+            index: 0,
+        }
+    }
+
+    /// Starts proposing a mutation of the sample.
+    fn start_iteration(&mut self) {
+        self.iteration += 1;
+        self.large_step = self.rng.gen::<f64>() < self.large_step_probability;
+        self.start_stream(Stream::Camera);
+    }
+
+    /// Keeps the mutation as the current sample.
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    /// Restores the sample from before the mutation.
+    fn reject(&mut self) {
+        for sample in &mut self.samples {
+            if sample.last_modification == self.iteration {
+                sample.value = sample.value_backup;
+                sample.last_modification = sample.modification_backup;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    /// Brings the dimension at `index` up to date with the current iteration.
+    fn mutate(&mut self, index: usize) {
+        // Dimensions consumed for the first time start from an independent value, as if it
+        // had been drawn by the previous iteration.
+        while self.samples.len() <= index {
+            let value = self.rng.gen();
+            self.samples.push(PrimarySample {
+                value,
+                last_modification: self.iteration.saturating_sub(1),
+                ..PrimarySample::default()
+            });
+        }
+        let sample = &mut self.samples[index];
+
+        // A large step since the last use replaces the value, whatever happened before it.
+        if sample.last_modification < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.last_modification = self.last_large_step;
+        }
+
+        sample.value_backup = sample.value;
+        sample.modification_backup = sample.last_modification;
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            let steps = (self.iteration - sample.last_modification) as f64;
+            let sigma = self.sigma * steps.sqrt();
+            // Box-Muller transform.
+            let (u1, u2): (f64, f64) = (self.rng.gen(), self.rng.gen());
+            let normal = (-2.0 * (1.0 - u1).ln()).sqrt() * (2.0 * PI * u2).cos();
+            sample.value += sigma * normal;
+            // Tiny negative values wrap around to exactly one.
+            sample.value = (sample.value - sample.value.floor()).min(1.0 - f64::EPSILON);
+        }
+        sample.last_modification = self.iteration;
+    }
+}
+
+impl Sampler for MltSampler {
+    fn next_1d(&mut self) -> f64 {
+        let index = self.index * Stream::COUNT + self.stream as usize;
+        self.index += 1;
+        self.mutate(index);
+        self.samples[index].value
+    }
+
+    fn start_stream(&mut self, stream: Stream) {
+        self.stream = stream;
+        self.index = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(sampler: &mut MltSampler, n: usize) -> Vec<f64> {
+        (0..n).map(|_| sampler.next_1d()).collect()
+    }
+
+    #[test]
+    fn rejected_mutations_restore_the_sample() {
+        let mut sampler = MltSampler::new(7, 0.01, 0.3);
+        let initial = draw(&mut sampler, 4);
+        assert_eq!(initial, draw(&mut MltSampler::new(7, 0.01, 0.3), 4));
+
+        for _ in 0..20 {
+            sampler.start_iteration();
+            let proposed = draw(&mut sampler, 4);
+            assert!(proposed.iter().all(|u| (0.0..1.0).contains(u)));
+            sampler.reject();
+        }
+        sampler.start_iteration();
+        sampler.large_step = false;
+        let perturbed = draw(&mut sampler, 4);
+        for (a, b) in initial.iter().zip(&perturbed) {
+            let distance = (a - b).abs();
+            assert!(distance.min(1.0 - distance) < 0.1);
+        }
+    }
+
+    #[test]
+    fn streams_keep_their_dimensions_when_others_change_length() {
+        let mut sampler = MltSampler::new(11, 0.01, 0.3);
+        draw(&mut sampler, 2);
+        sampler.start_stream(Stream::Light);
+        let light = draw(&mut sampler, 3);
+
+        // A small step whose camera stream grows longer.
+        sampler.start_iteration();
+        sampler.large_step = false;
+        draw(&mut sampler, 6);
+        sampler.start_stream(Stream::Light);
+        for (a, b) in light.iter().zip(&draw(&mut sampler, 3)) {
+            let distance = (a - b).abs();
+            assert!(distance.min(1.0 - distance) < 0.1);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Procedural gradient noise.
+use crate::prelude::*;
+
+/// Ken Perlin's improved gradient noise at `p`, which is smooth, varies at a scale of one unit
+/// and lies roughly in [-1, 1].
+///
+/// Lattice gradients are chosen by hashing the integer coordinates instead of looking up a
+/// permutation table, so the noise is the same everywhere without any state.
+pub fn perlin(p: Point3) -> f64 {
+    let (x0, y0, z0) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let (x, y, z) = (p.x - x0, p.y - y0, p.z - z0);
+    let (i, j, k) = (x0 as i64, y0 as i64, z0 as i64);
+
+    let corner = |di: i64, dj: i64, dk: i64| {
+        let hash = hash(i + di, j + dj, k + dk);
+        gradient(hash, x - di as f64, y - dj as f64, z - dk as f64)
+    };
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 0), corner(1, 0, 0)),
+            lerp(u, corner(0, 1, 0), corner(1, 1, 0)),
+        ),
+        lerp(
+            v,
+            lerp(u, corner(0, 0, 1), corner(1, 0, 1)),
+            lerp(u, corner(0, 1, 1), corner(1, 1, 1)),
+        ),
+    )
+}
+
+/// Fractional Brownian motion, i.e. `octaves` layers of [`perlin`] noise, each with twice the
+/// frequency and half the amplitude of the previous one, normalized to lie roughly in [-1, 1].
+pub fn fbm(p: Point3, octaves: u32) -> f64 {
+    let (mut sum, mut amplitude, mut total, mut frequency) = (0.0, 1.0, 0.0, 1.0);
+    for _ in 0..octaves {
+        sum += amplitude * perlin(frequency * p);
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    if total > 0.0 {
+        sum / total
+    } else {
+        0.0
+    }
+}
+
+/// Perlin's quintic interpolation weight, whose first and second derivatives vanish at 0 and 1.
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// The dot product of the offset `(x, y, z)` with one of the twelve edge directions of a cube.
+fn gradient(hash: u32, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash % 12;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 8 || h == 9 {
+        x
+    } else {
+        z
+    };
+    let u = if h & 1 == 0 { u } else { -u };
+    let v = if h & 2 == 0 { v } else { -v };
+    u + v
+}
+
+/// Mixes the coordinates of a lattice point into well distributed bits.
+fn hash(i: i64, j: i64, k: i64) -> u32 {
+    let mut h = (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (j as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (k as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    (h >> 32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_vanishes_on_the_lattice_and_is_bounded() {
+        assert_eq!(perlin(Point3::new(3.0, -2.0, 7.0)), 0.0);
+        for i in 0..1000 {
+            let t = i as f64 * 0.037;
+            let value = perlin(Point3::new(t, 1.3 * t - 4.0, 0.7 * t + 2.0));
+            assert!(value.abs() <= 1.5, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Orthonormal bases.
+use crate::prelude::*;
+
+/// An orthonormal basis, used to move directions in and out of a local shading frame whose `w`
+/// axis is the surface normal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis around the unit vector `w`, following Duff et al., "Building an Orthonormal
+    /// Basis, Revisited" (2017).
+    pub fn from_w(w: Vec3) -> Self {
+        let sign = 1.0_f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        let u = Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x);
+        let v = Vec3::new(b, sign + w.y * w.y * a, -w.y);
+        Onb { u, v, w }
+    }
+
+    /// Builds a basis around the unit vector `w`, with `u` aligned to the projection of `tangent`
+    /// onto the plane orthogonal to `w`.
+    ///
+    /// Falls back to [`Onb::from_w`] if `tangent` is parallel to `w`.
+    pub fn from_w_and_tangent(w: Vec3, tangent: Vec3) -> Self {
+        let u = tangent - tangent.dot(w) * w;
+        if u.near_zero() {
+            return Onb::from_w(w);
+        }
+        let u = u.normal();
+        Onb {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
+    /// Transforms a direction from local coordinates to world coordinates.
+    pub fn to_world(&self, a: Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// Transforms a direction from world coordinates to local coordinates.
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
@@ -0,0 +1,593 @@
+//! Photon mapping, which traces light from the emitters and estimates radiance from the density
+//! of the photons it leaves on surfaces.
+use crate::aabb::Aabb;
+use crate::integrator::Scene;
+use crate::light::LightSampling;
+use crate::prelude::*;
+use crate::util::random_f64;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Light that landed on a surface.
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub p: Point3,
+    /// The unit direction towards where the photon came from.
+    pub wi: Vec3,
+    /// The flux carried by the photon.
+    pub power: Rgb,
+}
+
+/// A kd-tree over photons for nearest neighbor queries.
+///
+/// The tree is stored implicitly: the photon at the middle of every range of the array splits
+/// the rest of the range along one axis, into the ranges before and after it.
+#[derive(Debug, Clone, Default)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    /// The split axis of the photon at the same index.
+    axes: Vec<u8>,
+}
+
+/// A photon found by a query, ordered by its squared distance.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    distance_squared: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared.total_cmp(&other.distance_squared)
+    }
+}
+
+fn coordinate(p: Point3, axis: usize) -> f64 {
+    [p.x, p.y, p.z][axis]
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        Self::build(&mut photons, &mut axes);
+        PhotonMap { photons, axes }
+    }
+
+    /// Splits `photons` at their median along the longest axis of their bounds, recursively.
+    fn build(photons: &mut [Photon], axes: &mut [u8]) {
+        if photons.len() <= 1 {
+            return;
+        }
+        let bounds = photons
+            .iter()
+            .fold(Aabb::from_point(photons[0].p), |b, photon| {
+                b.expand(photon.p)
+            });
+        let axis = bounds.longest_axis();
+
+        let mid = photons.len() / 2;
+        photons.select_nth_unstable_by(mid, |a, b| {
+            coordinate(a.p, axis).total_cmp(&coordinate(b.p, axis))
+        });
+        axes[mid] = axis as u8;
+
+        let (left, right) = photons.split_at_mut(mid);
+        let (left_axes, right_axes) = axes.split_at_mut(mid);
+        Self::build(left, left_axes);
+        Self::build(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    pub fn photons(&self) -> &[Photon] {
+        &self.photons
+    }
+
+    /// Finds the `k` photons nearest to `p` closer than `max_distance`, returning them along
+    /// with their squared distances to `p`, in no particular order.
+    pub fn nearest(&self, p: Point3, k: usize, max_distance: f64) -> Vec<(&Photon, f64)> {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        let mut max_distance_squared = max_distance * max_distance;
+        if k > 0 {
+            self.search(0, self.len(), p, k, &mut heap, &mut max_distance_squared);
+        }
+        heap.into_iter()
+            .map(|c| (&self.photons[c.index], c.distance_squared))
+            .collect()
+    }
+
+    fn search(
+        &self,
+        start: usize,
+        end: usize,
+        p: Point3,
+        k: usize,
+        heap: &mut BinaryHeap<Candidate>,
+        max_distance_squared: &mut f64,
+    ) {
+        if start >= end {
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        let photon = &self.photons[mid];
+        let axis = self.axes[mid] as usize;
+        let delta = coordinate(p, axis) - coordinate(photon.p, axis);
+
+        // Visit the side containing `p` first, which shrinks the search radius sooner.
+        let (near, far) = if delta < 0.0 {
+            ((start, mid), (mid + 1, end))
+        } else {
+            ((mid + 1, end), (start, mid))
+        };
+        self.search(near.0, near.1, p, k, heap, max_distance_squared);
+
+        let distance_squared = (photon.p - p).len_squared();
+        if distance_squared < *max_distance_squared {
+            heap.push(Candidate {
+                distance_squared,
+                index: mid,
+            });
+            if heap.len() > k {
+                heap.pop();
+            }
+            if heap.len() == k {
+                *max_distance_squared = heap.peek().unwrap().distance_squared;
+            }
+        }
+
+        if delta * delta < *max_distance_squared {
+            self.search(far.0, far.1, p, k, heap, max_distance_squared);
+        }
+    }
+}
+
+/// Photon mapping, following Jensen, "Realistic Image Synthesis Using Photon Mapping" (2001).
+///
+/// Photons traced from the emitters are stored where they land on surfaces whose material can
+/// be evaluated: all of them in a global map, and those that only went through perfectly
+/// specular surfaces, such as `Metal` and `Dielectric`, in a caustic map as well. Camera rays
+/// follow specular surfaces to the first other surface, where lights are sampled directly,
+/// caustics are estimated from the caustic map, and the remaining indirect light is found by a
+/// final gather that estimates the global map where the gather rays land.
+///
+/// Photons are not stored in participating media, which they cross like specular surfaces.
+/// Camera rays sample lights directly at every collision with a medium and carry on.
+#[derive(Debug, Clone)]
+pub struct PhotonMapper {
+    /// The maximum number of bounces of photons and specular paths.
+    pub max_depth: u32,
+    /// The number of gather rays at every surface seen by the camera.
+    pub gather_rays: u32,
+    /// The number of photons of every density estimate.
+    pub nearest_photons: usize,
+    /// The largest distance of the photons of a density estimate.
+    pub max_radius: f64,
+    global: PhotonMap,
+    caustic: PhotonMap,
+}
+
+impl PhotonMapper {
+    /// Traces photons through `scene`, which must be the scene it renders, until the global
+    /// and the caustic maps hold `global_photons` and `caustic_photons` photons.
+    ///
+    /// Emission stops early if photons rarely land, e.g. when there are no specular surfaces
+    /// to form caustics.
+    pub fn new(
+        scene: &Scene,
+        global_photons: usize,
+        caustic_photons: usize,
+        max_depth: u32,
+    ) -> Self {
+        let mut global = Vec::with_capacity(global_photons);
+        let mut caustic = Vec::with_capacity(caustic_photons);
+        let (mut global_emitted, mut caustic_emitted) = (0, 0);
+
+        // A map only receives the photons of whole paths, so that its photons are scaled by the
+        // number of paths emitted while it was filling.
+        let max_emitted = 10 * global_photons.max(caustic_photons);
+        for _ in 0..max_emitted {
+            let store_global = global.len() < global_photons;
+            let store_caustic = caustic.len() < caustic_photons;
+            if !store_global && !store_caustic {
+                break;
+            }
+            global_emitted += store_global as usize;
+            caustic_emitted += store_caustic as usize;
+
+            trace_photon(scene, max_depth, |photon, is_caustic| {
+                if store_global {
+                    global.push(photon);
+                }
+                if store_caustic && is_caustic {
+                    caustic.push(photon);
+                }
+            });
+        }
+
+        let scale = |photons: &mut Vec<Photon>, emitted: usize| {
+            for photon in photons.iter_mut() {
+                photon.power /= emitted.max(1) as f64;
+            }
+        };
+        scale(&mut global, global_emitted);
+        scale(&mut caustic, caustic_emitted);
+
+        PhotonMapper {
+            max_depth,
+            gather_rays: 4,
+            nearest_photons: 50,
+            max_radius: INIFINTY,
+            global: PhotonMap::new(global),
+            caustic: PhotonMap::new(caustic),
+        }
+    }
+
+    pub fn global_map(&self) -> &PhotonMap {
+        &self.global
+    }
+
+    pub fn caustic_map(&self) -> &PhotonMap {
+        &self.caustic
+    }
+
+    /// Estimates the radiance arriving along `ray`.
+    pub fn radiance(&self, ray: &Ray, scene: &Scene) -> Rgb {
+        self.radiance_with_first_hit(ray, scene, |_| {})
+    }
+
+    /// Like [`PhotonMapper::radiance`], also passing the first hit of `ray`, or `None` if it
+    /// escapes, to `first_hit`, e.g. to collect AOVs without tracing the ray again.
+    pub fn radiance_with_first_hit(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        mut first_hit: impl FnMut(Option<&HitRecord>),
+    ) -> Rgb {
+        let mut ray = *ray;
+        let mut beta = Rgb::new(1.0, 1.0, 1.0);
+        let mut color = Rgb::default();
+        // Whether the light reaching the previous vertex directly was sampled already.
+        let mut direct_sampled = false;
+        for depth in 0..self.max_depth {
+            // 0.001 here is for fixing shadow acne.
+            let rec = scene.world().hit(&ray, 0.001, INIFINTY);
+            if depth == 0 {
+                first_hit(rec.as_ref());
+            }
+            let rec = match rec {
+                Some(rec) => rec,
+                None if direct_sampled => return color,
+                None => {
+                    let radiance = scene.environment().radiance(ray.direction.normal());
+                    return color + beta * radiance;
+                }
+            };
+            let material = match rec.material {
+                Some(material) => material,
+                None => break,
+            };
+            if !direct_sampled {
+                color += beta * material.emitted(&rec);
+            }
+
+            // Photons are only stored on surfaces, so light scattered in media is sampled
+            // directly at every collision and the path carries on.
+            direct_sampled = !rec.is_on_surface();
+            if direct_sampled {
+                color += beta * direct_lighting(scene, &ray, &rec);
+            }
+
+            // Subsurface random walks are followed to the point where light leaves the surface.
+            let scatter = material.scatter(&ray, &rec);
+            if !scatter.is_some_and(|scatter| scatter.below_surface) && is_diffuse(&ray, &rec) {
+                let caustics = self.estimate(&self.caustic, &ray, &rec);
+                let indirect = self.final_gather(scene, &ray, &rec);
+                return color + beta * (direct_lighting(scene, &ray, &rec) + caustics + indirect);
+            }
+
+            match scatter {
+                Some(scatter) => {
+                    beta *= scatter.attenuation;
+                    ray = scatter.ray;
+                }
+                None => break,
+            }
+        }
+        color
+    }
+
+    /// Estimates the light scattered at the hit point of `ray` from the density of the photons
+    /// of `map` around it.
+    fn estimate(&self, map: &PhotonMap, ray: &Ray, rec: &HitRecord) -> Rgb {
+        let material = match rec.material {
+            Some(material) => material,
+            None => return Rgb::default(),
+        };
+        let photons = map.nearest(rec.p, self.nearest_photons, self.max_radius);
+        let radius_squared = photons.iter().map(|&(_, d)| d).fold(0.0, f64::max);
+        if radius_squared <= 0.0 {
+            return Rgb::default();
+        }
+
+        let mut color = Rgb::default();
+        for (photon, _) in photons {
+            let cosine = photon.wi.dot(rec.normal).abs();
+            if cosine < 1.0e-6 {
+                continue;
+            }
+            if let Some((f, _)) = material.eval(ray, rec, photon.wi) {
+                color += f / cosine * photon.power;
+            }
+        }
+        color / (PI * radius_squared)
+    }
+
+    /// Estimates the light reflected at the hit point of `ray` after scattering at least once
+    /// off a surface that is not perfectly specular.
+    fn final_gather(&self, scene: &Scene, ray: &Ray, rec: &HitRecord) -> Rgb {
+        let material = match rec.material {
+            Some(material) => material,
+            None => return Rgb::default(),
+        };
+
+        let mut color = Rgb::default();
+        for _ in 0..self.gather_rays {
+            let (mut beta, mut gather) = match scatter_from_surface(material, ray, rec) {
+                Some(scatter) => (scatter.attenuation, scatter.ray),
+                None => continue,
+            };
+
+            // Emitters and the environment found by gather rays, directly or through specular
+            // surfaces, are accounted for by direct lighting and caustics.
+            for _ in 0..self.max_depth {
+                let hit = match scene.world().hit(&gather, 0.001, INIFINTY) {
+                    Some(hit) => hit,
+                    None => break,
+                };
+                let scatter = hit.material.and_then(|m| m.scatter(&gather, &hit));
+                if !scatter.is_some_and(|scatter| scatter.below_surface)
+                    && is_diffuse(&gather, &hit)
+                {
+                    color += beta * self.estimate(&self.global, &gather, &hit);
+                    break;
+                }
+                match scatter {
+                    Some(scatter) => {
+                        beta *= scatter.attenuation;
+                        gather = scatter.ray;
+                    }
+                    None => break,
+                }
+            }
+        }
+        color / self.gather_rays.max(1) as f64
+    }
+}
+
+/// Samples the environment and one of the lights from the hit point of `ray`.
+///
+/// Unlike the path tracer, emitters are never found by scattered rays, so light samples need
+/// no multiple importance sampling.
+fn direct_lighting(scene: &Scene, ray: &Ray, rec: &HitRecord) -> Rgb {
+    let material = match rec.material {
+        Some(material) => material,
+        None => return Rgb::default(),
+    };
+    let mut color = Rgb::default();
+
+    let (direction, pdf) = scene.environment().sample(random_f64(), random_f64());
+    if pdf > 0.0 {
+        if let Some((f, _)) = material.eval(ray, rec, direction) {
+            if f.luminance() > 0.0 {
+                let transmittance = scene.transmittance(rec.p, direction, INIFINTY);
+                color += transmittance * f * scene.environment().radiance(direction) / pdf;
+            }
+        }
+    }
+
+    let sampler = scene.light_sampler();
+    let (index, pmf) = match sampler.sample(LightSampling::Tree, rec.p, random_f64()) {
+        Some((index, pmf)) if pmf > 0.0 => (index, pmf),
+        _ => return color,
+    };
+    let sample = match scene.lights()[index].sample_li(rec.p, random_f64(), random_f64()) {
+        Some(sample) if sample.pdf > 0.0 => sample,
+        _ => return color,
+    };
+    if let Some((f, _)) = material.eval(ray, rec, sample.direction) {
+        // Stop short of the light so that it does not occlude itself.
+        let max_t = sample.distance * (1.0 - 1.0e-4);
+        if f.luminance() > 0.0 {
+            let transmittance = scene.transmittance(rec.p, sample.direction, max_t);
+            color += transmittance * f * sample.radiance / (pmf * sample.pdf);
+        }
+    }
+    color
+}
+
+/// Samples a ray scattered by `material` from the hit point itself.
+///
+/// Gather rays estimate the light arriving at the hit point, so scattering below the surface is
+/// sampled again, which draws directions from the lobe of light leaving the surface alone. Very
+/// unlikely lobes are given up after a few attempts.
+fn scatter_from_surface(material: &dyn Material, ray: &Ray, rec: &HitRecord) -> Option<Scatter> {
+    (0..16)
+        .map(|_| material.scatter(ray, rec))
+        .find(|scatter| !scatter.is_some_and(|scatter| scatter.below_surface))
+        .flatten()
+}
+
+/// Whether the material hit by `ray` can be evaluated, i.e. is not perfectly specular, on a
+/// surface. Collisions with media are followed like specular bounces.
+fn is_diffuse(ray: &Ray, rec: &HitRecord) -> bool {
+    rec.is_on_surface()
+        && rec
+            .material
+            .is_some_and(|m| m.eval(ray, rec, rec.normal).is_some())
+}
+
+/// Traces a photon from an emitter, passing every photon it leaves on surfaces that are not
+/// perfectly specular to `store`, along with whether it only went through specular surfaces
+/// before. Photons travelling below a surface, e.g. during a subsurface random walk, are only
+/// stored once they leave it.
+fn trace_photon(scene: &Scene, max_depth: u32, mut store: impl FnMut(Photon, bool)) {
+    let emitters = scene.emitter_sampler();
+    let (emitter, pmf) = match emitters.sample(random_f64()) {
+        Some(sampled) => sampled,
+        None => return,
+    };
+    let u_pos = (random_f64(), random_f64());
+    let u_dir = (random_f64(), random_f64());
+    let sample = match emitters.sample_le(scene, emitter, u_pos, u_dir) {
+        Some(sample) if sample.pdf_pos > 0.0 && sample.pdf_dir > 0.0 => sample,
+        _ => return,
+    };
+
+    let cosine = if sample.normal == Vec3::default() {
+        1.0
+    } else {
+        sample.normal.dot(sample.ray.direction).abs()
+    };
+    let mut power = sample.radiance * (cosine / (pmf * sample.pdf_pos * sample.pdf_dir));
+    let mut ray = sample.ray;
+    let mut is_caustic = false;
+    let mut below_surface = false;
+    for depth in 0..max_depth {
+        let rec = match scene.world().hit(&ray, 0.001, INIFINTY) {
+            Some(rec) => rec,
+            None => return,
+        };
+        let material = match rec.material {
+            Some(material) => material,
+            None => return,
+        };
+
+        if !below_surface && is_diffuse(&ray, &rec) {
+            let photon = Photon {
+                p: rec.p,
+                wi: -ray.direction.normal(),
+                power,
+            };
+            store(photon, is_caustic);
+            is_caustic = false;
+        } else if depth == 0 {
+            is_caustic = true;
+        }
+
+        let scatter = material.scatter(&ray, &rec);
+        match scatter {
+            Some(scatter) => {
+                below_surface = scatter.below_surface;
+                power *= scatter.attenuation;
+                ray = scatter.ray;
+            }
+            None => return,
+        }
+        if power.luminance() <= 0.0 {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::PointLight;
+    use crate::material::Lambertian;
+    use crate::sampler::{with_sampler, IndependentSampler};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let photons: Vec<Photon> = (0..500)
+            .map(|i| {
+                let t = i as f64;
+                Photon {
+                    p: Point3::new((t * 0.37).sin(), (t * 0.71).cos(), (t * 1.13).sin() * 0.5),
+                    wi: Vec3::new(0.0, 1.0, 0.0),
+                    power: Rgb::new(1.0, 1.0, 1.0),
+                }
+            })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+        assert_eq!(map.len(), photons.len());
+
+        for &(p, k, max_distance) in &[
+            (Point3::new(0.1, 0.2, 0.0), 10, INIFINTY),
+            (Point3::new(-0.5, 0.9, 0.3), 25, 0.3),
+            (Point3::new(2.0, 2.0, 2.0), 5, 0.5),
+        ] {
+            let mut found: Vec<f64> = map
+                .nearest(p, k, max_distance)
+                .iter()
+                .map(|&(_, d)| d)
+                .collect();
+            found.sort_by(f64::total_cmp);
+
+            let mut expected: Vec<f64> = photons
+                .iter()
+                .map(|photon| (photon.p - p).len_squared())
+                .filter(|&d| d < max_distance * max_distance)
+                .collect();
+            expected.sort_by(f64::total_cmp);
+            expected.truncate(k);
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn photon_density_matches_the_irradiance_of_a_point_light() {
+        // A diffuse floor lit by a point light at height one, without other surfaces for
+        // photons to bounce off.
+        let albedo = 0.5;
+        let intensity = 2.0;
+        let mut world = HittableList::new();
+        world.add(Plane::new(
+            Point3::default(),
+            Vec3::new(0.0, 1.0, 0.0),
+            Lambertian::new(Rgb::from(albedo)),
+        ));
+        let mut scene = Scene::new(world, Rgb::default());
+        scene.add_light(PointLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Rgb::from(intensity),
+        ));
+
+        let sampler = Rc::new(RefCell::new(IndependentSampler::new(41)));
+        let mut mapper = with_sampler(sampler, || PhotonMapper::new(&scene, 100_000, 0, 4));
+        mapper.nearest_photons = 400;
+
+        for &x in &[0.0, 0.5, 1.0, 1.5] {
+            let ray = Ray::new(Point3::new(x, 1.0, 0.3), Vec3::new(0.0, -1.0, 0.0));
+            let rec = scene.world().hit(&ray, 0.001, INIFINTY).unwrap();
+            let estimate = mapper.estimate(mapper.global_map(), &ray, &rec);
+
+            let distance_squared: f64 = 1.0 + x * x + 0.3 * 0.3;
+            let irradiance = intensity / distance_squared.powf(1.5);
+            let expected = albedo / PI * irradiance;
+            let error = (estimate.r - expected).abs() / expected;
+            assert!(error < 0.1, "at {}: {} != {}", x, estimate.r, expected);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! 3D hittable infinite [`Plane`]s.
+use crate::hittable::{HitRecord, Hittable};
+use crate::onb::Onb;
+use crate::prelude::*;
+
+/// An infinite plane through `point`, e.g. a ground plane.
+///
+/// The surface coordinates are distances in scene units along two tangents of the plane, so
+/// textures repeat every unit.
+#[derive(Debug)]
+pub struct Plane {
+    pub point: Point3,
+    /// The unit normal of the plane, whose side it points to is the outside.
+    pub normal: Vec3,
+    pub material: Option<Box<dyn Material>>,
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, material: impl Material + 'static) -> Self {
+        Plane {
+            point,
+            normal: normal.normal(),
+            material: Some(Box::new(material)),
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1.0e-12 {
+            return None;
+        }
+        let t = self.normal.dot(self.point - ray.origin) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let p = ray.at(t);
+        let frame = Onb::from_w(self.normal);
+        let offset = p - self.point;
+        let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
+        record.set_face_normal(ray, self.normal);
+        record.u = offset.dot(frame.u);
+        record.v = offset.dot(frame.v);
+        record.dpdu = frame.u;
+        record.dpdv = frame.v;
+
+        if record.is_cut_out() {
+            return None;
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn floor() -> Plane {
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        Plane::new(
+            Point3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            material,
+        )
+    }
+
+    #[test]
+    fn normals_face_the_ray() {
+        let plane = floor();
+        let down = Ray::new(Point3::new(1.0, 1.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = plane.hit(&down, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1.0e-12);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+
+        let up = Ray::new(Point3::new(1.0, -3.0, 2.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = plane.hit(&up, 0.0, INIFINTY).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, -1.0, 0.0));
+
+        assert!(plane.hit(&down, 0.0, 1.5).is_none());
+        let parallel = Ray::new(Point3::new(1.0, 1.0, 2.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.hit(&parallel, 0.0, INIFINTY).is_none());
+    }
+
+    #[test]
+    fn surface_coordinates_are_distances_along_the_tangents() {
+        let plane = floor();
+        let down = Ray::new(Point3::new(1.0, 1.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = plane.hit(&down, 0.0, INIFINTY).unwrap();
+
+        assert!((rec.dpdu.len() - 1.0).abs() < 1.0e-12);
+        assert!((rec.dpdv.len() - 1.0).abs() < 1.0e-12);
+        assert!(rec.dpdu.dot(rec.dpdv).abs() < 1.0e-12);
+        assert!(rec.dpdu.dot(plane.normal).abs() < 1.0e-12);
+        assert!(rec.dpdv.dot(plane.normal).abs() < 1.0e-12);
+        let p = plane.point + rec.u * rec.dpdu + rec.v * rec.dpdv;
+        assert!((p - rec.p).len() < 1.0e-12, "{:?}", p);
+    }
+}
@@ -8,11 +8,17 @@
 
 pub use crate::camera::Camera;
 pub use crate::color::Rgb;
+pub use crate::cone::Cone;
 pub use crate::consts::*;
+pub use crate::cylinder::Cylinder;
+pub use crate::disk::Disk;
 pub use crate::error::{ErrorKind, Result};
+pub use crate::hittable::*;
+pub use crate::material::{Material, Scatter};
+pub use crate::plane::Plane;
 pub use crate::ray::Ray;
+pub use crate::sphere::Sphere;
+pub use crate::torus::Torus;
+pub use crate::triangle::Triangle;
 pub use crate::vec::raw::Scalar;
 pub use crate::vec::{Point3, Vec3};
-pub use crate::hittable::*;
-pub use crate::sphere::Sphere;
-pub use crate::material::Material;
@@ -6,11 +6,22 @@ use crate::prelude::*;
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// The wavelength in nanometers carried by the ray in spectral mode.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            wavelength: None,
+        }
+    }
+
+    /// Returns the ray tagged with a `wavelength` in nanometers.
+    pub fn with_wavelength(self, wavelength: Option<f64>) -> Self {
+        Ray { wavelength, ..self }
     }
 
     pub fn at(&self, t: f64) -> Point3 {
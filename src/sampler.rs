@@ -0,0 +1,125 @@
+//! [`Sampler`]s that provide the random numbers consumed while tracing a sample.
+use rand::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A source of the values of a sample, consumed one dimension at a time.
+///
+/// Everything that draws random numbers through [`random_f64`](crate::util::random_f64) or
+/// [`SamplerRng`] reads the dimensions of the sampler installed on the thread with
+/// [`with_sampler`], which makes a traced path a deterministic function of its sample.
+pub trait Sampler {
+    /// Returns the next dimension of the current sample, in [0, 1).
+    fn next_1d(&mut self) -> f64;
+
+    /// Continues with the first dimension of `stream`. Samples start in [`Stream::Camera`].
+    ///
+    /// Samplers whose dimensions are all independent can ignore streams, which is the default.
+    fn start_stream(&mut self, _stream: Stream) {}
+}
+
+/// Separate sequences of the dimensions of a sample, one for each part of a path.
+///
+/// Keeping the parts apart lets a dimension play the same role whenever a sample is traced,
+/// e.g. the dimensions of a light path do not shift when the camera path it follows changes
+/// its length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// The point of the image and the path traced from the camera.
+    Camera,
+    /// The path traced from a light.
+    Light,
+    /// The connections between camera and light paths.
+    Connection,
+}
+
+impl Stream {
+    /// The number of streams.
+    pub const COUNT: usize = 3;
+}
+
+/// A sampler whose dimensions are independent uniform random numbers.
+#[derive(Debug, Clone)]
+pub struct IndependentSampler {
+    rng: StdRng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn next_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<RefCell<dyn Sampler>>>> = RefCell::new(None);
+}
+
+/// Restores the previous sampler of the thread when dropped, even if the sampled code panics.
+struct Restore(Option<Rc<RefCell<dyn Sampler>>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Runs `f` with `sampler` providing the random numbers of the thread.
+pub fn with_sampler<R>(sampler: Rc<RefCell<dyn Sampler>>, f: impl FnOnce() -> R) -> R {
+    let _restore = Restore(CURRENT.with(|current| current.replace(Some(sampler))));
+    f()
+}
+
+/// Returns the next dimension of the sampler of the thread, or an independent random number if
+/// no sampler is installed.
+pub(crate) fn next_1d() -> f64 {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(sampler) => sampler.borrow_mut().next_1d(),
+        None => rand::thread_rng().gen(),
+    })
+}
+
+/// Continues the sampler of the thread, if any, with the first dimension of `stream`.
+pub(crate) fn start_stream(stream: Stream) {
+    CURRENT.with(|current| {
+        if let Some(sampler) = &*current.borrow() {
+            sampler.borrow_mut().start_stream(stream);
+        }
+    });
+}
+
+/// A random number generator reading the dimensions of the sampler of the thread, for code
+/// written against [`rand::Rng`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SamplerRng;
+
+impl RngCore for SamplerRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // `Rng::gen::<f64>` keeps the 53 most significant bits, which recovers the dimension.
+        (next_1d() * 2.0_f64.powi(64)) as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
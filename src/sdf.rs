@@ -0,0 +1,405 @@
+//! Surfaces given implicitly by signed distance functions, rendered by sphere tracing.
+use crate::aabb::Aabb;
+use crate::csg::Operation;
+use crate::hittable::{HitRecord, Hittable};
+use crate::prelude::*;
+use std::fmt::Debug;
+
+/// A signed distance function, the distance from a point to the closest point of a surface,
+/// negative inside it.
+///
+/// Functions that only bound the distance from below, e.g. after smooth combinations or for
+/// fractals, work too, with the step of [`Sdf`] scaled down if they overestimate it.
+pub trait Distance: Debug + Send + Sync {
+    fn distance(&self, p: Point3) -> f64;
+}
+
+/// A ball of `radius` around `center`.
+#[derive(Debug, Clone, Copy)]
+pub struct Ball {
+    pub center: Point3,
+    pub radius: f64,
+}
+
+impl Ball {
+    pub fn new(center: Point3, radius: f64) -> Self {
+        Ball { center, radius }
+    }
+}
+
+impl Distance for Ball {
+    fn distance(&self, p: Point3) -> f64 {
+        (p - self.center).len() - self.radius
+    }
+}
+
+/// A box around `center`, extending by `half_extents` along each axis, whose edges are
+/// rounded off by `rounding`.
+#[derive(Debug, Clone, Copy)]
+pub struct RoundBox {
+    pub center: Point3,
+    pub half_extents: Vec3,
+    pub rounding: f64,
+}
+
+impl RoundBox {
+    pub fn new(center: Point3, half_extents: Vec3, rounding: f64) -> Self {
+        RoundBox {
+            center,
+            half_extents,
+            rounding,
+        }
+    }
+}
+
+impl Distance for RoundBox {
+    fn distance(&self, p: Point3) -> f64 {
+        let r = self.rounding;
+        let q = (p - self.center).map(f64::abs) - self.half_extents + Vec3::new(r, r, r);
+        let outside = q.map(|x| x.max(0.0)).len();
+        let inside = q.x.max(q.y).max(q.z).min(0.0);
+        outside + inside - r
+    }
+}
+
+/// The points within `radius` of the segment from `a` to `b`.
+#[derive(Debug, Clone, Copy)]
+pub struct Capsule {
+    pub a: Point3,
+    pub b: Point3,
+    pub radius: f64,
+}
+
+impl Capsule {
+    pub fn new(a: Point3, b: Point3, radius: f64) -> Self {
+        Capsule { a, b, radius }
+    }
+}
+
+impl Distance for Capsule {
+    fn distance(&self, p: Point3) -> f64 {
+        let (pa, ba) = (p - self.a, self.b - self.a);
+        let h = (pa.dot(ba) / ba.len_squared()).clamp(0.0, 1.0);
+        (pa - h * ba).len() - self.radius
+    }
+}
+
+/// A torus around `center` in the plane orthogonal to the unit `axis`, like a
+/// [`Torus`](crate::torus::Torus).
+#[derive(Debug, Clone, Copy)]
+pub struct Ring {
+    pub center: Point3,
+    pub axis: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl Ring {
+    pub fn new(center: Point3, axis: Vec3, major_radius: f64, minor_radius: f64) -> Self {
+        Ring {
+            center,
+            axis: axis.normal(),
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Distance for Ring {
+    fn distance(&self, p: Point3) -> f64 {
+        let q = p - self.center;
+        let height = q.dot(self.axis);
+        let ring = (q - height * self.axis).len() - self.major_radius;
+        (ring * ring + height * height).sqrt() - self.minor_radius
+    }
+}
+
+/// The Mandelbulb fractal of `power`, scaled to fit in a ball of about `scale` around
+/// `center`.
+///
+/// Its distance is estimated from the derivative of the iteration, which needs more
+/// `iterations` for finer detail.
+#[derive(Debug, Clone, Copy)]
+pub struct Mandelbulb {
+    pub center: Point3,
+    pub scale: f64,
+    pub power: f64,
+    pub iterations: usize,
+}
+
+impl Mandelbulb {
+    pub fn new(center: Point3, scale: f64) -> Self {
+        Mandelbulb {
+            center,
+            scale,
+            power: 8.0,
+            iterations: 12,
+        }
+    }
+}
+
+impl Distance for Mandelbulb {
+    fn distance(&self, p: Point3) -> f64 {
+        let c = (p - self.center) / self.scale;
+        let n = self.power;
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.len();
+        for _ in 0..self.iterations {
+            if !(1.0e-12..=2.0).contains(&r) {
+                break;
+            }
+            // Raise z to the power in spherical coordinates, tracking the derivative.
+            let theta = (z.z / r).acos() * n;
+            let phi = z.y.atan2(z.x) * n;
+            dr = r.powf(n - 1.0) * n * dr + 1.0;
+            let direction = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            );
+            z = r.powf(n) * direction + c;
+            r = z.len();
+        }
+        if r < 1.0e-12 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+}
+
+/// Combines two distance functions like a [`Csg`](crate::csg::Csg), with the seams blended
+/// over about `smoothness` if it is positive.
+#[derive(Debug)]
+pub struct Combination {
+    pub operation: Operation,
+    pub left: Box<dyn Distance>,
+    pub right: Box<dyn Distance>,
+    pub smoothness: f64,
+}
+
+impl Combination {
+    pub fn new(
+        operation: Operation,
+        left: impl Distance + 'static,
+        right: impl Distance + 'static,
+    ) -> Self {
+        Self::smooth(operation, left, right, 0.0)
+    }
+
+    pub fn smooth(
+        operation: Operation,
+        left: impl Distance + 'static,
+        right: impl Distance + 'static,
+        smoothness: f64,
+    ) -> Self {
+        Combination {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+            smoothness,
+        }
+    }
+
+    /// The smooth union, which melts the shapes into each other like blobs.
+    pub fn smooth_union(
+        left: impl Distance + 'static,
+        right: impl Distance + 'static,
+        smoothness: f64,
+    ) -> Self {
+        Self::smooth(Operation::Union, left, right, smoothness)
+    }
+}
+
+impl Distance for Combination {
+    fn distance(&self, p: Point3) -> f64 {
+        let a = self.left.distance(p);
+        let b = self.right.distance(p);
+        // Differences intersect the left shape with the complement of the right one.
+        let (a, b, sign) = match self.operation {
+            Operation::Union => (a, b, 1.0),
+            Operation::Intersection => (-a, -b, -1.0),
+            Operation::Difference => (-a, b, -1.0),
+        };
+        let k = self.smoothness;
+        if k <= 0.0 {
+            return sign * a.min(b);
+        }
+
+        // The polynomial smooth minimum, which only differs from the minimum within `k`.
+        let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+        sign * (b + h * (a - b) - k * h * (1.0 - h))
+    }
+}
+
+/// Morphs from the `left` shape at `t` = 0 to the `right` one at `t` = 1.
+#[derive(Debug)]
+pub struct Blend {
+    pub left: Box<dyn Distance>,
+    pub right: Box<dyn Distance>,
+    pub t: f64,
+}
+
+impl Blend {
+    pub fn new(left: impl Distance + 'static, right: impl Distance + 'static, t: f64) -> Self {
+        Blend {
+            left: Box::new(left),
+            right: Box::new(right),
+            t,
+        }
+    }
+}
+
+impl Distance for Blend {
+    fn distance(&self, p: Point3) -> f64 {
+        (1.0 - self.t) * self.left.distance(p) + self.t * self.right.distance(p)
+    }
+}
+
+/// The surface where a signed distance function vanishes, within `bounds`.
+///
+/// Rays are intersected by sphere tracing: stepping by the distance to the surface, which is
+/// safe as long as it is never overestimated, until the distance changes sign. The normal is
+/// the gradient of the distance, estimated by central differences over `epsilon`. There are no
+/// surface coordinates.
+#[derive(Debug)]
+pub struct Sdf {
+    pub distance: Box<dyn Distance>,
+    pub bounds: Aabb,
+    pub material: Option<Box<dyn Material>>,
+    /// The smallest step, which also sets the precision of the hits.
+    pub epsilon: f64,
+    /// Scales the steps, below 1 for distance functions that overestimate the distance.
+    pub step_scale: f64,
+    /// The number of steps after which a ray is considered to miss.
+    pub max_steps: usize,
+}
+
+impl Sdf {
+    pub fn new(
+        distance: impl Distance + 'static,
+        bounds: Aabb,
+        material: impl Material + 'static,
+    ) -> Self {
+        Sdf {
+            distance: Box::new(distance),
+            bounds,
+            material: Some(Box::new(material)),
+            epsilon: 1.0e-4,
+            step_scale: 1.0,
+            max_steps: 512,
+        }
+    }
+
+    /// The gradient of the distance at `p` by central differences, pointing outwards.
+    fn gradient(&self, p: Point3) -> Vec3 {
+        let h = self.epsilon;
+        let f = |dx, dy, dz| self.distance.distance(p + Vec3::new(dx, dy, dz));
+        Vec3::new(
+            f(h, 0.0, 0.0) - f(-h, 0.0, 0.0),
+            f(0.0, h, 0.0) - f(0.0, -h, 0.0),
+            f(0.0, 0.0, h) - f(0.0, 0.0, -h),
+        )
+    }
+}
+
+impl Hittable for Sdf {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (mut t, t_end) = self.bounds.clip(ray, t_min, t_max)?;
+        let length = ray.direction.len();
+        let mut d = self.distance.distance(ray.at(t));
+        // The side the ray starts on, so that rays leaving the surface do not hit it again.
+        let side = if d < 0.0 { -1.0 } else { 1.0 };
+
+        for _ in 0..self.max_steps {
+            let step = (self.step_scale * d.abs()).max(self.epsilon) / length;
+            let next = t + step;
+            if next > t_end {
+                return None;
+            }
+            let next_d = self.distance.distance(ray.at(next));
+            if next_d * side > 0.0 {
+                t = next;
+                d = next_d;
+                continue;
+            }
+
+            // The surface was crossed within the step, so refine by bisection.
+            let (mut a, mut b) = (t, next);
+            while (b - a) * length > 1.0e-3 * self.epsilon {
+                let mid = 0.5 * (a + b);
+                if self.distance.distance(ray.at(mid)) * side > 0.0 {
+                    a = mid;
+                } else {
+                    b = mid;
+                }
+            }
+            let t = b;
+            let p = ray.at(t);
+            let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
+            let gradient = self.gradient(p);
+            let outward_normal = if gradient.near_zero() {
+                -ray.direction / length
+            } else {
+                gradient.normal()
+            };
+            record.set_face_normal(ray, outward_normal);
+            if record.is_cut_out() {
+                // Carry on marching from just behind the cut-out surface.
+                return self.hit(ray, t + self.epsilon / length, t_max);
+            }
+            return Some(record);
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn sphere_tracing_finds_both_sides_of_a_ball() {
+        let ball = Ball::new(Point3::new(0.0, 0.0, 0.0), 1.0);
+        let bounds = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let sdf = Sdf::new(ball, bounds, Lambertian::new(Rgb::new(0.5, 0.5, 0.5)));
+
+        let ray = Ray::new(Point3::new(-3.0, 0.1, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let entry = sdf.hit(&ray, 0.0, INIFINTY).unwrap();
+        let expected = -(1.0f64 - 0.01).sqrt();
+        assert!((entry.p.x - expected).abs() < 1.0e-6, "{:?}", entry.p);
+        assert!(entry.front_face);
+        assert!(
+            (entry.normal - entry.p).len() < 1.0e-6,
+            "{:?}",
+            entry.normal
+        );
+
+        let exit = sdf.hit(&ray, entry.t + 1.0e-3, INIFINTY).unwrap();
+        assert!((exit.p.x + expected).abs() < 1.0e-6, "{:?}", exit.p);
+        assert!(!exit.front_face);
+        assert!(sdf.hit(&ray, exit.t + 1.0e-3, INIFINTY).is_none());
+    }
+
+    #[test]
+    fn smooth_combinations_only_differ_near_the_seam() {
+        let a = Ball::new(Point3::new(-1.0, 0.0, 0.0), 1.0);
+        let b = Ball::new(Point3::new(1.0, 0.0, 0.0), 1.0);
+        let sharp = Combination::new(Operation::Union, a, b);
+        let smooth = Combination::smooth_union(a, b, 0.5);
+        let difference = Combination::smooth(Operation::Difference, a, b, 0.5);
+
+        let seam = Point3::new(0.0, 0.0, 0.0);
+        assert!(smooth.distance(seam) < sharp.distance(seam));
+        assert!(difference.distance(seam) > 0.0);
+
+        let far = Point3::new(-2.5, 0.0, 0.0);
+        assert!((smooth.distance(far) - sharp.distance(far)).abs() < 1.0e-12);
+        assert!((difference.distance(far) - 0.5).abs() < 1.0e-12);
+    }
+}
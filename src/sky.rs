@@ -0,0 +1,277 @@
+//! An analytic daylight [`PhysicalSky`].
+use crate::environment::Environment;
+use crate::onb::Onb;
+use crate::prelude::*;
+use crate::spectrum::xyz_to_srgb;
+
+/// The angular radius of the sun seen from the earth, in radians.
+const SUN_ANGULAR_RADIUS: f64 = 0.004_65;
+
+/// The luminance of the sun outside the atmosphere in kcd/m², which makes the illuminance of
+/// the sun at the top of the atmosphere about 128 klx.
+const SUN_LUMINANCE: f64 = 1.88e6;
+
+/// A clear sky following Preetham et al., "A Practical Analytic Model for Daylight" (1999),
+/// with a sun disk that is importance sampled like a light.
+///
+/// Radiance is given in kcd/m² times `intensity`, so a white surface under the noon sun reaches
+/// a radiance of about 40; lower the exposure or the intensity accordingly. Below the horizon, a
+/// diffuse ground of color `ground_albedo` reflects the light of the sky and the sun.
+#[derive(Debug, Clone)]
+pub struct PhysicalSky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    ground_albedo: Rgb,
+    intensity: f64,
+    /// Perez coefficients for the luminance `Y` and the chromaticities `x` and `y`.
+    perez: [[f64; 5]; 3],
+    /// The luminance and chromaticities at the zenith.
+    zenith: [f64; 3],
+    sun_radiance: Rgb,
+    ground_radiance: Rgb,
+    /// The probability of sampling the sun rather than the whole sphere.
+    sun_probability: f64,
+}
+
+impl PhysicalSky {
+    /// Creates a sky lit by the sun in the unit `sun_direction`.
+    ///
+    /// `turbidity` describes the haziness of the atmosphere, from 2 for a very clear sky to about
+    /// 10 for a hazy one.
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Rgb) -> Self {
+        let sun_direction = sun_direction.normal();
+        let t = turbidity.max(1.0);
+
+        // The model is only defined for the sun above the horizon.
+        let theta_s = sun_direction.y.clamp(0.0, 1.0).acos();
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let cubic =
+            |c: [f64; 4]| c[0] * theta_s.powi(3) + c[1] * theta_s.powi(2) + c[2] * theta_s + c[3];
+        let zenith_x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+            + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+            + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+            + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+            + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let mut sky = PhysicalSky {
+            sun_direction,
+            turbidity: t,
+            ground_albedo,
+            intensity: 1.0,
+            perez,
+            zenith: [zenith_luminance, zenith_x, zenith_y],
+            sun_radiance: Self::sun_radiance(sun_direction, t),
+            ground_radiance: Rgb::default(),
+            sun_probability: 0.0,
+        };
+
+        // Integrate the irradiance on the ground from the sky and the sun.
+        let n = 64;
+        let mut sky_irradiance = Rgb::default();
+        for j in 0..n {
+            for i in 0..4 * n {
+                let cos_theta = (j as f64 + 0.5) / n as f64;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let phi = 2.0 * PI * (i as f64 + 0.5) / (4 * n) as f64;
+                let direction = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                sky_irradiance += sky.sky_radiance(direction) * cos_theta;
+            }
+        }
+        let sky_irradiance = sky_irradiance * (2.0 * PI / (4 * n * n) as f64);
+        let sun_irradiance = sky.sun_radiance * sky.sun_solid_angle() * sun_direction.y.max(0.0);
+        sky.ground_radiance = ground_albedo * (sky_irradiance + sun_irradiance) / PI;
+
+        if sky.sun_radiance.luminance() > 0.0 {
+            let sun = sun_irradiance.luminance();
+            sky.sun_probability = (sun / (sun + sky_irradiance.luminance())).clamp(0.1, 0.9);
+        }
+        sky
+    }
+
+    /// Returns the direction of the sun at `elevation` degrees above the horizon, and at
+    /// `azimuth` degrees clockwise from `-z` seen from above.
+    pub fn sun_direction(elevation: f64, azimuth: f64) -> Vec3 {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        Vec3::new(
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        )
+    }
+
+    /// Scales the radiance of the sky and the sun by `intensity`.
+    pub fn with_intensity(self, intensity: f64) -> Self {
+        PhysicalSky { intensity, ..self }
+    }
+
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    pub fn ground_albedo(&self) -> Rgb {
+        self.ground_albedo
+    }
+
+    /// The radiance of the sun disk after passing through the atmosphere, from the Rayleigh and
+    /// aerosol optical depths of the model at representative wavelengths of the RGB channels.
+    fn sun_radiance(sun_direction: Vec3, turbidity: f64) -> Rgb {
+        if sun_direction.y <= 0.0 {
+            return Rgb::default();
+        }
+
+        let theta_s = sun_direction.y.acos();
+        let relative_mass =
+            1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * turbidity - 0.04586;
+        let transmittance = |lambda: f64| {
+            let rayleigh = 0.008735 * lambda.powf(-4.08);
+            let aerosol = beta * lambda.powf(-1.3);
+            (-relative_mass * (rayleigh + aerosol)).exp()
+        };
+
+        SUN_LUMINANCE
+            * Rgb::new(
+                transmittance(0.61),
+                transmittance(0.55),
+                transmittance(0.465),
+            )
+    }
+
+    fn sun_solid_angle(&self) -> f64 {
+        2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos())
+    }
+
+    /// The Perez distribution function for a direction with zenith angle cosine `cos_theta`, at
+    /// angle `gamma` from the sun.
+    fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        (1.0 + c[0] * (c[1] / cos_theta.max(0.01)).exp())
+            * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+    }
+
+    /// The radiance of the sky without the sun, for a unit `direction` above the horizon.
+    fn sky_radiance(&self, direction: Vec3) -> Rgb {
+        let cos_theta = direction.y.max(0.0);
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let cos_theta_s = self.sun_direction.y.max(0.0);
+        let theta_s = cos_theta_s.acos();
+
+        let mut values = [0.0; 3];
+        for (k, value) in values.iter_mut().enumerate() {
+            *value = self.zenith[k] * Self::perez(&self.perez[k], cos_theta, gamma)
+                / Self::perez(&self.perez[k], 1.0, theta_s);
+        }
+        let [luminance, x, y] = values;
+        if y <= 0.0 {
+            return Rgb::default();
+        }
+
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_srgb(xyz);
+        Rgb::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0))
+    }
+
+    fn in_sun_disk(&self, direction: Vec3) -> bool {
+        direction.dot(self.sun_direction) >= SUN_ANGULAR_RADIUS.cos()
+    }
+}
+
+impl Environment for PhysicalSky {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        if direction.y < 0.0 {
+            return self.intensity * self.ground_radiance;
+        }
+
+        let mut radiance = self.sky_radiance(direction);
+        if self.in_sun_disk(direction) {
+            radiance += self.sun_radiance;
+        }
+        self.intensity * radiance
+    }
+
+    fn sample(&self, u1: f64, u2: f64) -> (Vec3, f64) {
+        let direction = if u1 < self.sun_probability {
+            // Sample the cone subtended by the sun uniformly.
+            let u1 = u1 / self.sun_probability;
+            let cos_theta = 1.0 - u1 * (1.0 - SUN_ANGULAR_RADIUS.cos());
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u2;
+            let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+            Onb::from_w(self.sun_direction).to_world(local)
+        } else {
+            let u1 = (u1 - self.sun_probability) / (1.0 - self.sun_probability);
+            let z = 1.0 - 2.0 * u1;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * PI * u2;
+            Vec3::new(r * phi.cos(), r * phi.sin(), z)
+        };
+        (direction, self.pdf(direction))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let mut pdf = (1.0 - self.sun_probability) / (4.0 * PI);
+        if self.in_sun_disk(direction) {
+            pdf += self.sun_probability / self.sun_solid_angle();
+        }
+        pdf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_is_sampled_and_bright() {
+        let sky = PhysicalSky::new(PhysicalSky::sun_direction(35.0, 120.0), 3.0, Rgb::from(0.3));
+        let sun = sky.radiance(sky.sun_direction);
+        let zenith = sky.radiance(Vec3::new(0.0, 1.0, 0.0));
+        assert!(sun.luminance() > 1.0e4 * zenith.luminance());
+        assert!(zenith.b > zenith.r, "{:?}", zenith);
+
+        let n = 1000;
+        let hits = (0..n)
+            .map(|i| sky.sample((i as f64 + 0.5) / n as f64, 0.37))
+            .filter(|&(direction, pdf)| {
+                assert!((direction.len() - 1.0).abs() < 1.0e-9);
+                assert!((pdf - sky.pdf(direction)).abs() < 1.0e-9 * pdf);
+                sky.in_sun_disk(direction)
+            })
+            .count();
+        assert!((hits as f64 / n as f64 - sky.sun_probability).abs() < 0.01);
+    }
+
+    #[test]
+    fn low_sun_is_reddened() {
+        let noon = PhysicalSky::new(PhysicalSky::sun_direction(80.0, 0.0), 3.0, Rgb::from(0.3));
+        let dusk = PhysicalSky::new(PhysicalSky::sun_direction(3.0, 0.0), 3.0, Rgb::from(0.3));
+        let ratio = |sky: &PhysicalSky| sky.sun_radiance.r / sky.sun_radiance.b;
+        assert!(ratio(&dusk) > 2.0 * ratio(&noon));
+        assert!(dusk.sun_radiance.g < noon.sun_radiance.g);
+    }
+}
@@ -0,0 +1,313 @@
+//! Spectral rendering support.
+//!
+//! In spectral mode every camera path carries [`N_WAVELENGTHS`] wavelengths sampled by
+//! [`SampledWavelengths::sample_visible`], and radiance is transported as a [`SampledSpectrum`]
+//! holding one value per wavelength. RGB material and light colors are upsampled to spectra with
+//! Smits' method, "An RGB to Spectrum Conversion for Reflectances" (1999), and the result is
+//! projected onto the CIE 1931 color matching functions and converted to linear sRGB.
+use crate::prelude::*;
+use crate::vec::raw::Scalar;
+use crate::{impl_binop, impl_unop, impl_vec_common, replace_tt};
+use std::ops::*;
+
+/// The number of wavelengths carried by each path.
+pub const N_WAVELENGTHS: usize = 4;
+
+/// The shortest wavelength considered, in nanometers.
+pub const LAMBDA_MIN: f64 = 360.0;
+
+/// The longest wavelength considered, in nanometers.
+pub const LAMBDA_MAX: f64 = 830.0;
+
+/// Generic values at each of the [`N_WAVELENGTHS`] wavelengths of a path.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Spectrum<T>(pub T, pub T, pub T, pub T);
+
+impl_vec_common!(tuple_struct@4, Spectrum(a, b, c, d), (0, 1, 2, 3));
+
+/// Spectral radiance or throughput at the wavelengths of a path.
+pub type SampledSpectrum = Spectrum<f64>;
+
+impl SampledSpectrum {
+    /// Evaluates `f` at every wavelength.
+    pub fn from_fn(wavelengths: &SampledWavelengths, f: impl Fn(f64) -> f64) -> Self {
+        wavelengths.lambda.map(f)
+    }
+
+    pub fn max(self) -> f64 {
+        self.0.max(self.1).max(self.2).max(self.3)
+    }
+
+    pub fn is_black(self) -> bool {
+        self.max() <= 0.0
+    }
+
+    /// Converts the spectrum to CIE XYZ, as a Monte Carlo estimate over `wavelengths`.
+    pub fn to_xyz(self, wavelengths: &SampledWavelengths) -> Vec3 {
+        let values: [f64; N_WAVELENGTHS] = self.into();
+        let lambda: [f64; N_WAVELENGTHS] = wavelengths.lambda.into();
+        let pdf: [f64; N_WAVELENGTHS] = wavelengths.pdf.into();
+
+        let mut xyz = Vec3::default();
+        for i in 0..N_WAVELENGTHS {
+            if pdf[i] > 0.0 {
+                xyz += cie_xyz(lambda[i]) * (values[i] / pdf[i]);
+            }
+        }
+
+        xyz / (N_WAVELENGTHS as f64 * CIE_Y_INTEGRAL)
+    }
+
+    /// Converts the spectrum to linear sRGB, as a Monte Carlo estimate over `wavelengths`.
+    pub fn to_rgb(self, wavelengths: &SampledWavelengths) -> Rgb {
+        xyz_to_srgb(self.to_xyz(wavelengths))
+    }
+}
+
+/// The wavelengths carried by a path, in nanometers, and the densities they were sampled with.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SampledWavelengths {
+    lambda: SampledSpectrum,
+    pdf: SampledSpectrum,
+}
+
+impl SampledWavelengths {
+    /// Samples wavelengths proportionally to the visual response of the human eye.
+    ///
+    /// Only the first (hero) wavelength is derived from `u` directly; the others are stratified
+    /// at equal offsets so that every path covers the whole visible range.
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; N_WAVELENGTHS];
+        let mut pdf = [0.0; N_WAVELENGTHS];
+
+        for i in 0..N_WAVELENGTHS {
+            let u = (u + i as f64 / N_WAVELENGTHS as f64).fract();
+            lambda[i] = sample_visible_wavelength(u);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+
+        SampledWavelengths {
+            lambda: lambda.into(),
+            pdf: pdf.into(),
+        }
+    }
+
+    /// Returns the hero wavelength of the path.
+    pub fn hero(&self) -> f64 {
+        self.lambda.0
+    }
+
+    pub fn lambda(&self) -> SampledSpectrum {
+        self.lambda
+    }
+
+    pub fn pdf(&self) -> SampledSpectrum {
+        self.pdf
+    }
+
+    /// Drops every wavelength but the hero wavelength.
+    ///
+    /// This must be called when the path hits a wavelength-dependent interface, such as a
+    /// dispersive dielectric, since the secondary wavelengths would refract differently.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+
+        // The hero wavelength now stands in for all strata.
+        self.pdf = Spectrum(self.pdf.0 / N_WAVELENGTHS as f64, 0.0, 0.0, 0.0);
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf.1 == 0.0 && self.pdf.2 == 0.0 && self.pdf.3 == 0.0
+    }
+}
+
+/// Inverts the CDF of [`visible_wavelength_pdf`], from Pharr, Jakob and Humphreys, "Physically
+/// Based Rendering", 4th edition.
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888_889 * (0.856_910_62 - 1.827_501_97 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    let cosh = (0.0072 * (lambda - 538.0)).cosh();
+    0.003_939_804_2 / (cosh * cosh)
+}
+
+/// A piecewise Gaussian with different widths left and right of its mean.
+fn piecewise_gaussian(x: f64, mu: f64, sigma_left: f64, sigma_right: f64) -> f64 {
+    let sigma = if x < mu { sigma_left } else { sigma_right };
+    let t = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+/// Evaluates the CIE 1931 2° color matching functions at `lambda`, using the multi-lobe fit of
+/// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ Color Matching
+/// Functions" (2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let x = 1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+        + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+        + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+        + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8);
+    Vec3::new(x, y, z)
+}
+
+/// The integral of the fitted `y` color matching function over the visible range.
+const CIE_Y_INTEGRAL: f64 = 106.922;
+
+/// Converts CIE XYZ to linear sRGB with a D65 white point.
+pub fn xyz_to_srgb(xyz: Vec3) -> Rgb {
+    let (x, y, z) = xyz.into();
+    Rgb::new(
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266_0 * x + 1.876_010_8 * y + 0.041_556_0 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    )
+}
+
+/// The relative spectral power of CIE standard illuminant D65 from 380nm to 780nm in 10nm steps.
+const D65: [f64; 41] = [
+    49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100.0, 96.3342, 95.788,
+    88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778, 78.2842,
+    69.7213, 71.6091, 74.349, 61.604, 69.8856, 75.087, 63.5927, 46.4182, 66.8054, 63.3828,
+];
+
+/// Scales [`D65`] so that it has unit luminance.
+const D65_NORMALIZATION: f64 = 98.8499;
+
+fn d65(lambda: f64) -> f64 {
+    if !(380.0..=780.0).contains(&lambda) {
+        return 0.0;
+    }
+    let t = (lambda - 380.0) / 10.0;
+    let i = (t as usize).min(D65.len() - 2);
+    let f = t - i as f64;
+    (D65[i] * (1.0 - f) + D65[i + 1] * f) / D65_NORMALIZATION
+}
+
+/// Smits' basis spectra, sampled in 10 equal bins from 380nm to 720nm.
+mod smits {
+    pub(super) const WHITE: [f64; 10] = [
+        1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+    ];
+    pub(super) const CYAN: [f64; 10] = [
+        0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+    ];
+    pub(super) const MAGENTA: [f64; 10] = [
+        1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+    ];
+    pub(super) const YELLOW: [f64; 10] = [
+        0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+    ];
+    pub(super) const RED: [f64; 10] = [
+        0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+    ];
+    pub(super) const GREEN: [f64; 10] = [
+        0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+    ];
+    pub(super) const BLUE: [f64; 10] = [
+        1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+    ];
+
+    /// Looks up a basis spectrum, clamping wavelengths outside the tabulated range.
+    pub(super) fn eval(basis: &[f64; 10], lambda: f64) -> f64 {
+        let t = ((lambda - 380.0) / (720.0 - 380.0) * 10.0).clamp(0.0, 9.0);
+        basis[t as usize]
+    }
+}
+
+/// Evaluates the reflectance spectrum of an RGB albedo at `lambda`.
+pub fn rgb_albedo(rgb: Rgb, lambda: f64) -> f64 {
+    use smits::*;
+
+    let (r, g, b) = rgb.into();
+    let basis = |spectrum: &[f64; 10]| eval(spectrum, lambda);
+
+    let value = if r <= g && r <= b {
+        r * basis(&WHITE)
+            + if g <= b {
+                (g - r) * basis(&CYAN) + (b - g) * basis(&BLUE)
+            } else {
+                (b - r) * basis(&CYAN) + (g - b) * basis(&GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&WHITE)
+            + if r <= b {
+                (r - g) * basis(&MAGENTA) + (b - r) * basis(&BLUE)
+            } else {
+                (b - g) * basis(&MAGENTA) + (r - b) * basis(&RED)
+            }
+    } else {
+        b * basis(&WHITE)
+            + if r <= g {
+                (r - b) * basis(&YELLOW) + (g - r) * basis(&GREEN)
+            } else {
+                (g - b) * basis(&YELLOW) + (r - g) * basis(&RED)
+            }
+    };
+
+    value.max(0.0)
+}
+
+/// Evaluates the emission spectrum of an RGB light color at `lambda`.
+///
+/// The reflectance spectrum of the color is modulated by illuminant D65, so that white light
+/// converts back to white.
+pub fn rgb_illuminant(rgb: Rgb, lambda: f64) -> f64 {
+    rgb_albedo(rgb, lambda) * d65(lambda)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integrate_illuminant(rgb: Rgb) -> Rgb {
+        const N: usize = 4096;
+        let mut sum = Rgb::default();
+        for i in 0..N {
+            let wavelengths = SampledWavelengths::sample_visible((i as f64 + 0.5) / N as f64);
+            let spectrum = SampledSpectrum::from_fn(&wavelengths, |l| rgb_illuminant(rgb, l));
+            sum += spectrum.to_rgb(&wavelengths);
+        }
+        sum / N as f64
+    }
+
+    #[test]
+    fn white_light_round_trips() {
+        let white = integrate_illuminant(Rgb::new(1.0, 1.0, 1.0));
+        assert!(
+            (white - Rgb::new(1.0, 1.0, 1.0)).len() < 0.02,
+            "{:?}",
+            white
+        );
+    }
+
+    #[test]
+    fn primaries_keep_their_hue() {
+        let red = integrate_illuminant(Rgb::new(1.0, 0.0, 0.0));
+        assert!(red.r > 0.8 && red.g < 0.1 && red.b < 0.1, "{:?}", red);
+
+        let blue = integrate_illuminant(Rgb::new(0.0, 0.0, 1.0));
+        assert!(blue.b > 0.8 && blue.r < 0.1 && blue.g < 0.1, "{:?}", blue);
+    }
+
+    #[test]
+    fn terminating_secondary_wavelengths_keeps_the_estimate() {
+        let mut wavelengths = SampledWavelengths::sample_visible(0.3);
+        let lambda = wavelengths.hero();
+        wavelengths.terminate_secondary();
+        assert!(wavelengths.secondary_terminated());
+        assert_eq!(wavelengths.hero(), lambda);
+        assert_eq!(
+            wavelengths.pdf().0,
+            visible_wavelength_pdf(lambda) / N_WAVELENGTHS as f64
+        );
+    }
+}
@@ -1,4 +1,5 @@
 //! 3D hittable [`Sphere`]s.
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 use crate::prelude::*;
 
@@ -20,7 +21,7 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let oc = ray.origin - self.center;
         let a = ray.direction.dot(ray.direction);
         let half_b = oc.dot(ray.direction);
@@ -33,23 +34,86 @@ impl Hittable for Sphere {
 
         let sqrtd = discriminant.sqrt();
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || root > t_max {
-            root = (-half_b + sqrtd) / a;
+        // Find the nearest root that lies in the acceptable range and is not cut out.
+        for &root in &[(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
             if root < t_min || root > t_max {
-                return None;
+                continue;
+            }
+
+            let t = root;
+            let p = ray.at(root);
+            let outward_normal = (p - self.center) / self.radius;
+            let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
+            record.set_face_normal(ray, outward_normal);
+            let (u, v) = sphere_uv(outward_normal);
+            record.u = u;
+            record.v = v;
+            let (dpdu, dpdv) = sphere_tangents(outward_normal);
+            record.dpdu = self.radius * dpdu;
+            record.dpdv = self.radius * dpdv;
+
+            if !record.is_cut_out() {
+                return Some(record);
             }
         }
 
-        let t = root;
-        let p = ray.at(root);
-        let outward_normal = (p - self.center) / self.radius;
-        let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
-        record.set_face_normal(ray, outward_normal);
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = self.radius.abs();
+        let r = Vec3::new(r, r, r);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u1: f64, u2: f64) -> Option<HitRecord<'_>> {
+        let z = 1.0 - 2.0 * u1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let normal = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+
+        let p = self.center + self.radius * normal;
+        let mut record = HitRecord::new(p, 0.0, self.material.as_ref().map(Box::as_ref));
+        record.normal = normal;
+        record.geometric_normal = record.normal;
+        record.front_face = true;
+        let (u, v) = sphere_uv(normal);
+        record.u = u;
+        record.v = v;
+        let (dpdu, dpdv) = sphere_tangents(normal);
+        record.dpdu = self.radius * dpdu;
+        record.dpdv = self.radius * dpdv;
+        Some(record)
+    }
+}
+
+/// Computes the surface coordinates of a point on the unit sphere.
+///
+/// `u` is the angle around the y axis starting from -x, and `v` is the angle from -y to +y, both
+/// normalized to [0, 1].
+pub fn sphere_uv(p: Point3) -> (f64, f64) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
 
-        return Some(record);
+/// Computes the partial derivatives of [`sphere_uv`]'s parameterization at a point on the unit
+/// sphere.
+fn sphere_tangents(p: Point3) -> (Vec3, Vec3) {
+    let sin_theta = (p.x * p.x + p.z * p.z).sqrt();
+    if sin_theta < 1.0e-9 {
+        // The parameterization is singular at the poles; pick any tangent frame.
+        let frame = crate::onb::Onb::from_w(p);
+        return (frame.u, frame.v);
     }
+
+    let dpdu = 2.0 * PI * Vec3::new(p.z, 0.0, -p.x);
+    let dpdv = PI * Vec3::new(-p.x * p.y / sin_theta, sin_theta, -p.y * p.z / sin_theta);
+    (dpdu, dpdv)
 }
 
 /// Checks if a ray hit a sphere.
@@ -60,8 +124,68 @@ pub fn hit_sphere(center: Point3, radius: f64, ray: &Ray) -> f64 {
     let c = oc.len_squared() - radius * radius;
     let discriminant = half_b * half_b - a * c;
     if discriminant < 0.0 {
-        return -1.0;
+        -1.0
     } else {
-        return (-half_b - discriminant.sqrt()) / a;
+        (-half_b - discriminant.sqrt()) / a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Masked};
+    use crate::texture::Texture;
+
+    /// An opacity mask cutting out the upper half of space.
+    #[derive(Debug)]
+    struct LowerHalf;
+
+    impl Texture for LowerHalf {
+        fn value(&self, _u: f64, _v: f64, p: Point3) -> Rgb {
+            Rgb::from(if p.z > 0.0 { 0.0 } else { 1.0 })
+        }
+    }
+
+    fn masked(opacity: impl Texture + 'static) -> Sphere {
+        let material = Masked::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5)), opacity);
+        Sphere::new(Point3::default(), 1.0, material)
+    }
+
+    #[test]
+    fn rays_pass_through_cut_out_parts() {
+        let down = Ray::new(Point3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0));
+
+        let opaque = masked(1.0);
+        let rec = opaque.hit(&down, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-12);
+        assert!(masked(0.0).hit(&down, 0.0, INIFINTY).is_none());
+
+        // Without its upper half, the inside of the lower half is seen.
+        let lower_half = masked(LowerHalf);
+        let rec = lower_half.hit(&down, 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1.0e-12);
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).len() < 1.0e-12);
+    }
+
+    #[test]
+    fn partial_opacity_keeps_a_fraction_of_the_surface() {
+        let sphere = masked(0.3);
+        let n = 10_000;
+        let mut hits = 0;
+        for i in 0..n {
+            let x = 0.5 * (i as f64 + 0.5) / n as f64;
+            let down = Ray::new(Point3::new(x, 0.0, 2.0), Vec3::new(0.0, 0.0, -1.0));
+            let t = sphere.hit(&down, 0.0, INIFINTY).map(|rec| rec.t);
+            if t.is_some() {
+                hits += 1;
+            }
+            // Tracing the same ray again gives the same result.
+            assert_eq!(sphere.hit(&down, 0.0, INIFINTY).map(|rec| rec.t), t);
+        }
+
+        // Each of both crossings is kept with the opacity.
+        let fraction = hits as f64 / n as f64;
+        assert!((fraction - (1.0 - 0.7 * 0.7)).abs() < 0.02, "{}", fraction);
     }
 }
@@ -0,0 +1,408 @@
+//! Subdivision surfaces, refining a polygon [`Mesh`] towards a smooth limit surface.
+//!
+//! Creased edges follow the semi-sharp crease rules of DeRose et al., "Subdivision Surfaces in
+//! Character Animation" (1998): they are subdivided like curves while their sharpness lasts,
+//! losing one unit of sharpness per level. Boundary edges are always sharp.
+use crate::mesh::{edge, Mesh};
+use crate::prelude::*;
+use std::collections::HashMap;
+
+/// A subdivision scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// Catmull-Clark subdivision, which splits every face into quads and suits quad-dominant
+    /// meshes.
+    CatmullClark,
+    /// Loop subdivision, which splits every triangle into four and suits triangle meshes.
+    /// Other polygons are split into triangles first.
+    Loop,
+    /// Splits every triangle into four like [`Scheme::Loop`] without moving any vertex, which
+    /// tessellates the mesh without changing its shape, e.g. for displacement.
+    Linear,
+}
+
+/// The adjacency of the edges and vertices of a mesh.
+struct Topology {
+    /// The edges by their vertices, in the order they appear in the faces.
+    edges: Vec<(usize, usize)>,
+    edge_indices: HashMap<(usize, usize), usize>,
+    /// The faces on each side of each edge.
+    edge_faces: Vec<Vec<usize>>,
+    /// The edges and faces around each vertex.
+    vertex_edges: Vec<Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    fn new(mesh: &Mesh) -> Self {
+        let mut topology = Topology {
+            edges: Vec::new(),
+            edge_indices: HashMap::new(),
+            edge_faces: Vec::new(),
+            vertex_edges: vec![Vec::new(); mesh.positions.len()],
+            vertex_faces: vec![Vec::new(); mesh.positions.len()],
+        };
+        for (f, face) in mesh.faces.iter().enumerate() {
+            for (i, &a) in face.iter().enumerate() {
+                let b = face[(i + 1) % face.len()];
+                let key = edge(a, b);
+                let e = match topology.edge_indices.get(&key) {
+                    Some(&e) => e,
+                    None => {
+                        let e = topology.edges.len();
+                        topology.edge_indices.insert(key, e);
+                        topology.edges.push(key);
+                        topology.edge_faces.push(Vec::new());
+                        topology.vertex_edges[a].push(e);
+                        topology.vertex_edges[b].push(e);
+                        e
+                    }
+                };
+                topology.edge_faces[e].push(f);
+                topology.vertex_faces[a].push(f);
+            }
+        }
+        topology
+    }
+
+    /// The sharpness of an edge, infinite on the boundary.
+    fn sharpness(&self, mesh: &Mesh, e: usize) -> f64 {
+        let (a, b) = self.edges[e];
+        if self.edge_faces[e].len() != 2 {
+            INIFINTY
+        } else {
+            mesh.crease(a, b)
+        }
+    }
+
+    /// The vertex at the other end of edge `e` from `v`.
+    fn other(&self, e: usize, v: usize) -> usize {
+        let (a, b) = self.edges[e];
+        if a == v {
+            b
+        } else {
+            a
+        }
+    }
+
+    fn edge(&self, a: usize, b: usize) -> usize {
+        self.edge_indices[&edge(a, b)]
+    }
+}
+
+/// Subdivides `mesh` once with `scheme`.
+pub(crate) fn subdivide(mesh: &Mesh, scheme: Scheme) -> Mesh {
+    match scheme {
+        Scheme::CatmullClark => catmull_clark(mesh),
+        Scheme::Loop => loop_subdivision(&triangulate(mesh), true),
+        Scheme::Linear => loop_subdivision(&triangulate(mesh), false),
+    }
+}
+
+/// Moves the smooth position of an edge or vertex towards the sharp one, all the way once the
+/// `sharpness` reaches one.
+fn crease(smooth: Point3, sharp: Point3, sharpness: f64) -> Point3 {
+    let s = sharpness.clamp(0.0, 1.0);
+    smooth + s * (sharp - smooth)
+}
+
+/// Computes the new position of vertex `v` given its `smooth` one, which is only kept if
+/// fewer than two sharp edges meet there.
+fn vertex_point(mesh: &Mesh, topology: &Topology, v: usize, smooth: Point3) -> Point3 {
+    let p = mesh.positions[v];
+    let sharp: Vec<(usize, f64)> = topology.vertex_edges[v]
+        .iter()
+        .map(|&e| (e, topology.sharpness(mesh, e)))
+        .filter(|&(_, s)| s > 0.0)
+        .collect();
+    let position = match sharp.len() {
+        0 | 1 => return smooth,
+        // The vertex slides along the crease like on a cubic B-spline curve.
+        2 => {
+            let a = mesh.positions[topology.other(sharp[0].0, v)];
+            let b = mesh.positions[topology.other(sharp[1].0, v)];
+            (6.0 * p + a + b) / 8.0
+        }
+        // Corners stay in place.
+        _ => p,
+    };
+    let sharpness = sharp.iter().map(|&(_, s)| s).sum::<f64>() / sharp.len() as f64;
+    crease(smooth, position, sharpness)
+}
+
+/// Carries the creases of `mesh` over to the two halves of each edge, split at the new vertex
+/// given by `midpoint`, with `decay` units of sharpness less.
+fn split_creases(
+    mesh: &Mesh,
+    midpoint: impl Fn(usize, usize) -> usize,
+    decay: f64,
+) -> HashMap<(usize, usize), f64> {
+    let mut creases = HashMap::new();
+    for (&(a, b), &sharpness) in &mesh.creases {
+        let sharpness = sharpness - decay;
+        if sharpness > 0.0 {
+            let m = midpoint(a, b);
+            creases.insert(edge(a, m), sharpness);
+            creases.insert(edge(m, b), sharpness);
+        }
+    }
+    creases
+}
+
+/// Interpolates the surface coordinates of the new vertices linearly from the old ones, in
+/// the same order as the positions.
+fn split_uvs(mesh: &Mesh, topology: &Topology, faces: bool) -> Option<Vec<(f64, f64)>> {
+    let uvs = mesh.uvs.as_ref()?;
+    let mut new_uvs = uvs.clone();
+    if faces {
+        for face in &mesh.faces {
+            let n = face.len() as f64;
+            let u = face.iter().map(|&v| uvs[v].0).sum::<f64>() / n;
+            let v = face.iter().map(|&v| uvs[v].1).sum::<f64>() / n;
+            new_uvs.push((u, v));
+        }
+    }
+    for &(a, b) in &topology.edges {
+        new_uvs.push((0.5 * (uvs[a].0 + uvs[b].0), 0.5 * (uvs[a].1 + uvs[b].1)));
+    }
+    Some(new_uvs)
+}
+
+/// One level of Catmull-Clark subdivision.
+///
+/// The new vertices are the moved old ones, followed by one per face and one per edge.
+fn catmull_clark(mesh: &Mesh) -> Mesh {
+    let topology = Topology::new(mesh);
+    let positions = &mesh.positions;
+    let face_points: Vec<Point3> = mesh
+        .faces
+        .iter()
+        .map(|face| {
+            face.iter()
+                .map(|&v| positions[v])
+                .fold(Point3::default(), |sum, p| sum + p)
+                / face.len() as f64
+        })
+        .collect();
+
+    let edge_points = topology.edges.iter().enumerate().map(|(e, &(a, b))| {
+        let midpoint = 0.5 * (positions[a] + positions[b]);
+        match topology.edge_faces[e][..] {
+            [f, g] => {
+                let smooth = 0.25 * (positions[a] + positions[b] + face_points[f] + face_points[g]);
+                crease(smooth, midpoint, topology.sharpness(mesh, e))
+            }
+            _ => midpoint,
+        }
+    });
+
+    let vertex_points = (0..positions.len()).map(|v| {
+        let (edges, faces) = (&topology.vertex_edges[v], &topology.vertex_faces[v]);
+        if edges.is_empty() {
+            return positions[v];
+        }
+        // (Q + 2R + (n - 3) S) / n, with Q the average of the face points around the vertex
+        // and R that of the midpoints of its edges.
+        let n = edges.len() as f64;
+        let q = faces
+            .iter()
+            .map(|&f| face_points[f])
+            .fold(Point3::default(), |sum, p| sum + p)
+            / faces.len() as f64;
+        let r = edges
+            .iter()
+            .map(|&e| 0.5 * (positions[v] + positions[topology.other(e, v)]))
+            .fold(Point3::default(), |sum, p| sum + p)
+            / n;
+        let smooth = (q + 2.0 * r + (n - 3.0) * positions[v]) / n;
+        vertex_point(mesh, &topology, v, smooth)
+    });
+
+    let face_base = positions.len();
+    let edge_base = face_base + mesh.faces.len();
+    let edge_vertex = |a: usize, b: usize| edge_base + topology.edge(a, b);
+    let mut faces = Vec::with_capacity(4 * mesh.faces.len());
+    for (f, face) in mesh.faces.iter().enumerate() {
+        let k = face.len();
+        for i in 0..k {
+            let (previous, v, next) = (face[(i + k - 1) % k], face[i], face[(i + 1) % k]);
+            faces.push(vec![
+                v,
+                edge_vertex(v, next),
+                face_base + f,
+                edge_vertex(previous, v),
+            ]);
+        }
+    }
+
+    let mut new_positions: Vec<Point3> = vertex_points.collect();
+    new_positions.extend(face_points.iter().copied());
+    new_positions.extend(edge_points);
+    Mesh {
+        positions: new_positions,
+        uvs: split_uvs(mesh, &topology, true),
+        faces,
+        creases: split_creases(mesh, edge_vertex, 1.0),
+    }
+}
+
+/// Splits the faces of `mesh` that are not triangles into fans of triangles.
+fn triangulate(mesh: &Mesh) -> Mesh {
+    let mut faces = Vec::new();
+    for face in &mesh.faces {
+        for w in face[1..].windows(2) {
+            faces.push(vec![face[0], w[0], w[1]]);
+        }
+    }
+    Mesh {
+        faces,
+        ..mesh.clone()
+    }
+}
+
+/// One level of Loop subdivision of a triangle mesh, or of linear subdivision unless `smooth`,
+/// which keeps the vertices and the sharpness of the creases as they are.
+///
+/// The new vertices are the moved old ones, followed by one per edge.
+fn loop_subdivision(mesh: &Mesh, smooth: bool) -> Mesh {
+    let topology = Topology::new(mesh);
+    let positions = &mesh.positions;
+    // The vertex of triangle `f` opposite to the edge from `a` to `b`.
+    let opposite = |f: usize, a: usize, b: usize| {
+        let face = &mesh.faces[f];
+        positions[face.iter().copied().find(|&v| v != a && v != b).unwrap()]
+    };
+
+    let edge_points = topology.edges.iter().enumerate().map(|(e, &(a, b))| {
+        let midpoint = 0.5 * (positions[a] + positions[b]);
+        match topology.edge_faces[e][..] {
+            [f, g] if smooth => {
+                let far = opposite(f, a, b) + opposite(g, a, b);
+                let smooth = 0.375 * (positions[a] + positions[b]) + 0.125 * far;
+                crease(smooth, midpoint, topology.sharpness(mesh, e))
+            }
+            _ => midpoint,
+        }
+    });
+
+    let vertex_points = (0..positions.len()).map(|v| {
+        let edges = &topology.vertex_edges[v];
+        if edges.is_empty() || !smooth {
+            return positions[v];
+        }
+        // Loop's original weights for the neighbours of a vertex of valence n.
+        let n = edges.len() as f64;
+        let beta = (0.625 - (0.375 + 0.25 * (2.0 * PI / n).cos()).powi(2)) / n;
+        let neighbours = edges
+            .iter()
+            .map(|&e| positions[topology.other(e, v)])
+            .fold(Point3::default(), |sum, p| sum + p);
+        let smooth = (1.0 - n * beta) * positions[v] + beta * neighbours;
+        vertex_point(mesh, &topology, v, smooth)
+    });
+
+    let edge_base = positions.len();
+    let edge_vertex = |a: usize, b: usize| edge_base + topology.edge(a, b);
+    let mut faces = Vec::with_capacity(4 * mesh.faces.len());
+    for face in &mesh.faces {
+        let (a, b, c) = (face[0], face[1], face[2]);
+        let (ab, bc, ca) = (edge_vertex(a, b), edge_vertex(b, c), edge_vertex(c, a));
+        faces.push(vec![a, ab, ca]);
+        faces.push(vec![b, bc, ab]);
+        faces.push(vec![c, ca, bc]);
+        faces.push(vec![ab, bc, ca]);
+    }
+
+    let mut new_positions: Vec<Point3> = vertex_points.collect();
+    new_positions.extend(edge_points);
+    Mesh {
+        positions: new_positions,
+        uvs: split_uvs(mesh, &topology, false),
+        faces,
+        creases: split_creases(mesh, edge_vertex, if smooth { 1.0 } else { 0.0 }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cube from -1 to 1 along every axis.
+    fn cube() -> Mesh {
+        let positions = (0..8)
+            .map(|i| {
+                let coordinate = |bit: usize| if i & bit != 0 { 1.0 } else { -1.0 };
+                Point3::new(coordinate(1), coordinate(2), coordinate(4))
+            })
+            .collect();
+        let faces = vec![
+            vec![0, 2, 3, 1],
+            vec![4, 5, 7, 6],
+            vec![0, 1, 5, 4],
+            vec![2, 6, 7, 3],
+            vec![0, 4, 6, 2],
+            vec![1, 3, 7, 5],
+        ];
+        Mesh::new(positions, faces)
+    }
+
+    fn max_norm(p: Point3) -> f64 {
+        p.x.abs().max(p.y.abs()).max(p.z.abs())
+    }
+
+    #[test]
+    fn catmull_clark_rounds_off_a_cube() {
+        let mesh = cube().subdivide(Scheme::CatmullClark, 1);
+        assert_eq!(mesh.positions.len(), 26);
+        assert_eq!(mesh.faces.len(), 24);
+        // The corners move to 5/9 of the way out, after Catmull and Clark.
+        let corner = mesh.positions[7];
+        assert!(
+            (corner - Point3::new(5.0, 5.0, 5.0) / 9.0).len() < 1.0e-12,
+            "{:?}",
+            corner
+        );
+
+        let mesh = mesh.subdivide(Scheme::CatmullClark, 2);
+        assert_eq!(mesh.faces.len(), 24 * 16);
+        assert!(mesh.positions.iter().all(|&p| max_norm(p) < 1.0));
+    }
+
+    #[test]
+    fn loop_subdivides_triangles_into_four() {
+        let mesh = cube().subdivide(Scheme::Loop, 2);
+        assert_eq!(mesh.faces.len(), 12 * 16);
+        assert!(mesh.faces.iter().all(|face| face.len() == 3));
+        assert!(mesh.positions.iter().all(|&p| max_norm(p) < 1.0));
+    }
+
+    #[test]
+    fn sharp_creases_keep_their_shape() {
+        let mut mesh = cube();
+        for face in mesh.faces.clone() {
+            for i in 0..4 {
+                mesh.set_crease(face[i], face[(i + 1) % 4], INIFINTY);
+            }
+        }
+        for &scheme in &[Scheme::CatmullClark, Scheme::Loop] {
+            let subdivided = mesh.subdivide(scheme, 2);
+            for &p in &subdivided.positions {
+                assert!((max_norm(p) - 1.0).abs() < 1.0e-12, "{:?} {:?}", scheme, p);
+            }
+        }
+
+        // A sharpness of one only lasts for the first level.
+        let mut mesh = cube();
+        mesh.set_crease(0, 1, 1.0);
+        let once = mesh.subdivide(Scheme::CatmullClark, 1);
+        assert!(once.creases.is_empty());
+        let midpoint = Point3::new(0.0, -1.0, -1.0);
+        let on_edge = |mesh: &Mesh| {
+            mesh.positions
+                .iter()
+                .any(|&p| (p - midpoint).len() < 1.0e-12)
+        };
+        assert!(on_edge(&once));
+        assert!(!on_edge(&cube().subdivide(Scheme::CatmullClark, 1)));
+    }
+}
@@ -0,0 +1,161 @@
+//! [`Texture`]s that vary material parameters over a surface.
+use crate::image::Image;
+use crate::prelude::*;
+use std::fmt::Debug;
+
+/// A spatially varying color, looked up by surface coordinates `(u, v)` and hit point `p`.
+///
+/// Plain [`Rgb`] colors and `f64` values are constant textures.
+pub trait Texture: Debug + Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Rgb;
+
+    /// Looks up the texture as a scalar, e.g. for roughness or metallic maps.
+    fn scalar(&self, u: f64, v: f64, p: Point3) -> f64 {
+        self.value(u, v, p).luminance()
+    }
+}
+
+impl Texture for Rgb {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Rgb {
+        *self
+    }
+}
+
+impl Texture for f64 {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Rgb {
+        Rgb::from(*self)
+    }
+
+    fn scalar(&self, _u: f64, _v: f64, _p: Point3) -> f64 {
+        *self
+    }
+}
+
+/// A 3D checkerboard alternating between two textures.
+#[derive(Debug)]
+pub struct CheckerTexture {
+    odd: Box<dyn Texture>,
+    even: Box<dyn Texture>,
+    /// The number of checks per unit length.
+    frequency: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(odd: impl Texture + 'static, even: impl Texture + 'static, frequency: f64) -> Self {
+        CheckerTexture {
+            odd: Box::new(odd),
+            even: Box::new(even),
+            frequency,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Rgb {
+        let sines = (PI * self.frequency * p.x).sin()
+            * (PI * self.frequency * p.y).sin()
+            * (PI * self.frequency * p.z).sin();
+        if sines < 0.0 {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+}
+
+/// A texture that looks up an [`Image`] by surface coordinates, with bilinear filtering.
+///
+/// `(0, 0)` maps to the bottom-left corner of the image, and coordinates wrap around.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        ImageTexture { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Rgb {
+        let (width, height) = (self.image.width(), self.image.height());
+        if width == 0 || height == 0 {
+            return Rgb::default();
+        }
+
+        // Image rows are stored from the top.
+        let x = u.rem_euclid(1.0) * width as f64 - 0.5;
+        let y = (1.0 - v.rem_euclid(1.0)) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |i: f64, j: f64| {
+            let i = (i as i64).rem_euclid(width as i64) as usize;
+            let j = (j as i64).rem_euclid(height as i64) as usize;
+            self.image.get(i, j)
+        };
+
+        (1.0 - fx) * (1.0 - fy) * texel(x0, y0)
+            + fx * (1.0 - fy) * texel(x0 + 1.0, y0)
+            + (1.0 - fx) * fy * texel(x0, y0 + 1.0)
+            + fx * fy * texel(x0 + 1.0, y0 + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2×2 image with a distinct color per texel, red at the top left.
+    fn texture() -> ImageTexture {
+        let mut image = Image::new(2, 2);
+        image.set(0, 0, Rgb::new(1.0, 0.0, 0.0));
+        image.set(1, 0, Rgb::new(0.0, 1.0, 0.0));
+        image.set(0, 1, Rgb::new(0.0, 0.0, 1.0));
+        image.set(1, 1, Rgb::new(1.0, 1.0, 1.0));
+        ImageTexture::new(image)
+    }
+
+    fn assert_close(a: Rgb, b: Rgb) {
+        let close = (a.r - b.r).abs() < 1.0e-12
+            && (a.g - b.g).abs() < 1.0e-12
+            && (a.b - b.b).abs() < 1.0e-12;
+        assert!(close, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn texel_centers_look_up_their_texel() {
+        let texture = texture();
+        let p = Point3::default();
+        assert_close(texture.value(0.25, 0.75, p), Rgb::new(1.0, 0.0, 0.0));
+        assert_close(texture.value(0.75, 0.75, p), Rgb::new(0.0, 1.0, 0.0));
+        assert_close(texture.value(0.25, 0.25, p), Rgb::new(0.0, 0.0, 1.0));
+        assert_close(texture.value(0.75, 0.25, p), Rgb::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn lookups_are_bilinear() {
+        let texture = texture();
+        let p = Point3::default();
+        assert_close(texture.value(0.5, 0.75, p), Rgb::new(0.5, 0.5, 0.0));
+        assert_close(texture.value(0.5, 0.5, p), Rgb::new(0.5, 0.5, 0.5));
+        assert_close(texture.value(0.375, 0.25, p), Rgb::new(0.25, 0.25, 1.0));
+    }
+
+    #[test]
+    fn coordinates_wrap_around() {
+        let texture = texture();
+        let p = Point3::default();
+        for &(u, v) in &[(0.3, 0.6), (0.1, 0.9), (0.8, 0.2)] {
+            let value = texture.value(u, v, p);
+            assert_close(texture.value(u + 1.0, v, p), value);
+            assert_close(texture.value(u - 2.0, v - 1.0, p), value);
+            assert_close(texture.value(u, v + 3.0, p), value);
+        }
+
+        // Filtering across the edges blends with the texels on the opposite side.
+        assert_close(texture.value(0.0, 0.75, p), Rgb::new(0.5, 0.5, 0.0));
+        assert_close(texture.value(0.25, 1.0, p), Rgb::new(0.5, 0.0, 0.5));
+    }
+}
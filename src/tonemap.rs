@@ -0,0 +1,180 @@
+//! Tone mapping and display transforms.
+//!
+//! Rendered radiance is scene-referred and unbounded. Before it can be written to an 8-bit image
+//! it is scaled by an exposure, compressed into [0, 1] by a [`ToneMapOperator`], and encoded with
+//! a [`TransferFunction`].
+use crate::prelude::*;
+
+/// Operators that compress scene-referred values into the displayable range [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    /// Hard clamps every channel into [0, 1].
+    Clamp,
+    /// Extended Reinhard applied to luminance; luminance values of `white` and above map to 1.
+    ///
+    /// Use [`f64::INFINITY`] for the classic `L / (1 + L)` curve.
+    Reinhard { white: f64 },
+    /// Krzysztof Narkowicz's fit of the ACES filmic reference rendering transform.
+    AcesFilmic,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+impl ToneMapOperator {
+    /// Applies the operator to a linear color.
+    pub fn apply(self, color: Rgb) -> Rgb {
+        match self {
+            ToneMapOperator::Clamp => color,
+            ToneMapOperator::Reinhard { white } => {
+                let l = color.luminance();
+                if l <= 0.0 {
+                    return Rgb::default();
+                }
+                let mapped = l * (1.0 + l / (white * white)) / (1.0 + l);
+                color * (mapped / l)
+            }
+            ToneMapOperator::AcesFilmic => color.map(|x| {
+                // The fit expects values pre-exposed by 0.6 to match the reference curve.
+                let x = x * 0.6;
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }),
+            ToneMapOperator::Hable => {
+                const EXPOSURE_BIAS: f64 = 2.0;
+                const WHITE: f64 = 11.2;
+                let white_scale = 1.0 / hable(WHITE);
+                color.map(|x| hable(x * EXPOSURE_BIAS) * white_scale)
+            }
+        }
+        .map(|x| x.clamp(0.0, 1.0))
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// Encodings from linear [0, 1] values to display values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransferFunction {
+    /// Writes linear values unchanged.
+    Linear,
+    /// A pure power curve with the given gamma, e.g. `2.0` for the legacy `sqrt` encoding.
+    Gamma(f64),
+    /// The piecewise sRGB transfer function (IEC 61966-2-1).
+    Srgb,
+}
+
+impl TransferFunction {
+    /// Encodes a linear value in [0, 1].
+    pub fn encode(self, x: f64) -> f64 {
+        match self {
+            TransferFunction::Linear => x,
+            TransferFunction::Gamma(gamma) => x.powf(1.0 / gamma),
+            TransferFunction::Srgb => {
+                if x <= 0.003_130_8 {
+                    12.92 * x
+                } else {
+                    1.055 * x.powf(1.0 / 2.4) - 0.055
+                }
+            }
+        }
+    }
+}
+
+/// The post-process stage applied to rendered radiance before it is quantized for display.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    /// Exposure adjustment in stops; every pixel is scaled by `2^exposure`.
+    pub exposure: f64,
+    pub operator: ToneMapOperator,
+    pub transfer: TransferFunction,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            exposure: 0.0,
+            operator: ToneMapOperator::Clamp,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+}
+
+impl ToneMapping {
+    /// Maps a scene-referred color to an encoded display color within [0, 1].
+    pub fn apply(&self, color: Rgb) -> Rgb {
+        let exposed = color * self.exposure.exp2();
+        let transfer = self.transfer;
+        self.operator
+            .apply(exposed)
+            .map(|x| transfer.encode(x).clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_transfer_is_continuous_and_bounded() {
+        let srgb = TransferFunction::Srgb;
+        assert_eq!(srgb.encode(0.0), 0.0);
+        assert!((srgb.encode(1.0) - 1.0).abs() < 1e-12);
+
+        let knee = 0.003_130_8;
+        assert!((srgb.encode(knee) - srgb.encode(knee + 1e-12)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn operators_map_into_unit_range() {
+        let operators = [
+            ToneMapOperator::Clamp,
+            ToneMapOperator::Reinhard {
+                white: f64::INFINITY,
+            },
+            ToneMapOperator::Reinhard { white: 4.0 },
+            ToneMapOperator::AcesFilmic,
+            ToneMapOperator::Hable,
+        ];
+
+        for &operator in &operators {
+            let mut previous = 0.0;
+            for &x in &[0.0, 0.01, 0.18, 1.0, 4.0, 100.0, 1.0e6] {
+                let mapped = operator.apply(Rgb::new(x, x, x)).r;
+                assert!(
+                    (0.0..=1.0).contains(&mapped),
+                    "{:?}({}) = {}",
+                    operator,
+                    x,
+                    mapped
+                );
+                assert!(
+                    mapped >= previous,
+                    "{:?} is not monotonic at {}",
+                    operator,
+                    x
+                );
+                previous = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_scales_in_stops() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.0,
+            transfer: TransferFunction::Linear,
+            ..Default::default()
+        };
+        assert_eq!(
+            tone_mapping.apply(Rgb::new(0.25, 0.1, 0.0)),
+            Rgb::new(0.5, 0.2, 0.0)
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! 3D hittable [`Torus`]es.
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::onb::Onb;
+use crate::prelude::*;
+
+/// A torus around `center`, whose tube of `minor_radius` circles the `axis` at
+/// `major_radius`, e.g. a ring or a donut.
+///
+/// `u` is the angle around the axis and `v` the angle around the tube, starting from its outer
+/// equator, both normalized to [0, 1]. The major radius must not be smaller than the minor
+/// radius.
+#[derive(Debug)]
+pub struct Torus {
+    pub center: Point3,
+    /// The unit axis of the torus.
+    pub axis: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Option<Box<dyn Material>>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Point3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material: impl Material + 'static,
+    ) -> Self {
+        Torus {
+            center,
+            axis: axis.normal(),
+            major_radius,
+            minor_radius,
+            material: Some(Box::new(material)),
+        }
+    }
+
+    /// Builds the record of the point at the angles `phi` around the axis and `theta` around
+    /// the tube, with an outward normal.
+    fn record(&self, frame: &Onb, phi: f64, theta: f64, t: f64) -> HitRecord<'_> {
+        let (r_major, r_minor) = (self.major_radius, self.minor_radius);
+        let (cos_phi, sin_phi) = (phi.cos(), phi.sin());
+        let (cos_theta, sin_theta) = (theta.cos(), theta.sin());
+        let ring = r_major + r_minor * cos_theta;
+
+        let normal = Vec3::new(cos_theta * cos_phi, cos_theta * sin_phi, sin_theta);
+        let local = Vec3::new(ring * cos_phi, ring * sin_phi, r_minor * sin_theta);
+        let p = self.center + frame.to_world(local);
+        let mut record = HitRecord::new(p, t, self.material.as_ref().map(Box::as_ref));
+        record.normal = frame.to_world(normal);
+        record.geometric_normal = record.normal;
+        record.front_face = true;
+        record.u = phi / (2.0 * PI);
+        record.v = theta / (2.0 * PI);
+        record.dpdu = 2.0 * PI * frame.to_world(Vec3::new(-ring * sin_phi, ring * cos_phi, 0.0));
+        let tube = Vec3::new(-sin_theta * cos_phi, -sin_theta * sin_phi, cos_theta);
+        record.dpdv = 2.0 * PI * r_minor * frame.to_world(tube);
+        record
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (r_major, r_minor) = (self.major_radius, self.minor_radius);
+        let frame = Onb::from_w(self.axis);
+        let length = ray.direction.len();
+        let d = frame.to_local(ray.direction) / length;
+        let o = frame.to_local(ray.origin - self.center);
+
+        // Restrict the search to a sphere around the torus, and measure distances from where the
+        // ray enters it, which keeps the coefficients of the quartic well conditioned. The
+        // sphere is padded so that the outer equator is not right where the search starts.
+        let half_b = o.dot(d);
+        let bound = r_major + 2.0 * r_minor;
+        let discriminant = half_b * half_b - (o.len_squared() - bound * bound);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+        let lo = (-half_b - sqrtd).max(t_min * length);
+        let hi = (-half_b + sqrtd).min(t_max * length);
+        if lo > hi {
+            return None;
+        }
+        let o = o + lo * d;
+
+        // Expand (|p|² + R² - r²)² = 4R² (x² + y²) along the ray.
+        let b = 2.0 * o.dot(d);
+        let c = o.len_squared() + r_major * r_major - r_minor * r_minor;
+        let r2 = 4.0 * r_major * r_major;
+        let coefficients = [
+            c * c - r2 * (o.x * o.x + o.y * o.y),
+            2.0 * b * c - 2.0 * r2 * (o.x * d.x + o.y * d.y),
+            b * b + 2.0 * c - r2 * (d.x * d.x + d.y * d.y),
+            2.0 * b,
+            1.0,
+        ];
+
+        // Find the nearest hit that is not cut out.
+        for s in real_roots(&coefficients, 0.0, hi - lo) {
+            let local = o + s * d;
+            let phi = local.y.atan2(local.x).rem_euclid(2.0 * PI);
+            let ring = (local.x * local.x + local.y * local.y).sqrt();
+            let theta = local.z.atan2(ring - r_major).rem_euclid(2.0 * PI);
+
+            let t = (lo + s) / length;
+            let mut record = self.record(&frame, phi, theta, t);
+            record.p = ray.at(t);
+            record.set_face_normal(ray, record.normal);
+            if !record.is_cut_out() {
+                return Some(record);
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let ring = Aabb::disk(self.center, self.axis, self.major_radius);
+        let r = Vec3::new(self.minor_radius, self.minor_radius, self.minor_radius);
+        Some(Aabb::new(ring.min - r, ring.max + r))
+    }
+
+    fn area(&self) -> f64 {
+        4.0 * PI * PI * self.major_radius * self.minor_radius
+    }
+
+    fn sample_surface(&self, u1: f64, u2: f64) -> Option<HitRecord<'_>> {
+        // The area element grows with R + r cos θ, whose distribution over θ is inverted by
+        // bisection.
+        let (r_major, r_minor) = (self.major_radius, self.minor_radius);
+        let cdf = |theta: f64| (r_major * theta + r_minor * theta.sin()) / (2.0 * PI * r_major);
+        let (mut lo, mut hi) = (0.0, 2.0 * PI);
+        for _ in 0..48 {
+            let mid = 0.5 * (lo + hi);
+            if cdf(mid) < u1 {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let theta = 0.5 * (lo + hi);
+        let frame = Onb::from_w(self.axis);
+        Some(self.record(&frame, 2.0 * PI * u2, theta, 0.0))
+    }
+}
+
+/// Finds the real roots of the polynomial with `coefficients`, from the constant term up,
+/// between `lo` and `hi`, in increasing order.
+///
+/// The polynomial is monotonic between consecutive roots of its derivative, found recursively,
+/// so each of these intervals holds at most one root, refined by bisection. Roots of even
+/// multiplicity, where the polynomial only touches zero, are missed.
+fn real_roots(coefficients: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let evaluate = |x: f64| coefficients.iter().rev().fold(0.0, |acc, &c| acc * x + c);
+    match coefficients {
+        [] | [_] => return Vec::new(),
+        &[c0, c1] => {
+            let root = -c0 / c1;
+            return if c1 != 0.0 && (lo..=hi).contains(&root) {
+                vec![root]
+            } else {
+                Vec::new()
+            };
+        }
+        _ => {}
+    }
+
+    let derivative: Vec<f64> = coefficients[1..]
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| (i + 1) as f64 * c)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(real_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = Vec::new();
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let (fa, fb) = (evaluate(a), evaluate(b));
+        if (fa <= 0.0) == (fb <= 0.0) {
+            continue;
+        }
+        let rising = fa <= 0.0;
+        for _ in 0..64 {
+            let mid = 0.5 * (a + b);
+            if mid <= a || mid >= b {
+                break;
+            }
+            if (evaluate(mid) <= 0.0) == rising {
+                a = mid;
+            } else {
+                b = mid;
+            }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn real_roots_of_a_quartic() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = real_roots(&[24.0, -50.0, 35.0, -10.0, 1.0], 0.0, 10.0);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip(&[1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1.0e-9, "{:?}", roots);
+        }
+        assert_eq!(
+            real_roots(&[24.0, -50.0, 35.0, -10.0, 1.0], 1.5, 2.5).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn rays_cross_the_tube_twice_on_each_side() {
+        let material = Lambertian::new(Rgb::new(0.5, 0.5, 0.5));
+        let torus = Torus::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            2.0,
+            0.5,
+            material,
+        );
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0));
+
+        let mut t = 0.0;
+        for &expected in &[2.5, 3.5, 6.5, 7.5] {
+            let record = torus.hit(&ray, t, INIFINTY).unwrap();
+            assert!(
+                (record.p.x - (expected - 5.0)).abs() < 1.0e-9,
+                "{:?}",
+                record.p
+            );
+            t = record.t + 0.001;
+        }
+        assert!(torus.hit(&ray, t, INIFINTY).is_none());
+
+        // Down the axis, through the hole.
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(torus.hit(&ray, 0.0, INIFINTY).is_none());
+    }
+}
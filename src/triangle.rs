@@ -0,0 +1,307 @@
+//! 3D hittable [`Triangle`]s.
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::prelude::*;
+
+/// A triangle with optional per-vertex shading normals and texture coordinates.
+#[derive(Debug)]
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    /// Per-vertex shading normals, interpolated across the triangle for smooth shading.
+    pub normals: Option<[Vec3; 3]>,
+    /// Per-vertex surface coordinates, `(0, 0)`, `(1, 0)` and `(1, 1)` by default.
+    pub uvs: [(f64, f64); 3],
+    pub material: Option<Box<dyn Material>>,
+}
+
+impl Triangle {
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, material: impl Material + 'static) -> Self {
+        Triangle {
+            vertices: [v0, v1, v2],
+            normals: None,
+            uvs: [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+            material: Some(Box::new(material)),
+        }
+    }
+
+    /// Returns the triangle with smooth shading normals at its vertices.
+    pub fn with_normals(self, normals: [Vec3; 3]) -> Self {
+        Triangle {
+            normals: Some(normals),
+            ..self
+        }
+    }
+
+    /// Returns the triangle with surface coordinates at its vertices.
+    pub fn with_uvs(self, uvs: [(f64, f64); 3]) -> Self {
+        Triangle { uvs, ..self }
+    }
+
+    /// Returns the partial derivatives of the surface position with respect to `(u, v)`.
+    fn tangents(&self, geometric_normal: Vec3) -> (Vec3, Vec3) {
+        let [p0, p1, p2] = self.vertices;
+        let [(u0, v0), (u1, v1), (u2, v2)] = self.uvs;
+        let (du02, dv02) = (u0 - u2, v0 - v2);
+        let (du12, dv12) = (u1 - u2, v1 - v2);
+        let (dp02, dp12) = (p0 - p2, p1 - p2);
+
+        let determinant = du02 * dv12 - dv02 * du12;
+        if determinant.abs() < 1.0e-12 {
+            // Degenerate surface coordinates; pick any tangent frame.
+            let frame = crate::onb::Onb::from_w(geometric_normal);
+            return (frame.u, frame.v);
+        }
+
+        let inverse = 1.0 / determinant;
+        let dpdu = (dv12 * dp02 - dv02 * dp12) * inverse;
+        let dpdv = (du02 * dp12 - du12 * dp02) * inverse;
+        (dpdu, dpdv)
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // Möller-Trumbore intersection.
+        let [p0, p1, p2] = self.vertices;
+        let edge1 = p1 - p0;
+        let edge2 = p2 - p0;
+
+        let pvec = ray.direction.cross(edge2);
+        let determinant = edge1.dot(pvec);
+        if determinant.abs() < 1.0e-12 {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+
+        let tvec = ray.origin - p0;
+        let b1 = tvec.dot(pvec) * inverse;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let b2 = ray.direction.dot(qvec) * inverse;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inverse;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let geometric_normal = edge1.cross(edge2).normal();
+
+        let mut record = HitRecord::new(ray.at(t), t, self.material.as_ref().map(Box::as_ref));
+        record.set_face_normal(ray, geometric_normal);
+
+        if let Some([n0, n1, n2]) = self.normals {
+            let mut shading_normal = (b0 * n0 + b1 * n1 + b2 * n2).normal();
+            if shading_normal.dot(geometric_normal) < 0.0 {
+                shading_normal = -shading_normal;
+            }
+            record.normal = if record.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+
+        let [(u0, v0), (u1, v1), (u2, v2)] = self.uvs;
+        record.u = b0 * u0 + b1 * u1 + b2 * u2;
+        record.v = b0 * v0 + b1 * v1 + b2 * v2;
+        let (dpdu, dpdv) = self.tangents(geometric_normal);
+        record.dpdu = dpdu;
+        record.dpdv = dpdv;
+
+        if record.is_cut_out() {
+            return None;
+        }
+
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = self.vertices;
+        Some(Aabb::new(p0, p1).expand(p2))
+    }
+
+    fn area(&self) -> f64 {
+        let [p0, p1, p2] = self.vertices;
+        0.5 * (p1 - p0).cross(p2 - p0).len()
+    }
+
+    fn sample_surface(&self, u1: f64, u2: f64) -> Option<HitRecord<'_>> {
+        // Uniform barycentric coordinates by warping the unit square.
+        let su = u1.sqrt();
+        let (b0, b1) = (1.0 - su, u2 * su);
+        let b2 = 1.0 - b0 - b1;
+
+        let [p0, p1, p2] = self.vertices;
+        let normal = (p1 - p0).cross(p2 - p0);
+        if normal.near_zero() {
+            return None;
+        }
+        let normal = normal.normal();
+
+        let p = b0 * p0 + b1 * p1 + b2 * p2;
+        let mut record = HitRecord::new(p, 0.0, self.material.as_ref().map(Box::as_ref));
+        record.normal = normal;
+        record.geometric_normal = record.normal;
+        record.front_face = true;
+        let [(u0, v0), (u1, v1), (u2, v2)] = self.uvs;
+        record.u = b0 * u0 + b1 * u1 + b2 * u2;
+        record.v = b0 * v0 + b1 * v1 + b2 * v2;
+        let (dpdu, dpdv) = self.tangents(normal);
+        record.dpdu = dpdu;
+        record.dpdv = dpdv;
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Masked};
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Lambertian::new(Rgb::new(0.5, 0.5, 0.5)),
+        )
+    }
+
+    /// A ray from `z = 1` straight down onto `(x, y, 0)`, or up from `z = -1`.
+    fn ray(x: f64, y: f64, from_above: bool) -> Ray {
+        let z = if from_above { 1.0 } else { -1.0 };
+        Ray::new(Point3::new(x, y, z), Vec3::new(0.0, 0.0, -z))
+    }
+
+    #[test]
+    fn rays_hit_inside_the_edges() {
+        let triangle = triangle();
+        let rec = triangle.hit(&ray(0.25, 0.5, true), 0.0, INIFINTY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1.0e-12);
+        assert!((rec.p - Point3::new(0.25, 0.5, 0.0)).len() < 1.0e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, 1.0)).len() < 1.0e-12);
+
+        let rec = triangle.hit(&ray(0.25, 0.5, false), 0.0, INIFINTY).unwrap();
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3::new(0.0, 0.0, -1.0)).len() < 1.0e-12);
+
+        assert!(triangle.hit(&ray(0.6, 0.6, true), 0.0, INIFINTY).is_none());
+        assert!(triangle.hit(&ray(-0.1, 0.5, true), 0.0, INIFINTY).is_none());
+        assert!(triangle
+            .hit(&ray(0.25, -0.1, true), 0.0, INIFINTY)
+            .is_none());
+        assert!(triangle.hit(&ray(0.25, 0.5, true), 0.0, 0.5).is_none());
+        assert!(triangle.hit(&ray(0.25, 0.5, true), 1.5, INIFINTY).is_none());
+
+        // Rays parallel to the plane miss.
+        let grazing = Ray::new(Point3::new(-1.0, 0.25, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(triangle.hit(&grazing, 0.0, INIFINTY).is_none());
+    }
+
+    #[test]
+    fn surface_coordinates_are_interpolated_barycentrically() {
+        let triangle = triangle().with_uvs([(0.2, 0.1), (0.6, 0.1), (0.2, 0.9)]);
+        let rec = triangle.hit(&ray(0.25, 0.5, true), 0.0, INIFINTY).unwrap();
+        // The barycentric coordinates of (0.25, 0.5) are (0.25, 0.25, 0.5).
+        assert!((rec.u - 0.3).abs() < 1.0e-12, "{}", rec.u);
+        assert!((rec.v - 0.5).abs() < 1.0e-12, "{}", rec.v);
+
+        let triangle = self::triangle();
+        let rec = triangle.hit(&ray(0.25, 0.5, true), 0.0, INIFINTY).unwrap();
+        assert!((rec.u - 0.75).abs() < 1.0e-12, "{}", rec.u);
+        assert!((rec.v - 0.5).abs() < 1.0e-12, "{}", rec.v);
+    }
+
+    #[test]
+    fn shading_normals_are_interpolated_and_face_the_ray() {
+        let normals = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0).normal(),
+            Vec3::new(0.0, 1.0, 1.0).normal(),
+        ];
+        let triangle = triangle().with_normals(normals);
+        let expected = (0.25 * normals[0] + 0.25 * normals[1] + 0.5 * normals[2]).normal();
+
+        let rec = triangle.hit(&ray(0.25, 0.5, true), 0.0, INIFINTY).unwrap();
+        assert!((rec.normal - expected).len() < 1.0e-12, "{:?}", rec.normal);
+        let rec = triangle.hit(&ray(0.25, 0.5, false), 0.0, INIFINTY).unwrap();
+        assert!((rec.normal + expected).len() < 1.0e-12, "{:?}", rec.normal);
+
+        // At a vertex the normal is the one of the vertex.
+        let rec = triangle.hit(&ray(1.0, 0.0, true), 0.0, INIFINTY).unwrap();
+        assert!((rec.normal - normals[1]).len() < 1.0e-9, "{:?}", rec.normal);
+    }
+
+    #[test]
+    fn tangents_follow_the_surface_coordinates() {
+        let triangle = Triangle::new(
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(3.0, 1.0, 0.0),
+            Point3::new(1.0, 4.0, 0.0),
+            Lambertian::new(Rgb::new(0.5, 0.5, 0.5)),
+        )
+        .with_uvs([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        let rec = triangle.hit(&ray(1.5, 2.0, true), 0.0, INIFINTY).unwrap();
+        assert!(
+            (rec.dpdu - Vec3::new(2.0, 0.0, 0.0)).len() < 1.0e-12,
+            "{:?}",
+            rec.dpdu
+        );
+        assert!(
+            (rec.dpdv - Vec3::new(0.0, 3.0, 0.0)).len() < 1.0e-12,
+            "{:?}",
+            rec.dpdv
+        );
+
+        // Moving along the tangents moves the surface coordinates by one.
+        let moved = triangle
+            .hit(&ray(1.5 + 0.1 * 2.0, 2.0, true), 0.0, INIFINTY)
+            .unwrap();
+        assert!((moved.u - rec.u - 0.1).abs() < 1.0e-12);
+
+        let sample = triangle.sample_surface(0.3, 0.7).unwrap();
+        assert_eq!(sample.dpdu, rec.dpdu);
+        assert_eq!(sample.dpdv, rec.dpdv);
+        assert!((sample.normal - Vec3::new(0.0, 0.0, 1.0)).len() < 1.0e-12);
+
+        // Degenerate surface coordinates still give a frame around the normal.
+        let triangle = triangle.with_uvs([(0.5, 0.5); 3]);
+        let rec = triangle.hit(&ray(1.5, 2.0, true), 0.0, INIFINTY).unwrap();
+        assert!(rec.dpdu.dot(rec.normal).abs() < 1.0e-12);
+        assert!(rec.dpdv.dot(rec.normal).abs() < 1.0e-12);
+        assert!(!rec.dpdu.cross(rec.dpdv).near_zero());
+    }
+
+    #[test]
+    fn partial_opacity_keeps_a_fraction_of_the_hits() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Masked::new(Lambertian::new(Rgb::new(0.5, 0.5, 0.5)), 0.25),
+        );
+        let n = 100;
+        let mut hits = 0;
+        for i in 0..n {
+            for j in 0..n - i {
+                let (x, y) = ((i as f64 + 0.25) / n as f64, (j as f64 + 0.25) / n as f64);
+                let hit = triangle.hit(&ray(x, y, true), 0.0, INIFINTY).is_some();
+                assert_eq!(triangle.hit(&ray(x, y, true), 0.0, INIFINTY).is_some(), hit);
+                hits += hit as usize;
+            }
+        }
+        let fraction = hits as f64 / (n * (n + 1) / 2) as f64;
+        assert!((fraction - 0.25).abs() < 0.02, "{}", fraction);
+
+        let opaque = self::triangle();
+        assert!(opaque.hit(&ray(0.25, 0.5, true), 0.0, INIFINTY).is_some());
+    }
+}
@@ -1,14 +1,14 @@
 //! Utility functions.
-use rand::prelude::*;
+use crate::sampler;
 
-/// Generates a random number within range [0, 1).
+/// Generates a random number within range [0, 1), from the sampler of the thread if any.
 pub fn random_f64() -> f64 {
-    rand::thread_rng().gen()
+    sampler::next_1d()
 }
 
 /// Generates a random number within range [min, max).
 pub fn random_f64_within(min: f64, max: f64) -> f64 {
-    rand::thread_rng().gen_range(min..max)
+    min + (max - min) * random_f64()
 }
 
 /// Converts degrees to radians.
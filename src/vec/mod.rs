@@ -3,6 +3,7 @@
 pub mod raw;
 
 use self::raw::*;
+use crate::sampler::SamplerRng;
 use std::fmt;
 use std::ops::*;
 
@@ -68,12 +69,12 @@ impl Vec3 {
     }
 
     pub fn random_in_unit_sphere(rng: &mut impl rand::Rng) -> Self {
-        // Reject all points that are outside the unit sphere until catch a point inside the unit 
+        // Reject all points that are outside the unit sphere until catch a point inside the unit
         // sphere.
         loop {
             let p = Self::random_within(rng, -1.0..1.0);
             if p.len_squared() < 1.0 {
-                return p
+                return p;
             }
         }
     }
@@ -87,10 +88,17 @@ impl Vec3 {
         }
     }
 
+    /// Generates a direction around `+z` with a density proportional to its cosine with `+z`.
+    pub fn random_cosine_direction(rng: &mut impl rand::Rng) -> Self {
+        let r1: f64 = rng.gen();
+        let r2: f64 = rng.gen();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let r = r2.sqrt();
+        Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - r2).sqrt())
+    }
+
     pub fn random_unit_vector() -> Self {
-        // TODO: Cache thread rng intead of calling this every time.
-        let mut rng = rand::thread_rng();
-        Self::random_in_unit_sphere(&mut rng).normal()
+        Self::random_in_unit_sphere(&mut SamplerRng).normal()
     }
 
     /// Checks if the vector is close to zero in all dimensions.
@@ -102,4 +110,14 @@ impl Vec3 {
     pub fn reflect(self, normal: Vec3) -> Vec3 {
         self - 2.0 * self.dot(normal) * normal
     }
+
+    /// Refracts a unit vector through a surface with unit `normal` by Snell's law, where
+    /// `etai_over_etat` is the ratio of the refractive indices on the incident and transmitted
+    /// sides.
+    pub fn refract(self, normal: Vec3, etai_over_etat: f64) -> Vec3 {
+        let cos_theta = (-self).dot(normal).min(1.0);
+        let r_out_perp = etai_over_etat * (self + cos_theta * normal);
+        let r_out_parallel = -(1.0 - r_out_perp.len_squared()).abs().sqrt() * normal;
+        r_out_perp + r_out_parallel
+    }
 }
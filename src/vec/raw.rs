@@ -105,11 +105,11 @@ macro_rules! impl_binop {
 /// Implements common traits and methods for a given vector.
 ///
 /// - 1st arg is the representation (`named struct` or `tuple struct`) and the dimension of the
-/// given vector.
+///   given vector.
 /// - 2nd arg is the identifier and parameters used to generate a `new` method; each parameter can
-/// be a single alphabet letter.
+///   be a single alphabet letter.
 /// - 3rd arg is either the fields or the the indexes of the given vector, depending on its
-/// internal representation.
+///   internal representation.
 ///
 /// In order to implement dimension-specifc methods for your vector, see [`impl_vec_specific`].
 ///
@@ -182,6 +182,7 @@ macro_rules! impl_vec_common {
             }
         }
 
+        #[allow(clippy::from_over_into)]
         impl<T> Into<($(replace_tt!($field T)),+)> for $Vec<T> {
             #[inline]
             fn into(self) -> ($(replace_tt!($field T)),+) {
@@ -197,6 +198,7 @@ macro_rules! impl_vec_common {
             }
         }
 
+        #[allow(clippy::from_over_into)]
         impl<T> Into<[T; $fieldcount]> for $Vec<T> {
             #[inline]
             fn into(self) -> [T; $fieldcount] {
@@ -234,7 +236,7 @@ macro_rules! impl_vec_common {
 
             #[inline]
             pub fn dot(self, rhs: Self) -> T {
-                return (self * rhs).sum();
+                (self * rhs).sum()
             }
 
             #[inline]